@@ -1,3 +1,59 @@
-fn main() {
-    println!("Hello world!");
-}
\ No newline at end of file
+use rens::nes::Nes;
+use rens::rom::NesLoader;
+use rens::wav::export_wav;
+use std::path::PathBuf;
+use std::process::exit;
+
+const USAGE: &str =
+    "用法: rens-play <ROM> --wav <输出文件> [--frames <帧数>] [--rate <采样率>] [--stems]";
+
+/// 无界面运行 ROM 并导出音频
+struct Options {
+    rom: PathBuf,
+    wav: PathBuf,
+    frames: u32,
+    sample_rate: u32,
+    stems: bool,
+}
+
+fn parse_options() -> Option<Options> {
+    let mut args = std::env::args().skip(1);
+    let (mut rom, mut wav) = (None, None);
+    let (mut frames, mut sample_rate, mut stems) = (600, 44100, false);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--wav" => wav = Some(args.next()?.into()),
+            "--frames" => frames = args.next()?.parse().ok()?,
+            "--rate" => sample_rate = args.next()?.parse().ok()?,
+            "--stems" => stems = true,
+            _ if rom.is_none() => rom = Some(arg.into()),
+            _ => return None,
+        }
+    }
+    Some(Options {
+        rom: rom?,
+        wav: wav?,
+        frames,
+        sample_rate,
+        stems,
+    })
+}
+
+fn main() -> anyhow::Result<()> {
+    let Some(options) = parse_options() else {
+        eprintln!("{}", USAGE);
+        exit(2);
+    };
+    let rom = std::fs::read(&options.rom)?;
+    let loader = NesLoader::from_slice(&rom)?;
+    let mut nes = Nes::from_loader(&loader)?;
+    nes.reset()?;
+    export_wav(
+        &mut nes,
+        options.frames,
+        options.sample_rate,
+        &options.wav,
+        options.stems,
+    )?;
+    Ok(())
+}
@@ -0,0 +1,148 @@
+/// 以 CPU 周期为单位的输出周期
+const RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+/// 增量调制通道，$4010-$4013，样本通过总线上的 DMA 读取
+#[derive(Debug, Clone)]
+pub(super) struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    rate: u16,
+    timer: u16,
+    /// 7 位输出电平
+    level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    /// 已读入、等待移入移位寄存器的样本
+    buffer: Option<u8>,
+    /// 已向总线请求样本，尚未送达
+    requested: bool,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    pub(super) irq: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            irq_enabled: false,
+            looping: false,
+            rate: RATE_TABLE[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            requested: false,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            irq: false,
+        }
+    }
+}
+
+impl Dmc {
+    pub fn write(&mut self, index: u16, data: u8) {
+        match index {
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.looping = data & 0x40 != 0;
+                self.rate = RATE_TABLE[(data & 0x0F) as usize];
+            }
+            1 => self.level = data & 0x7F,
+            2 => self.sample_address = 0xC000 | (data as u16) << 6,
+            _ => self.sample_length = (data as u16) << 4 | 1,
+        }
+    }
+
+    /// 通过 $4015 启用或禁用，启用时如果样本已经播放完毕则重新开始
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// 样本缓冲区为空时需要读取的地址，每个样本只请求一次
+    pub fn request(&mut self) -> Option<u16> {
+        if self.buffer.is_some() || self.bytes_remaining == 0 || self.requested {
+            return None;
+        }
+        self.requested = true;
+        Some(self.current_address)
+    }
+
+    /// DMA 读到的样本
+    pub fn load(&mut self, sample: u8) {
+        self.requested = false;
+        if self.bytes_remaining == 0 {
+            return;
+        }
+        self.buffer = Some(sample);
+        // 越过 $FFFF 后回到 $8000
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// 每个 CPU 周期执行一次
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.rate - 1;
+        if !self.silence {
+            if self.shift & 0x01 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+}
@@ -0,0 +1,82 @@
+/// 4 步模式中各步所在的 CPU 周期，最后三个周期都会设置帧中断
+const FOUR_STEP_CYCLES: [u32; 6] = [7457, 14913, 22371, 29828, 29829, 29830];
+/// 5 步模式中各步所在的 CPU 周期，不产生中断
+const FIVE_STEP_CYCLES: [u32; 6] = [7457, 14913, 22371, 29829, 37281, 37282];
+
+/// 帧计数器在一个周期内产生的时钟
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub(super) struct FrameClock {
+    /// 包络和三角波的线性计数器
+    pub quarter: bool,
+    /// 长度计数器和扫频
+    pub half: bool,
+}
+
+/// 帧计数器，$4017
+#[derive(Debug, Default, Clone)]
+pub(super) struct FrameCounter {
+    five_step: bool,
+    irq_inhibit: bool,
+    pub(super) irq: bool,
+    cycle: u32,
+    step: usize,
+    /// 写入 $4017 后等待生效的周期数和写入的值
+    pending: Option<(u8, u8)>,
+}
+
+impl FrameCounter {
+    /// `odd_cycle` 表示写入发生在奇数 CPU 周期，此时要多等一个周期
+    pub fn write(&mut self, data: u8, odd_cycle: bool) {
+        self.irq_inhibit = data & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+        self.pending = Some((if odd_cycle { 4 } else { 3 }, data));
+    }
+
+    /// 每个 CPU 周期执行一次
+    pub fn clock(&mut self) -> FrameClock {
+        let mut clock = FrameClock::default();
+        if let Some((delay, data)) = self.pending {
+            if delay > 1 {
+                self.pending = Some((delay - 1, data));
+            } else {
+                self.pending = None;
+                self.five_step = data & 0x80 != 0;
+                self.cycle = 0;
+                self.step = 0;
+                // 切换到 5 步模式时立即产生一次半帧时钟
+                if self.five_step {
+                    clock.quarter = true;
+                    clock.half = true;
+                }
+                return clock;
+            }
+        }
+        self.cycle += 1;
+        let cycles = match self.five_step {
+            true => &FIVE_STEP_CYCLES,
+            false => &FOUR_STEP_CYCLES,
+        };
+        if self.cycle != cycles[self.step] {
+            return clock;
+        }
+        match self.step {
+            0 | 2 => clock.quarter = true,
+            1 | 4 => {
+                clock.quarter = true;
+                clock.half = true;
+            }
+            _ => {}
+        }
+        if !self.five_step && self.step >= 3 && !self.irq_inhibit {
+            self.irq = true;
+        }
+        self.step += 1;
+        if self.step == cycles.len() {
+            self.step = 0;
+            self.cycle = 0;
+        }
+        clock
+    }
+}
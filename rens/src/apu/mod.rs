@@ -0,0 +1,354 @@
+mod dmc;
+mod frame_counter;
+mod noise;
+mod pulse;
+mod resampler;
+mod triangle;
+mod unit;
+
+use dmc::Dmc;
+use frame_counter::FrameCounter;
+use noise::Noise;
+use pulse::Pulse;
+pub use resampler::Resampler;
+use triangle::Triangle;
+
+/// 各通道当前的输出电平，方波、三角波、噪声为 0-15，DMC 为 0-127
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ChannelOutput {
+    pub pulse1: u8,
+    pub pulse2: u8,
+    pub triangle: u8,
+    pub noise: u8,
+    pub dmc: u8,
+}
+
+impl ChannelOutput {
+    /// 按硬件的非线性混音得到 0.0-1.0 之间的采样
+    pub fn mix(&self) -> f32 {
+        let pulse = self.pulse1 + self.pulse2;
+        let pulse_out = match pulse {
+            0 => 0.0,
+            _ => 95.88 / (8128.0 / pulse as f32 + 100.0),
+        };
+        let tnd_out = match (self.triangle, self.noise, self.dmc) {
+            (0, 0, 0) => 0.0,
+            (triangle, noise, dmc) => {
+                let tnd = triangle as f32 / 8227.0 + noise as f32 / 12241.0 + dmc as f32 / 22638.0;
+                159.79 / (1.0 / tnd + 100.0)
+            }
+        };
+        pulse_out + tnd_out
+    }
+}
+
+/// 音频处理器，寄存器位于 CPU 总线的 $4000-$4013、$4015 和 $4017。
+///
+/// 每个 CPU 周期执行一次，产生一个采样
+#[derive(Debug, Clone)]
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    /// 上电以来的 CPU 周期数
+    cycles: u64,
+}
+
+impl Apu {
+    const ADDRESS_STATUS: u16 = 0x4015;
+    const ADDRESS_FRAME_COUNTER: u16 = 0x4017;
+
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame_counter: FrameCounter::default(),
+            cycles: 0,
+        }
+    }
+
+    /// 复位时所有通道静音，相当于写入 $4015 = 0
+    pub fn reset(&mut self) {
+        self.write_register(Self::ADDRESS_STATUS, 0);
+        self.frame_counter.irq = false;
+    }
+
+    /// 执行一个 CPU 周期
+    pub fn clock(&mut self) {
+        let frame = self.frame_counter.clock();
+        if frame.quarter {
+            self.pulse1.envelope.clock();
+            self.pulse2.envelope.clock();
+            self.noise.envelope.clock();
+            self.triangle.clock_linear();
+        }
+        if frame.half {
+            for length in [
+                &mut self.pulse1.length,
+                &mut self.pulse2.length,
+                &mut self.triangle.length,
+                &mut self.noise.length,
+            ] {
+                length.clock();
+            }
+            self.pulse1.clock_sweep();
+            self.pulse2.clock_sweep();
+        }
+        self.triangle.clock_timer();
+        self.dmc.clock_timer();
+        // 方波和噪声的计时器以 APU 周期为单位
+        if !self.cycles.is_multiple_of(2) {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+            self.noise.clock_timer();
+        }
+        self.cycles += 1;
+    }
+
+    pub fn write_register(&mut self, address: u16, data: u8) {
+        let index = address & 0x03;
+        match address {
+            0x4000..=0x4003 => self.pulse1.write(index, data),
+            0x4004..=0x4007 => self.pulse2.write(index, data),
+            0x4008..=0x400B => self.triangle.write(index, data),
+            0x400C..=0x400F => self.noise.write(index, data),
+            0x4010..=0x4013 => self.dmc.write(index, data),
+            Self::ADDRESS_STATUS => {
+                self.pulse1.length.set_enabled(data & 0x01 != 0);
+                self.pulse2.length.set_enabled(data & 0x02 != 0);
+                self.triangle.length.set_enabled(data & 0x04 != 0);
+                self.noise.length.set_enabled(data & 0x08 != 0);
+                self.dmc.set_enabled(data & 0x10 != 0);
+            }
+            Self::ADDRESS_FRAME_COUNTER => self
+                .frame_counter
+                .write(data, !self.cycles.is_multiple_of(2)),
+            _ => {}
+        }
+    }
+
+    /// 读取 $4015，同时清除帧中断标志
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_counter.irq = false;
+        status
+    }
+
+    /// 读取 $4015 但不产生副作用，供调试工具使用
+    pub fn peek_status(&self) -> u8 {
+        [
+            self.pulse1.length.active(),
+            self.pulse2.length.active(),
+            self.triangle.length.active(),
+            self.noise.length.active(),
+            self.dmc.active(),
+            false,
+            self.frame_counter.irq,
+            self.dmc.irq,
+        ]
+        .iter()
+        .rev()
+        .fold(0, |status, bit| status << 1 | *bit as u8)
+    }
+
+    /// 帧计数器的 IRQ 输出
+    pub fn frame_irq(&self) -> bool {
+        self.frame_counter.irq
+    }
+    /// DMC 的 IRQ 输出
+    pub fn dmc_irq(&self) -> bool {
+        self.dmc.irq
+    }
+
+    /// DMC 需要通过 DMA 读取的样本地址
+    pub fn dmc_request(&mut self) -> Option<u16> {
+        self.dmc.request()
+    }
+    /// 把 DMA 读到的样本交给 DMC
+    pub fn load_dmc_sample(&mut self, sample: u8) {
+        self.dmc.load(sample);
+    }
+
+    pub fn channels(&self) -> ChannelOutput {
+        ChannelOutput {
+            pulse1: self.pulse1.output(),
+            pulse2: self.pulse2.output(),
+            triangle: self.triangle.output(),
+            noise: self.noise.output(),
+            dmc: self.dmc.output(),
+        }
+    }
+
+    /// 当前周期的混音输出
+    pub fn output(&self) -> f32 {
+        self.channels().mix()
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Apu, ChannelOutput};
+    use crate::nes::run_blargg;
+
+    fn run(apu: &mut Apu, cycles: u32) {
+        for _ in 0..cycles {
+            apu.clock();
+        }
+    }
+
+    #[test]
+    fn length_counter_test() {
+        let mut apu = Apu::new();
+        // 禁用时忽略装载
+        apu.write_register(0x4003, 0x18);
+        assert_eq!(apu.peek_status(), 0x00);
+        apu.write_register(0x4015, 0x0F);
+        for address in [0x4003, 0x4007, 0x400B, 0x400F] {
+            apu.write_register(address, 0x18);
+        }
+        assert_eq!(apu.peek_status(), 0x0F);
+        apu.write_register(0x4015, 0x0E);
+        assert_eq!(apu.peek_status(), 0x0E);
+
+        // 长度为 2，切换到 5 步模式时立即产生一次半帧时钟
+        apu.write_register(0x4008, 0x00);
+        apu.write_register(0x4017, 0x80);
+        run(&mut apu, 4);
+        assert_eq!(apu.peek_status(), 0x0E);
+        apu.write_register(0x4017, 0x80);
+        run(&mut apu, 4);
+        assert_eq!(apu.peek_status(), 0x00);
+    }
+
+    #[test]
+    fn frame_irq_test() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4017, 0x00);
+        // 在偶数周期写入，3 个周期后计数器清零
+        run(&mut apu, 3 + 29827);
+        assert!(!apu.frame_irq());
+        run(&mut apu, 1);
+        assert!(apu.frame_irq());
+        assert_eq!(apu.read_status(), 0x40);
+        // 之后两个周期再次设置
+        run(&mut apu, 1);
+        assert!(apu.frame_irq());
+        assert_eq!(apu.read_status(), 0x40);
+        run(&mut apu, 1);
+        assert_eq!(apu.read_status(), 0x40);
+        assert!(!apu.frame_irq());
+        run(&mut apu, 29829);
+        assert!(apu.frame_irq());
+        // 禁止中断时立即清除
+        apu.write_register(0x4017, 0x40);
+        assert!(!apu.frame_irq());
+        run(&mut apu, 2 * 29830 + 100);
+        assert!(!apu.frame_irq());
+        // 5 步模式不产生中断
+        apu.write_register(0x4017, 0x80);
+        run(&mut apu, 2 * 37282);
+        assert!(!apu.frame_irq());
+    }
+
+    #[test]
+    fn channel_test() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4015, 0x0F);
+        // 方波：50% 占空比，固定音量 9，周期 $100
+        apu.write_register(0x4000, 0xB9);
+        apu.write_register(0x4002, 0x00);
+        apu.write_register(0x4003, 0x09);
+        let levels = (0..16)
+            .map(|_| {
+                run(&mut apu, 2 * 0x101);
+                apu.channels().pulse1
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(levels, [9, 9, 9, 9, 0, 0, 0, 0, 9, 9, 9, 9, 0, 0, 0, 0]);
+        // 周期小于 8 时静音
+        apu.write_register(0x4002, 0x07);
+        apu.write_register(0x4003, 0x08);
+        assert!((0..64).all(|_| {
+            apu.clock();
+            apu.channels().pulse1 == 0
+        }));
+
+        // 三角波需要线性计数器不为 0 才前进
+        apu.write_register(0x400A, 0x10);
+        apu.write_register(0x400B, 0x08);
+        run(&mut apu, 0x11 * 4);
+        assert_eq!(apu.channels().triangle, 15);
+        apu.write_register(0x4008, 0x7F);
+        apu.write_register(0x400B, 0x08);
+        apu.write_register(0x4017, 0x00);
+        run(&mut apu, 7460);
+        run(&mut apu, 0x11 * 4);
+        assert_eq!(apu.channels().triangle, 11);
+
+        // 噪声：移位寄存器第 0 位为 0 时输出包络
+        apu.write_register(0x400C, 0x1F);
+        apu.write_register(0x400E, 0x00);
+        apu.write_register(0x400F, 0x08);
+        let noise = (0..32)
+            .map(|_| {
+                run(&mut apu, 4);
+                apu.channels().noise
+            })
+            .collect::<Vec<_>>();
+        assert!(noise.contains(&15) && noise.contains(&0));
+    }
+
+    #[test]
+    fn mix_test() {
+        assert_eq!(ChannelOutput::default().mix(), 0.0);
+        let pulse = ChannelOutput {
+            pulse1: 15,
+            pulse2: 15,
+            ..Default::default()
+        };
+        assert!((pulse.mix() - 0.2585).abs() < 0.0001);
+        let tnd = ChannelOutput {
+            triangle: 15,
+            noise: 15,
+            dmc: 127,
+            ..Default::default()
+        };
+        assert!((tnd.mix() - 0.7415).abs() < 0.0001);
+        // 非线性：两个方波叠加小于各自之和
+        let single = ChannelOutput {
+            pulse1: 15,
+            ..Default::default()
+        };
+        assert!(pulse.mix() < single.mix() * 2.0);
+    }
+
+    #[test]
+    #[ignore = "需要把 blargg 的 apu_test ROM 放到 test_data/apu_test"]
+    fn blargg_apu_test() {
+        for name in [
+            "1-len_ctr",
+            "2-len_table",
+            "3-irq_flag",
+            "4-jitter",
+            "5-len_timing",
+            "6-irq_flag_timing",
+            "7-dmc_basics",
+            "8-dmc_rates",
+        ] {
+            let (result, text) = run_blargg(&format!("test_data/apu_test/{name}.nes"));
+            assert_eq!(result, 0, "{name}: {text}");
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use super::unit::{Envelope, LengthCounter};
+
+/// 以 APU 周期为单位的计时器周期
+const PERIOD_TABLE: [u16; 16] = [
+    2, 4, 8, 16, 32, 48, 64, 80, 101, 127, 190, 254, 381, 508, 1017, 2034,
+];
+
+/// 噪声通道，$400C-$400F
+#[derive(Debug, Clone)]
+pub(super) struct Noise {
+    /// 短周期模式用第 6 位而不是第 1 位做反馈
+    short_mode: bool,
+    period: u16,
+    timer: u16,
+    /// 15 位线性反馈移位寄存器
+    shift: u16,
+    pub(super) envelope: Envelope,
+    pub(super) length: LengthCounter,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            short_mode: false,
+            period: PERIOD_TABLE[0],
+            timer: 0,
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+}
+
+impl Noise {
+    pub fn write(&mut self, index: u16, data: u8) {
+        match index {
+            0 => {
+                self.length.set_halt(data & 0x20 != 0);
+                self.envelope.write(data);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                self.period = PERIOD_TABLE[(data & 0x0F) as usize];
+            }
+            _ => {
+                self.length.load(data);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// 每个 APU 周期执行一次
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 0x01;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.shift & 0x01 != 0 || !self.length.active() {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+/// 每个输出采样间隔内的相位数
+const PHASES: usize = 32;
+/// 每个阶跃影响的输出采样数
+const WIDTH: usize = 16;
+/// 截止频率，相对于输出采样率的奈奎斯特频率
+const CUTOFF: f64 = 0.9;
+/// 模拟主机输出端的一阶高通滤波器
+const HIGH_PASS_FREQUENCY: f64 = 90.0;
+
+/// 各相位的带限冲激，每个相位的系数之和为 1
+fn kernel() -> &'static [[f32; WIDTH]; PHASES] {
+    static KERNEL: OnceLock<[[f32; WIDTH]; PHASES]> = OnceLock::new();
+    KERNEL.get_or_init(|| {
+        let half = (WIDTH / 2) as f64;
+        let mut kernel = [[0.0; WIDTH]; PHASES];
+        for (phase, taps) in kernel.iter_mut().enumerate() {
+            let offset = phase as f64 / PHASES as f64;
+            let values = (0..WIDTH).map(|k| {
+                let x = k as f64 - half - offset + 1.0;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * CUTOFF * x).sin() / (PI * CUTOFF * x)
+                };
+                // Blackman 窗
+                let window =
+                    0.42 + 0.5 * (PI * x / half).cos() + 0.08 * (2.0 * PI * x / half).cos();
+                sinc * window
+            });
+            let values = values.collect::<Vec<_>>();
+            let sum = values.iter().sum::<f64>();
+            for (tap, value) in taps.iter_mut().zip(values) {
+                *tap = (value / sum) as f32;
+            }
+        }
+        kernel
+    })
+}
+
+/// 带限重采样器，把每个 CPU 周期的 APU 输出转换成指定采样率的音频。
+///
+/// 输入电平每次变化时，在缓冲区中叠加一个带限阶跃，读取时再积分，
+/// 与 blip_buf 的做法相同
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    clock_rate: f64,
+    sample_rate: u32,
+    stereo: bool,
+    /// 动态速率调整，大于 1 时产生更多采样
+    rate_adjust: f64,
+    /// 每个输入周期前进的输出采样数
+    step: f64,
+    /// 当前时间，以输出采样为单位，相对于缓冲区开头
+    position: f64,
+    /// 尚未读取的采样中叠加的电平变化，长度至少比可读取的采样多 `WIDTH`
+    deltas: Vec<f32>,
+    /// 上一个输入周期的电平
+    level: f32,
+    /// 积分器
+    sum: f32,
+    high_pass: f32,
+    previous: f32,
+    output: f32,
+}
+
+impl Resampler {
+    /// 常用的输出采样率
+    pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+    /// 未读取的采样最多保留半秒，超出时丢弃最早的
+    const MAX_BUFFERED_SECONDS: f64 = 0.5;
+
+    /// `clock_rate` 是每秒调用 `clock` 的次数
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        let mut resampler = Self {
+            clock_rate,
+            sample_rate,
+            stereo: false,
+            rate_adjust: 1.0,
+            step: 0.0,
+            position: 0.0,
+            deltas: vec![0.0; WIDTH],
+            level: 0.0,
+            sum: 0.0,
+            high_pass: 0.0,
+            previous: 0.0,
+            output: 0.0,
+        };
+        resampler.update_step();
+        resampler
+    }
+
+    fn update_step(&mut self) {
+        self.step = self.sample_rate as f64 * self.rate_adjust / self.clock_rate;
+        self.high_pass = (-2.0 * PI * HIGH_PASS_FREQUENCY / self.sample_rate as f64).exp() as f32;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    /// 修改输出采样率，已经缓冲的采样不受影响
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.update_step();
+    }
+
+    pub fn stereo(&self) -> bool {
+        self.stereo
+    }
+    /// 立体声时每个采样复制到左右两个声道
+    pub fn set_stereo(&mut self, stereo: bool) {
+        self.stereo = stereo;
+    }
+
+    pub fn rate_adjust(&self) -> f64 {
+        self.rate_adjust
+    }
+    /// 微调实际的输出采样率，用于让音频与画面同步。
+    ///
+    /// 前端可以根据自己缓冲区的填充程度每帧调用，例如在 0.995-1.005 之间变化，
+    /// 缓冲区快空时增大、快满时减小，避免丢帧或者爆音
+    pub fn set_rate_adjust(&mut self, rate_adjust: f64) {
+        self.rate_adjust = rate_adjust.clamp(0.5, 2.0);
+        self.update_step();
+    }
+
+    /// 输入一个周期的电平
+    pub fn clock(&mut self, level: f32) {
+        if level != self.level {
+            self.add_delta(level - self.level);
+            self.level = level;
+        }
+        self.position += self.step;
+        let available = self.position as usize;
+        if self.deltas.len() < available + WIDTH {
+            self.deltas.resize(available + WIDTH, 0.0);
+        }
+        let limit = (self.sample_rate as f64 * Self::MAX_BUFFERED_SECONDS) as usize;
+        if available > limit {
+            self.skip(available - limit);
+        }
+    }
+
+    fn add_delta(&mut self, delta: f32) {
+        let index = self.position as usize;
+        let phase = ((self.position - index as f64) * PHASES as f64) as usize;
+        for (value, tap) in self.deltas[index..].iter_mut().zip(&kernel()[phase]) {
+            *value += delta * tap;
+        }
+    }
+
+    /// 可以读取的采样数，立体声时一个采样包含两个值
+    pub fn available(&self) -> usize {
+        self.position as usize
+    }
+
+    /// 丢弃最早的 `count` 个采样
+    fn skip(&mut self, count: usize) {
+        for delta in &self.deltas[..count] {
+            self.sum += delta;
+        }
+        self.consume(count);
+    }
+
+    fn consume(&mut self, count: usize) {
+        self.deltas.drain(..count);
+        self.position -= count as f64;
+    }
+
+    /// 读取采样到 `buffer`，返回写入的值的个数。
+    ///
+    /// 立体声时左右声道交替排列，只写入完整的采样
+    pub fn take(&mut self, buffer: &mut [f32]) -> usize {
+        let channels = if self.stereo { 2 } else { 1 };
+        let count = self.available().min(buffer.len() / channels);
+        for (frame, delta) in buffer.chunks_exact_mut(channels).zip(&self.deltas[..count]) {
+            self.sum += delta;
+            self.output = self.sum - self.previous + self.high_pass * self.output;
+            self.previous = self.sum;
+            frame.fill(self.output);
+        }
+        self.consume(count);
+        count * channels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resampler;
+
+    const CLOCK_RATE: f64 = 1_789_773.0;
+
+    /// 输入频率为 `frequency` 的方波
+    fn square(resampler: &mut Resampler, frequency: f64, clocks: usize) {
+        let period = CLOCK_RATE / frequency;
+        for clock in 0..clocks {
+            let level = if (clock as f64 % period) < period / 2.0 {
+                0.5
+            } else {
+                0.0
+            };
+            resampler.clock(level);
+        }
+    }
+
+    #[test]
+    fn rate_test() {
+        let mut resampler = Resampler::new(CLOCK_RATE, 48000);
+        square(&mut resampler, 440.0, CLOCK_RATE as usize / 10);
+        // 不足一个采样的部分留到下次
+        assert_eq!(resampler.available(), 4799);
+        let mut buffer = vec![0.0; 10000];
+        assert_eq!(resampler.take(&mut buffer), 4799);
+        assert_eq!(resampler.available(), 0);
+        // 带限后不会有明显的过冲，直流也被滤除
+        let samples = &buffer[2400..4799];
+        assert!(samples.iter().all(|sample| sample.abs() < 0.6));
+        assert!(samples.iter().any(|sample| sample.abs() > 0.2));
+        assert!(samples.iter().sum::<f32>().abs() / 2400.0 < 0.01);
+
+        resampler.set_rate_adjust(1.01);
+        resampler.set_sample_rate(44100);
+        square(&mut resampler, 440.0, CLOCK_RATE as usize / 10);
+        assert_eq!(resampler.available(), 4455);
+    }
+
+    #[test]
+    fn stereo_test() {
+        let mut resampler = Resampler::new(CLOCK_RATE, 44100);
+        resampler.set_stereo(true);
+        square(&mut resampler, 1000.0, 10000);
+        let available = resampler.available();
+        // 缓冲区不足时只写入完整的采样
+        let mut buffer = [0.0; 9];
+        assert_eq!(resampler.take(&mut buffer), 8);
+        assert_eq!(resampler.available(), available - 4);
+        let mut buffer = vec![0.0; 2 * available];
+        assert_eq!(resampler.take(&mut buffer), 2 * (available - 4));
+        assert!(buffer.chunks(2).all(|frame| frame[0] == frame[1]));
+    }
+
+    #[test]
+    fn overflow_test() {
+        let mut resampler = Resampler::new(CLOCK_RATE, 44100);
+        square(&mut resampler, 1000.0, CLOCK_RATE as usize);
+        assert_eq!(resampler.available(), 22050);
+    }
+}
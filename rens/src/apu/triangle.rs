@@ -0,0 +1,68 @@
+use super::unit::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/// 三角波通道，$4008-$400B
+#[derive(Debug, Default, Clone)]
+pub(super) struct Triangle {
+    /// 同时是长度计数器的暂停标志
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    period: u16,
+    timer: u16,
+    step: u8,
+    pub(super) length: LengthCounter,
+}
+
+impl Triangle {
+    pub fn write(&mut self, index: u16, data: u8) {
+        match index {
+            0 => {
+                self.control = data & 0x80 != 0;
+                self.length.set_halt(self.control);
+                self.linear_reload_value = data & 0x7F;
+            }
+            1 => {}
+            2 => self.period = (self.period & 0x0700) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data as u16 & 0x07) << 8);
+                self.length.load(data);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    /// 每个 CPU 周期执行一次，两个计数器都不为 0 时才前进
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.active() && self.linear_counter > 0 {
+                self.step = (self.step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    /// 每四分之一帧执行一次
+    pub fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    /// 静音时停在当前的值上
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.step as usize]
+    }
+}
@@ -0,0 +1,86 @@
+/// 长度计数器的装载值，由写入的高 5 位索引
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// 长度计数器，减到 0 时通道静音，每半帧减一
+#[derive(Debug, Default, Clone)]
+pub(super) struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    /// 通过 $4015 启用或禁用，禁用时立即清零
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+    pub fn set_halt(&mut self, halt: bool) {
+        self.halt = halt;
+    }
+    /// 用写入寄存器的高 5 位装载，禁用时忽略
+    pub fn load(&mut self, data: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(data >> 3) as usize];
+        }
+    }
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+/// 音量包络，每四分之一帧执行一次
+#[derive(Debug, Default, Clone)]
+pub(super) struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    /// 固定音量或者分频器的周期
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// 写入 --LC VVVV
+    pub fn write(&mut self, data: u8) {
+        self.looping = data & 0x20 != 0;
+        self.constant = data & 0x10 != 0;
+        self.volume = data & 0x0F;
+    }
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+    pub fn output(&self) -> u8 {
+        match self.constant {
+            true => self.volume,
+            false => self.decay,
+        }
+    }
+}
@@ -1,15 +1,37 @@
-use crate::{memory::Memory, memory::Result, rom::Mapper};
+use crate::{memory::Memory, memory::MemoryError, memory::Result, rom::Mapper};
 
+use crate::apu::Apu;
 use crate::cpu::{stack, CpuMemory};
-use crate::ppu::PpuMemory;
+use crate::debugger::{Access, WatchHit, Watchpoint};
+use crate::input::{ButtonState, InputDevice, Joypad, Port};
+use crate::ppu::{Ppu, PpuMemory};
 use crate::register::{CpuRegisters, PpuRegister};
+use std::cell::Cell;
 use std::fmt::{Debug, Formatter};
 
 pub struct Bus {
     cpu_memory: CpuMemory,
     ppu_memory: PpuMemory,
+    ppu: Ppu,
+    apu: Apu,
     mapper: Box<dyn Mapper>,
     registers: CpuRegisters,
+    watchpoints: Vec<Watchpoint>,
+    /// 第一次命中的监视点，取出前不再记录
+    watch_hit: Cell<Option<WatchHit>>,
+    /// 写入 $4014 后等待复制到 OAM 的页
+    oam_dma: Option<u8>,
+    /// 正在复制到 OAM 的页
+    oam_transfer: Option<OamTransfer>,
+    /// DMC 等待读取的样本地址，以及读取前还需等待的周期数
+    dmc_dma: Option<(u16, u32)>,
+    /// DMC DMA 读到的样本，下个周期交给 APU
+    dmc_sample: Option<u8>,
+    /// 两个控制器端口上的外设
+    inputs: [Box<dyn InputDevice>; 2],
+    /// 最后一次出现在 CPU 数据总线上的值
+    open_bus: u8,
+    a12: A12Watch,
 }
 
 impl Bus {
@@ -17,62 +39,277 @@ impl Bus {
         Self {
             cpu_memory: CpuMemory::new(),
             ppu_memory: PpuMemory::new(),
+            ppu: Ppu::new(),
+            apu: Apu::new(),
             mapper,
             registers: CpuRegisters::new(),
+            watchpoints: Vec::new(),
+            watch_hit: Cell::new(None),
+            oam_dma: None,
+            oam_transfer: None,
+            dmc_dma: None,
+            dmc_sample: None,
+            inputs: [Box::new(Joypad::new()), Box::new(Joypad::new())],
+            open_bus: 0,
+            a12: A12Watch::default(),
         }
     }
 
-    pub fn cpu_read(&self, address: u16) -> Result<u8> {
-        self.cpu_memory
-            .read(address)
-            .or_else(|_| self.mapper.read(address))
+    const ADDRESS_PPU_REGISTER_START: u16 = 0x2000;
+    const ADDRESS_PPU_REGISTER_END: u16 = 0x3FFF;
+    const ADDRESS_APU_REGISTER_START: u16 = 0x4000;
+    const ADDRESS_APU_REGISTER_END: u16 = 0x4013;
+    const ADDRESS_OAM_DMA: u16 = 0x4014;
+    const ADDRESS_APU_STATUS: u16 = 0x4015;
+    const ADDRESS_APU_FRAME_COUNTER: u16 = 0x4017;
+    const ADDRESS_INPUT_PORT_ONE: u16 = 0x4016;
+    const ADDRESS_INPUT_PORT_TWO: u16 = 0x4017;
+    /// 控制器端口只驱动低 5 位，其余是总线上残留的值
+    const INPUT_PORT_MASK: u8 = 0x1F;
+    const ADDRESS_OAM_DATA: u16 = 0x2004;
+    const ADDRESS_PRG_ROM_START: u16 = 0x8000;
+    /// DMC DMA 读取样本前的挂起周期和虚读周期
+    const DMC_DMA_DELAY: u32 = 2;
+
+    pub fn cpu_read(&mut self, address: u16) -> Result<u8> {
+        let data = match address {
+            Self::ADDRESS_PPU_REGISTER_START..=Self::ADDRESS_PPU_REGISTER_END => {
+                let mut vram = VideoMemory {
+                    memory: &mut self.ppu_memory,
+                    mapper: self.mapper.as_mut(),
+                    a12: &self.a12,
+                };
+                let data = self.ppu.read_register(address, &mut vram)?;
+                self.notify_a12();
+                data
+            }
+            Self::ADDRESS_INPUT_PORT_ONE | Self::ADDRESS_INPUT_PORT_TWO => {
+                let port = Self::input_port(address);
+                let data = self.inputs[port as usize].read() & Self::INPUT_PORT_MASK;
+                data | (self.open_bus & !Self::INPUT_PORT_MASK)
+            }
+            // 第 5 位没有驱动，是总线上残留的值
+            Self::ADDRESS_APU_STATUS => self.apu.read_status() | (self.open_bus & 0x20),
+            _ => self.cpu_peek(address)?,
+        };
+        self.open_bus = data;
+        self.watch(Access::Read, address, data);
+        Ok(data)
+    }
+    pub fn cpu_read_word(&mut self, address: u16) -> Result<u16> {
+        let low = self.cpu_read(address)?;
+        let high = self.cpu_read(address.wrapping_add(1))?;
+        Ok(u16::from_le_bytes([low, high]))
+    }
+    /// 读取内存但不触发监视点，也没有寄存器的读取副作用，供调试工具使用
+    pub fn cpu_peek(&self, address: u16) -> Result<u8> {
+        match address {
+            Self::ADDRESS_PPU_REGISTER_START..=Self::ADDRESS_PPU_REGISTER_END => {
+                self.ppu.peek_register(address, &VideoPeek(self))
+            }
+            Self::ADDRESS_INPUT_PORT_ONE | Self::ADDRESS_INPUT_PORT_TWO => {
+                let port = Self::input_port(address);
+                let data = self.inputs[port as usize].peek() & Self::INPUT_PORT_MASK;
+                Ok(data | (self.open_bus & !Self::INPUT_PORT_MASK))
+            }
+            Self::ADDRESS_APU_STATUS => Ok(self.apu.peek_status() | (self.open_bus & 0x20)),
+            _ => self
+                .cpu_memory
+                .read(address)
+                .or_else(|_| self.cartridge_read(address)),
+        }
+    }
+    /// 卡带没有驱动 $4020-$7FFF 时读到的是总线上残留的值，只驱动部分位时其余位也一样
+    fn cartridge_read(&self, address: u16) -> Result<u8> {
+        match self.mapper.cpu_read(address) {
+            Ok(data) => {
+                let mask = self.mapper.cpu_open_bus_mask(address);
+                Ok((data & !mask) | (self.open_bus & mask))
+            }
+            Err(MemoryError::Unmapped(_)) if address < Self::ADDRESS_PRG_ROM_START => {
+                Ok(self.open_bus)
+            }
+            result => result,
+        }
     }
-    pub fn cpu_read_word(&self, address: u16) -> Result<u16> {
-        self.cpu_memory
-            .read_word(address)
-            .or_else(|_| self.mapper.read_word(address))
+    /// 卡带没有接收 $4020-$7FFF 的写入时，写入什么也不做
+    fn cartridge_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match self.mapper.cpu_write(address, data) {
+            Err(MemoryError::Unmapped(_)) if address < Self::ADDRESS_PRG_ROM_START => Ok(()),
+            result => result,
+        }
     }
     pub fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
-        self.cpu_memory
-            .write(address, data)
-            .or_else(|_| self.mapper.write(address, data))
+        match address {
+            Self::ADDRESS_PPU_REGISTER_START..=Self::ADDRESS_PPU_REGISTER_END => {
+                let mut vram = VideoMemory {
+                    memory: &mut self.ppu_memory,
+                    mapper: self.mapper.as_mut(),
+                    a12: &self.a12,
+                };
+                self.ppu.write_register(address, data, &mut vram)?;
+                self.notify_a12();
+            }
+            Self::ADDRESS_APU_REGISTER_START..=Self::ADDRESS_APU_REGISTER_END
+            | Self::ADDRESS_APU_STATUS
+            | Self::ADDRESS_APU_FRAME_COUNTER => self.apu.write_register(address, data),
+            Self::ADDRESS_OAM_DMA => self.oam_dma = Some(data),
+            // 选通信号同时送到两个端口，$4017 的写入属于 APU
+            Self::ADDRESS_INPUT_PORT_ONE => {
+                for input in self.inputs.iter_mut() {
+                    input.write(data);
+                }
+            }
+            _ => self
+                .cpu_memory
+                .write(address, data)
+                .or_else(|_| self.cartridge_write(address, data))?,
+        }
+        self.open_bus = data;
+        self.watch(Access::Write, address, data);
+        Ok(())
     }
     pub fn cpu_write_word(&mut self, address: u16, data: u16) -> Result<()> {
-        self.cpu_memory
-            .write_word(address, data)
-            .or_else(|_| self.mapper.write_word(address, data))
+        let [low, high] = data.to_le_bytes();
+        self.cpu_write(address, low)?;
+        self.cpu_write(address.wrapping_add(1), high)
     }
     pub fn ppu_read(&self, address: u16) -> Result<u8> {
-        self.ppu_memory
-            .read(address)
-            .or_else(|_| self.mapper.read(address))
+        video_read(&self.ppu_memory, self.mapper.as_ref(), address)
     }
     pub fn ppu_read_word(&self, address: u16) -> Result<u16> {
-        self.ppu_memory
-            .read_word(address)
-            .or_else(|_| self.mapper.read_word(address))
+        let low = self.ppu_read(address)?;
+        let high = self.ppu_read(address.wrapping_add(1))?;
+        Ok(u16::from_le_bytes([low, high]))
     }
     pub fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
-        self.ppu_memory
-            .write(address, data)
-            .or_else(|_| self.mapper.write(address, data))
+        video_write(&mut self.ppu_memory, self.mapper.as_mut(), address, data)
     }
     pub fn ppu_write_word(&mut self, address: u16, data: u16) -> Result<()> {
-        self.ppu_memory
-            .write_word(address, data)
-            .or_else(|_| self.mapper.write_word(address, data))
+        let [low, high] = data.to_le_bytes();
+        self.ppu_write(address, low)?;
+        self.ppu_write(address.wrapping_add(1), high)
     }
     pub fn stack_push(&mut self, data: u8) -> Result<()> {
-        stack::push(&mut self.cpu_memory, &mut self.registers, data)
+        let address = stack::address(self.registers.sp);
+        stack::push(&mut self.cpu_memory, &mut self.registers, data)?;
+        self.watch(Access::Write, address, data);
+        Ok(())
     }
     pub fn stack_push_word(&mut self, data: u16) -> Result<()> {
-        stack::push_word(&mut self.cpu_memory, &mut self.registers, data)
+        self.stack_push((data >> 8) as u8)?;
+        self.stack_push(data as u8)
     }
     pub fn stack_pop(&mut self) -> Result<u8> {
-        stack::pop(&mut self.cpu_memory, &mut self.registers)
+        let data = stack::pop(&mut self.cpu_memory, &mut self.registers)?;
+        self.watch(Access::Read, stack::address(self.registers.sp), data);
+        Ok(data)
     }
     pub fn stack_pop_word(&mut self) -> Result<u16> {
-        stack::pop_word(&mut self.cpu_memory, &mut self.registers)
+        let low = self.stack_pop()?;
+        let high = self.stack_pop()?;
+        Ok(u16::from_le_bytes([low, high]))
+    }
+    fn input_port(address: u16) -> Port {
+        match address {
+            Self::ADDRESS_INPUT_PORT_ONE => Port::One,
+            _ => Port::Two,
+        }
+    }
+    /// 把外设接到控制器端口上，替换原来的外设
+    pub fn set_input_device(&mut self, port: Port, device: Box<dyn InputDevice>) {
+        self.inputs[port as usize] = device;
+    }
+    pub fn input_device(&self, port: Port) -> &dyn InputDevice {
+        self.inputs[port as usize].as_ref()
+    }
+    /// 更新端口上手柄的按键
+    pub fn set_buttons(&mut self, port: Port, buttons: ButtonState) {
+        self.inputs[port as usize].set_buttons(buttons);
+    }
+    /// 请求 DMC 在 CPU 下次可以挂起时读取一个样本
+    pub fn request_dmc_dma(&mut self, address: u16) {
+        self.dmc_dma = Some((address, Self::DMC_DMA_DELAY));
+    }
+    /// 是否有 DMA 等待挂起 CPU
+    pub fn dma_pending(&self) -> bool {
+        self.oam_dma.is_some() || self.oam_transfer.is_some() || self.dmc_dma.is_some()
+    }
+    /// 执行一个 DMA 周期，返回 DMA 是否还要继续挂起 CPU，`cycle` 是当前 CPU 的周期数。
+    ///
+    /// 奇数周期读取，偶数周期写入 $2004，OAM DMA 因此占用 513 或 514 个周期；
+    /// DMC 在挂起和虚读之后占用一个读取周期，OAM DMA 随后要多等一个写入周期
+    pub fn clock_dma(&mut self, cycle: u32) -> Result<bool> {
+        let get = cycle % 2 == 1;
+        // OAM DMA 开始时的挂起周期
+        let halt = match self.oam_dma.take() {
+            Some(page) => {
+                self.oam_transfer = Some(OamTransfer {
+                    page,
+                    offset: 0,
+                    data: None,
+                });
+                true
+            }
+            None => false,
+        };
+        let mut dmc_read = false;
+        if let Some((address, delay)) = self.dmc_dma {
+            if delay == 0 && get && !halt {
+                self.dmc_sample = Some(self.cpu_read(address)?);
+                self.dmc_dma = None;
+                dmc_read = true;
+            } else {
+                self.dmc_dma = Some((address, delay.saturating_sub(1)));
+            }
+        }
+        // 挂起周期和 DMC 占用的周期里 OAM DMA 不动
+        if !halt && !dmc_read {
+            if let Some(mut transfer) = self.oam_transfer.take() {
+                match transfer.data.take() {
+                    // 从当前的 OAMADDR 开始写入
+                    Some(data) if !get => {
+                        self.cpu_write(Self::ADDRESS_OAM_DATA, data)?;
+                        transfer.offset += 1;
+                    }
+                    None if get => {
+                        let address = u16::from_be_bytes([transfer.page, transfer.offset as u8]);
+                        transfer.data = Some(self.cpu_read(address)?);
+                    }
+                    // 等待对齐
+                    data => transfer.data = data,
+                }
+                if transfer.offset <= 0xFF {
+                    self.oam_transfer = Some(transfer);
+                }
+            }
+        }
+        Ok(self.oam_transfer.is_some() || self.dmc_dma.is_some())
+    }
+    /// 替换 CPU 总线上的监视点
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.watchpoints = watchpoints;
+        self.watch_hit.set(None);
+    }
+    /// 取出上次取出后第一次命中的监视点
+    pub fn take_watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+    fn watch(&self, access: Access, address: u16, data: u8) {
+        if self.watchpoints.is_empty() || self.watch_hit.get().is_some() {
+            return;
+        }
+        if self
+            .watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.matches(access, address, data))
+        {
+            self.watch_hit.set(Some(WatchHit {
+                access,
+                address,
+                data,
+            }));
+        }
     }
     pub fn registers(&self) -> &CpuRegisters {
         &self.registers
@@ -81,10 +318,207 @@ impl Bus {
         &mut self.registers
     }
 
+    /// PPU 执行一个点。
+    ///
+    /// 期间 PPU 地址线 A12 保持低电平足够久之后变为高电平时通知卡带，
+    /// 短暂的低电平（例如同一行内读取名称表）被过滤掉；每条扫描线开始时也通知卡带
+    pub fn clock_ppu(&mut self) -> Result<()> {
+        let mut vram = VideoMemory {
+            memory: &mut self.ppu_memory,
+            mapper: self.mapper.as_mut(),
+            a12: &self.a12,
+        };
+        self.ppu.clock(&mut vram)?;
+        self.a12.dot += 1;
+        self.notify_a12();
+        if self.ppu.dot() == 0 {
+            self.mapper.scanline();
+        }
+        Ok(())
+    }
+    fn notify_a12(&mut self) {
+        for _ in 0..self.a12.rises.take() {
+            self.mapper.ppu_a12_rise();
+        }
+    }
+    /// 卡带执行一个 CPU 周期
+    pub fn clock_mapper(&mut self) {
+        self.mapper.clock_cpu();
+    }
+    /// 卡带的 IRQ 输出
+    pub fn mapper_irq(&self) -> bool {
+        self.mapper.irq()
+    }
+    /// APU 执行一个 CPU 周期，并在 DMC 的样本缓冲区空出时请求 DMA
+    pub fn clock_apu(&mut self) {
+        if let Some(sample) = self.dmc_sample.take() {
+            self.apu.load_dmc_sample(sample);
+        }
+        self.apu.clock();
+        if let Some(address) = self.apu.dmc_request() {
+            self.request_dmc_dma(address);
+        }
+    }
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
     pub fn ppu_register(&self) -> PpuRegister<'_> {
-        PpuRegister { cpu_bus: self }
+        self.ppu.register()
+    }
+}
+
+const ADDRESS_NAME_TABLE_START: u16 = 0x2000;
+const ADDRESS_NAME_TABLE_END: u16 = 0x3EFF;
+const ADDRESS_PALETTE_START: u16 = 0x3F00;
+
+/// 读取 PPU 的地址空间：卡带上的图案表，卡带决定映射方式的名称表，主机内的调色板
+fn video_read(memory: &PpuMemory, mapper: &dyn Mapper, address: u16) -> Result<u8> {
+    let address = address & 0x3FFF;
+    match address {
+        ADDRESS_NAME_TABLE_START..=ADDRESS_NAME_TABLE_END => match mapper.map_name_table(address) {
+            Some(offset) => Ok(memory.read_name_table(offset)),
+            None => mapper.ppu_read(address),
+        },
+        ADDRESS_PALETTE_START.. => memory.read(address),
+        _ => mapper.ppu_read(address),
+    }
+}
+
+fn video_write(
+    memory: &mut PpuMemory,
+    mapper: &mut dyn Mapper,
+    address: u16,
+    data: u8,
+) -> Result<()> {
+    let address = address & 0x3FFF;
+    match address {
+        ADDRESS_NAME_TABLE_START..=ADDRESS_NAME_TABLE_END => match mapper.map_name_table(address) {
+            Some(offset) => {
+                memory.write_name_table(offset, data);
+                Ok(())
+            }
+            None => mapper.ppu_write(address, data),
+        },
+        ADDRESS_PALETTE_START.. => memory.write(address, data),
+        _ => mapper.ppu_write(address, data),
+    }
+}
+
+/// 正在进行的 OAM DMA
+#[derive(Debug)]
+struct OamTransfer {
+    page: u8,
+    /// 下一个要读取的字节
+    offset: u16,
+    /// 已读取、等待写入 $2004 的字节
+    data: Option<u8>,
+}
+
+/// PPU 地址线 A12 的状态
+#[derive(Debug, Default)]
+struct A12Watch {
+    /// PPU 执行的点数
+    dot: u64,
+    /// A12 从哪个点开始保持低电平，高电平时为 `None`
+    low_since: Cell<Option<u64>>,
+    /// 尚未通知卡带的上升沿
+    rises: Cell<u32>,
+}
+
+impl A12Watch {
+    /// 低电平至少保持这么多点，上升沿才有效。
+    ///
+    /// 扫描线之间读取名称表的低电平不超过 9 个点，取图案的间隔内则有 64 个点
+    const MIN_LOW_DOTS: u64 = 16;
+
+    fn observe(&self, address: u16) {
+        // 调色板在 PPU 内部，不出现在外部总线上
+        if address & 0x3FFF >= ADDRESS_PALETTE_START {
+            return;
+        }
+        if address & 0x1000 == 0 {
+            if self.low_since.get().is_none() {
+                self.low_since.set(Some(self.dot));
+            }
+        } else if let Some(since) = self.low_since.take() {
+            if self.dot - since >= Self::MIN_LOW_DOTS {
+                self.rises.set(self.rises.get() + 1);
+            }
+        }
+    }
+}
+
+/// PPU 实际访问的地址空间，记录 A12 的变化
+struct VideoMemory<'a> {
+    memory: &'a mut PpuMemory,
+    mapper: &'a mut dyn Mapper,
+    a12: &'a A12Watch,
+}
+
+impl Memory for VideoMemory<'_> {
+    fn read(&self, address: u16) -> Result<u8> {
+        self.a12.observe(address);
+        video_read(self.memory, self.mapper, address)
+    }
+
+    fn write(&mut self, address: u16, data: u8) -> Result<()> {
+        self.a12.observe(address);
+        video_write(self.memory, self.mapper, address, data)
+    }
+}
+
+/// 只读的 PPU 地址空间，用于无副作用地读取寄存器
+struct VideoPeek<'a>(&'a Bus);
+
+impl Memory for VideoPeek<'_> {
+    fn read(&self, address: u16) -> Result<u8> {
+        self.0.ppu_read(address)
+    }
+
+    fn write(&mut self, address: u16, _data: u8) -> Result<()> {
+        Err(MemoryError::WriteMemory(address))
+    }
+}
+/// 不经过总线的 PPU 地址空间，图案表和名称表由 NROM 卡带提供，用于测试 PPU
+#[cfg(test)]
+pub(crate) struct TestVideo {
+    memory: PpuMemory,
+    mapper: Box<dyn Mapper>,
+}
+
+#[cfg(test)]
+impl TestVideo {
+    /// 8K CHR RAM，名称表按 `mirroring` 映射
+    pub(crate) fn new(mirroring: crate::ppu::Mirroring) -> Self {
+        let mapper = crate::rom::make_mapper(0, vec![0; 0x4000], Vec::new(), mirroring).unwrap();
+        Self {
+            memory: PpuMemory::new(),
+            mapper,
+        }
     }
 }
+
+#[cfg(test)]
+impl Memory for TestVideo {
+    fn read(&self, address: u16) -> Result<u8> {
+        video_read(&self.memory, self.mapper.as_ref(), address)
+    }
+
+    fn write(&mut self, address: u16, data: u8) -> Result<()> {
+        video_write(&mut self.memory, self.mapper.as_mut(), address, data)
+    }
+}
+
 impl Debug for Bus {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CpuBus")
@@ -94,3 +528,56 @@ impl Debug for Bus {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Bus, TestVideo};
+    use crate::memory::Memory;
+    use crate::ppu::Mirroring;
+    use crate::rom::make_mapper;
+
+    /// 依次写入四个名称表的第一个字节，返回读到的值
+    fn name_tables(mirroring: Mirroring) -> [u8; 4] {
+        let mut video = TestVideo::new(mirroring);
+        for (index, address) in [0x2000, 0x2400, 0x2800, 0x2C00].into_iter().enumerate() {
+            video.write(address, index as u8 + 1).unwrap();
+        }
+        [0x2000, 0x2400, 0x2800, 0x2C00].map(|address| video.read(address).unwrap())
+    }
+
+    #[test]
+    fn mirroring_test() {
+        assert_eq!(name_tables(Mirroring::Horizontal), [2, 2, 4, 4]);
+        assert_eq!(name_tables(Mirroring::Vertical), [3, 4, 3, 4]);
+        assert_eq!(name_tables(Mirroring::SingleScreenA), [4; 4]);
+        assert_eq!(name_tables(Mirroring::SingleScreenB), [4; 4]);
+        // 后两个名称表由卡带提供
+        assert_eq!(name_tables(Mirroring::FourScreen), [1, 2, 3, 4]);
+
+        let mut video = TestVideo::new(Mirroring::Vertical);
+        video.write(0x2400, 0x22).unwrap();
+        // $3000-$3EFF 是 $2000-$2EFF 的镜像
+        assert_eq!(video.read(0x3400).unwrap(), 0x22);
+        assert_eq!(video.read(0x3C00).unwrap(), 0x22);
+    }
+
+    #[test]
+    fn unmapped_write_test() {
+        // 卡带在 $8000 以下没有接线的地址，写入什么也不做；
+        // $6000 是 NROM/MMC1/MMC3/VRC4 的 PRG RAM，或者 VRC2 只驱动最低位的锁存器
+        for number in [0, 1, 2, 3, 4, 7, 21, 22, 23, 25, 66] {
+            let mapper = make_mapper(
+                number,
+                vec![0; 0x8000],
+                vec![0; 0x2000],
+                Mirroring::Vertical,
+            )
+            .unwrap();
+            let mut bus = Bus::new(mapper);
+            for address in [0x5000, 0x6000] {
+                bus.cpu_write(address, 0x5A).unwrap();
+                assert_eq!(bus.cpu_read(address).unwrap(), 0x5A, "mapper {number}");
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use super::Bus;
 use crate::memory::Result;
+use crate::register::CpuRegisters;
 #[derive(PartialEq, Eq, Debug, Hash, Clone)]
 pub enum AddressingMode {
     Implicit,
@@ -60,7 +61,32 @@ impl AddressingMode {
         }
     }
 
-    pub fn read(&self, bus: &Bus, address: u16) -> Result<u8> {
+    /// 操作数占用的字节数
+    pub fn operand_len(&self) -> usize {
+        match self {
+            AddressingMode::Implicit | AddressingMode::Accumulator => 0,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => 2,
+            _ => 1,
+        }
+    }
+
+    /// 变址寻址所用的变址寄存器值
+    pub fn index(&self, registers: &CpuRegisters) -> u8 {
+        match self {
+            AddressingMode::AbsoluteX | AddressingMode::ZeroPageX | AddressingMode::IndirectX => {
+                registers.x
+            }
+            AddressingMode::AbsoluteY | AddressingMode::ZeroPageY | AddressingMode::IndirectY => {
+                registers.y
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn read(&self, bus: &mut Bus, address: u16) -> Result<u8> {
         if self.addressing_type() == AddressingType::Address {
             bus.cpu_read(address)
         } else {
@@ -139,7 +165,7 @@ impl AddressingMode {
     fn indirect_addressing(bus: &mut Bus) -> Result<(u16, bool)> {
         let pc = bus.registers().pc;
         bus.registers_mut().pc += 2;
-        let low = bus.cpu_read_word(pc)? as u16;
+        let low = bus.cpu_read_word(pc)?;
         let high = (low & 0xFF00) | ((low + 1) & 0x00FF);
         let address = ((bus.cpu_read(high)? as u16) << 8) | (bus.cpu_read(low)? as u16);
         Ok((address, false))
@@ -0,0 +1,791 @@
+use super::addressing::AddressingMode;
+use super::instruction::InstructionInfo;
+use super::{AssemblerError, Bus};
+use crate::memory;
+use std::collections::HashMap;
+
+type Result<T> = std::result::Result<T, AssemblerError>;
+
+/// 从 `origin` 开始连续存放的机器码
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// 汇编结果，每条 `.org` 开始一个新的段
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+    segments: Vec<Segment>,
+    labels: HashMap<String, u16>,
+}
+
+impl Assembly {
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).copied()
+    }
+
+    /// 所有段首尾相接得到的字节
+    pub fn bytes(&self) -> Vec<u8> {
+        self.segments
+            .iter()
+            .flat_map(|segment| segment.bytes.iter().copied())
+            .collect()
+    }
+
+    /// 把各段写入从 `base` 开始映射的内存映像，例如 PRG ROM
+    pub fn write_to(&self, image: &mut [u8], base: u16) -> Result<()> {
+        for segment in &self.segments {
+            let start = segment.origin.wrapping_sub(base) as usize;
+            let end = start + segment.bytes.len();
+            if segment.origin < base || end > image.len() {
+                return Err(AssemblerError::OutOfImage(segment.origin));
+            }
+            image[start..end].copy_from_slice(&segment.bytes);
+        }
+        Ok(())
+    }
+
+    /// 通过 CPU 总线写入各段，只对 RAM 等可写的地址有效
+    pub fn load(&self, bus: &mut Bus) -> memory::Result<()> {
+        for segment in &self.segments {
+            for (offset, byte) in segment.bytes.iter().enumerate() {
+                bus.cpu_write(segment.origin.wrapping_add(offset as u16), *byte)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 两遍汇编。
+///
+/// 支持标签 `name:`、常量 `name = 表达式`、`.org`/`.byte`/`.word` 和 `;` 注释。
+/// 表达式由 `$` 十六进制、`%` 二进制、十进制数和标签相加减组成，`<`/`>` 取低/高字节。
+/// 写成 4 位十六进制或值不在零页的操作数使用绝对寻址，向前引用的标签同样按绝对寻址处理
+pub fn assemble(source: &str) -> Result<Assembly> {
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut address = 0u16;
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = strip_comment(text).trim();
+        while let Some((label, rest)) = split_label(text) {
+            define(&mut labels, line, label, address)?;
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        if let Some((name, expr)) = text.split_once('=') {
+            let name = name.trim();
+            if is_identifier(name) {
+                let value = Expr::parse(expr, line)?.resolve(&labels, line)?;
+                define(&mut labels, line, name, value as u16)?;
+                continue;
+            }
+        }
+        let mut statement = Statement::parse(text, line)?;
+        let size = match &mut statement {
+            Statement::Org(expr) => {
+                address = expr.resolve(&labels, line)? as u16;
+                0
+            }
+            Statement::Byte(data) => data.iter().map(Data::len).sum(),
+            Statement::Word(exprs) => exprs.len() * 2,
+            Statement::Instruction(instruction) => {
+                // 第二遍沿用这里的选择，保证指令长度不变
+                let (code, mode) = instruction.select(&labels, line)?;
+                let len = 1 + mode.operand_len();
+                instruction.selected = Some((code, mode));
+                len
+            }
+        };
+        items.push((line, address, statement));
+        address = address.wrapping_add(size as u16);
+    }
+
+    let mut segments = vec![Segment {
+        origin: 0,
+        bytes: Vec::new(),
+    }];
+    for (line, address, statement) in items {
+        let bytes = &mut segments.last_mut().unwrap().bytes;
+        match statement {
+            Statement::Org(_) => segments.push(Segment {
+                origin: address,
+                bytes: Vec::new(),
+            }),
+            Statement::Byte(data) => {
+                for data in data {
+                    match data {
+                        Data::Expr(expr) => bytes.push(byte(&expr, &labels, line)?),
+                        Data::Text(text) => bytes.extend_from_slice(text.as_bytes()),
+                    }
+                }
+            }
+            Statement::Word(exprs) => {
+                for expr in exprs {
+                    bytes.extend_from_slice(&word(&expr, &labels, line)?.to_le_bytes());
+                }
+            }
+            Statement::Instruction(instruction) => {
+                bytes.extend(instruction.encode(address, &labels, line)?);
+            }
+        }
+    }
+    segments.retain(|segment| !segment.bytes.is_empty());
+    Ok(Assembly { segments, labels })
+}
+
+fn define(labels: &mut HashMap<String, u16>, line: usize, name: &str, value: u16) -> Result<()> {
+    if labels.insert(name.to_string(), value).is_some() {
+        return Err(AssemblerError::DuplicateLabel {
+            line,
+            label: name.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// 去掉字符串之外的 `;` 注释
+fn strip_comment(text: &str) -> &str {
+    let mut quoted = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &text[..index],
+            _ => {}
+        }
+    }
+    text
+}
+
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (label, rest) = text.split_once(':')?;
+    is_identifier(label).then_some((label, rest))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 按 `,` 分割，忽略字符串中的 `,`
+fn split_list(text: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(text[start..].trim());
+    items
+}
+
+fn byte(expr: &Expr, labels: &HashMap<String, u16>, line: usize) -> Result<u8> {
+    let value = expr.resolve(labels, line)?;
+    match value {
+        -0x80..=0xFF => Ok(value as u8),
+        _ => Err(AssemblerError::OutOfRange { line, value }),
+    }
+}
+
+fn zero_page(expr: &Expr, labels: &HashMap<String, u16>, line: usize) -> Result<u8> {
+    let value = expr.resolve(labels, line)?;
+    match value {
+        0..=0xFF => Ok(value as u8),
+        _ => Err(AssemblerError::OutOfRange { line, value }),
+    }
+}
+
+fn word(expr: &Expr, labels: &HashMap<String, u16>, line: usize) -> Result<u16> {
+    let value = expr.resolve(labels, line)?;
+    match value {
+        -0x8000..=0xFFFF => Ok(value as u16),
+        _ => Err(AssemblerError::OutOfRange { line, value }),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    /// 数值以及是否写成了 16 位
+    Number(i32, bool),
+    Label(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Select {
+    Low,
+    High,
+}
+
+#[derive(Debug, Clone)]
+struct Expr {
+    select: Option<Select>,
+    /// 各项及其是否取负
+    terms: Vec<(bool, Term)>,
+}
+
+impl Expr {
+    fn parse(text: &str, line: usize) -> Result<Self> {
+        let syntax = || AssemblerError::Syntax {
+            line,
+            text: text.trim().to_string(),
+        };
+        let mut rest = text.trim();
+        let select = match rest.chars().next() {
+            Some('<') => Some(Select::Low),
+            Some('>') => Some(Select::High),
+            _ => None,
+        };
+        if select.is_some() {
+            rest = rest[1..].trim_start();
+        }
+        let mut terms = Vec::new();
+        let mut negative = false;
+        loop {
+            if let Some(next) = rest.strip_prefix('-') {
+                negative = !negative;
+                rest = next.trim_start();
+                continue;
+            }
+            if let Some(next) = rest.strip_prefix('+') {
+                rest = next.trim_start();
+                continue;
+            }
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let token = rest[..end].trim();
+            let term = if let Some(hex) = token.strip_prefix('$') {
+                let value = i32::from_str_radix(hex, 16).map_err(|_| syntax())?;
+                Term::Number(value, hex.len() > 2)
+            } else if let Some(binary) = token.strip_prefix('%') {
+                let value = i32::from_str_radix(binary, 2).map_err(|_| syntax())?;
+                Term::Number(value, binary.len() > 8)
+            } else if token.starts_with(|c: char| c.is_ascii_digit()) {
+                Term::Number(token.parse().map_err(|_| syntax())?, false)
+            } else if is_identifier(token) {
+                Term::Label(token.to_string())
+            } else {
+                return Err(syntax());
+            };
+            terms.push((negative, term));
+            negative = false;
+            rest = &rest[end..];
+            if rest.is_empty() {
+                break;
+            }
+        }
+        Ok(Self { select, terms })
+    }
+
+    /// 还有未定义的标签时返回 `None`
+    fn eval(&self, labels: &HashMap<String, u16>) -> Option<i32> {
+        let mut value = 0i32;
+        for (negative, term) in &self.terms {
+            let term = match term {
+                Term::Number(number, _) => *number,
+                Term::Label(label) => *labels.get(label)? as i32,
+            };
+            value += if *negative { -term } else { term };
+        }
+        Some(match self.select {
+            Some(Select::Low) => value & 0xFF,
+            Some(Select::High) => (value >> 8) & 0xFF,
+            None => value,
+        })
+    }
+
+    fn resolve(&self, labels: &HashMap<String, u16>, line: usize) -> Result<i32> {
+        self.eval(labels).ok_or_else(|| {
+            let label = self
+                .terms
+                .iter()
+                .find_map(|(_, term)| match term {
+                    Term::Label(label) if !labels.contains_key(label) => Some(label.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            AssemblerError::UndefinedLabel { line, label }
+        })
+    }
+
+    /// 写成了 16 位的数值，即使值很小也使用绝对寻址
+    fn is_wide(&self) -> bool {
+        self.select.is_none()
+            && self
+                .terms
+                .iter()
+                .any(|(_, term)| matches!(term, Term::Number(_, true)))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Data {
+    Expr(Expr),
+    Text(String),
+}
+
+impl Data {
+    fn len(&self) -> usize {
+        match self {
+            Data::Expr(_) => 1,
+            Data::Text(text) => text.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Index {
+    None,
+    X,
+    Y,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    Direct(Expr, Index),
+    Indirect(Expr),
+    IndirectX(Expr),
+    IndirectY(Expr),
+}
+
+impl Operand {
+    fn parse(text: &str, line: usize) -> Result<Self> {
+        let text = text
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>();
+        let upper = text.to_ascii_uppercase();
+        let len = text.len();
+        Ok(if text.is_empty() {
+            Operand::None
+        } else if upper == "A" {
+            Operand::Accumulator
+        } else if let Some(value) = text.strip_prefix('#') {
+            Operand::Immediate(Expr::parse(value, line)?)
+        } else if text.starts_with('(') {
+            if upper.ends_with(",X)") {
+                Operand::IndirectX(Expr::parse(&text[1..len - 3], line)?)
+            } else if upper.ends_with("),Y") {
+                Operand::IndirectY(Expr::parse(&text[1..len - 3], line)?)
+            } else if text.ends_with(')') {
+                Operand::Indirect(Expr::parse(&text[1..len - 1], line)?)
+            } else {
+                return Err(AssemblerError::Syntax { line, text });
+            }
+        } else if upper.ends_with(",X") {
+            Operand::Direct(Expr::parse(&text[..len - 2], line)?, Index::X)
+        } else if upper.ends_with(",Y") {
+            Operand::Direct(Expr::parse(&text[..len - 2], line)?, Index::Y)
+        } else {
+            Operand::Direct(Expr::parse(&text, line)?, Index::None)
+        })
+    }
+
+    fn expr(&self) -> Option<&Expr> {
+        match self {
+            Operand::None | Operand::Accumulator => None,
+            Operand::Immediate(expr)
+            | Operand::Direct(expr, _)
+            | Operand::Indirect(expr)
+            | Operand::IndirectX(expr)
+            | Operand::IndirectY(expr) => Some(expr),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Instruction {
+    mnemonic: String,
+    operand: Operand,
+    /// 第一遍选定的操作码和寻址模式
+    selected: Option<(u8, AddressingMode)>,
+}
+
+impl Instruction {
+    /// 选择操作码和寻址模式，零页与绝对寻址都可用时按第一遍已知的值决定
+    fn select(&self, labels: &HashMap<String, u16>, line: usize) -> Result<(u8, AddressingMode)> {
+        let candidates = match &self.operand {
+            Operand::None => vec![AddressingMode::Implicit, AddressingMode::Accumulator],
+            Operand::Accumulator => vec![AddressingMode::Accumulator],
+            Operand::Immediate(_) => vec![AddressingMode::Immediate],
+            Operand::Direct(expr, index) => {
+                let short = !expr.is_wide()
+                    && matches!(expr.eval(labels), Some(value) if (0..=0xFF).contains(&value));
+                let (zero_page, absolute) = match index {
+                    Index::None => (AddressingMode::ZeroPage, AddressingMode::Absolute),
+                    Index::X => (AddressingMode::ZeroPageX, AddressingMode::AbsoluteX),
+                    Index::Y => (AddressingMode::ZeroPageY, AddressingMode::AbsoluteY),
+                };
+                let mut candidates = if short {
+                    vec![zero_page, absolute]
+                } else {
+                    vec![absolute, zero_page]
+                };
+                if *index == Index::None {
+                    candidates.insert(0, AddressingMode::Relative);
+                }
+                candidates
+            }
+            Operand::Indirect(_) => vec![AddressingMode::Indirect],
+            Operand::IndirectX(_) => vec![AddressingMode::IndirectX],
+            Operand::IndirectY(_) => vec![AddressingMode::IndirectY],
+        };
+        for mode in candidates {
+            if let Some(code) = opcode(&self.mnemonic, &mode) {
+                return Ok((code, mode));
+            }
+        }
+        let known =
+            (0..=0xFF).any(|code| InstructionInfo::from_code(code).ins.mnemonic() == self.mnemonic);
+        Err(if known {
+            AssemblerError::UnsupportedMode {
+                line,
+                mnemonic: self.mnemonic.clone(),
+            }
+        } else {
+            AssemblerError::UnknownMnemonic {
+                line,
+                mnemonic: self.mnemonic.clone(),
+            }
+        })
+    }
+
+    fn encode(&self, address: u16, labels: &HashMap<String, u16>, line: usize) -> Result<Vec<u8>> {
+        let (code, mode) = match &self.selected {
+            Some(selected) => selected.clone(),
+            None => self.select(labels, line)?,
+        };
+        let expr = match self.operand.expr() {
+            Some(expr) => expr,
+            None => return Ok(vec![code]),
+        };
+        Ok(match mode {
+            AddressingMode::Immediate => vec![code, byte(expr, labels, line)?],
+            AddressingMode::Relative => {
+                let target = word(expr, labels, line)?;
+                let offset = target.wrapping_sub(address.wrapping_add(2)) as i16;
+                if !(-0x80..=0x7F).contains(&offset) {
+                    return Err(AssemblerError::BranchOutOfRange { line, target });
+                }
+                vec![code, offset as u8]
+            }
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => {
+                let [low, high] = word(expr, labels, line)?.to_le_bytes();
+                vec![code, low, high]
+            }
+            _ => vec![code, zero_page(expr, labels, line)?],
+        })
+    }
+}
+
+/// 助记符和寻址模式对应的操作码，有多个时优先选择官方指令
+fn opcode(mnemonic: &str, mode: &AddressingMode) -> Option<u8> {
+    let mut found = None;
+    for code in 0..=0xFF {
+        let info = InstructionInfo::from_code(code);
+        if info.ins.mnemonic() == mnemonic && info.mode == *mode {
+            if info.is_official() {
+                return Some(code);
+            }
+            found = found.or(Some(code));
+        }
+    }
+    found
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Org(Expr),
+    Byte(Vec<Data>),
+    Word(Vec<Expr>),
+    Instruction(Instruction),
+}
+
+impl Statement {
+    fn parse(text: &str, line: usize) -> Result<Self> {
+        let (head, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let head = head.to_ascii_uppercase();
+        Ok(match head.as_str() {
+            ".ORG" => Statement::Org(Expr::parse(rest, line)?),
+            ".BYTE" => Statement::Byte(
+                split_list(rest)
+                    .into_iter()
+                    .map(|item| match item.strip_prefix('"') {
+                        Some(text) => match text.strip_suffix('"') {
+                            Some(text) => Ok(Data::Text(text.to_string())),
+                            None => Err(AssemblerError::Syntax {
+                                line,
+                                text: item.to_string(),
+                            }),
+                        },
+                        None => Ok(Data::Expr(Expr::parse(item, line)?)),
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            ".WORD" => Statement::Word(
+                split_list(rest)
+                    .into_iter()
+                    .map(|item| Expr::parse(item, line))
+                    .collect::<Result<_>>()?,
+            ),
+            _ if head.starts_with('.') => {
+                return Err(AssemblerError::Syntax {
+                    line,
+                    text: text.to_string(),
+                })
+            }
+            _ => Statement::Instruction(Instruction {
+                mnemonic: head,
+                operand: Operand::parse(rest, line)?,
+                selected: None,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use crate::clock::Clock;
+    use crate::cpu::{disassemble, AssemblerError, CpuMode};
+    use crate::nes::Nes;
+    use crate::ppu::Mirroring;
+    use crate::rom::make_mapper;
+
+    #[test]
+    fn addressing_mode_test() {
+        let assembly = assemble(
+            "
+            .org $8000
+            nop
+            asl
+            asl a
+            lda #$10
+            lda $10
+            lda $10,x
+            ldx $10,Y
+            lda $0010
+            lda $1234,X
+            lda $1234,y
+            jmp ($1234)
+            lda ($10,X)
+            lda ($10),Y
+            ",
+        )
+        .unwrap();
+        assert_eq!(assembly.segments().len(), 1);
+        assert_eq!(assembly.segments()[0].origin, 0x8000);
+        assert_eq!(
+            assembly.bytes(),
+            [
+                0xEA, 0x0A, 0x0A, 0xA9, 0x10, 0xA5, 0x10, 0xB5, 0x10, 0xB6, 0x10, 0xAD, 0x10, 0x00,
+                0xBD, 0x34, 0x12, 0xB9, 0x34, 0x12, 0x6C, 0x34, 0x12, 0xA1, 0x10, 0xB1, 0x10,
+            ]
+        );
+    }
+
+    #[test]
+    fn label_and_directive_test() {
+        let assembly = assemble(
+            "
+            PPUCTRL = $2000
+            ZP = $10
+            .org $C000
+            start:  ldx #0
+            loop:   inx             ; 向后分支
+                    bne loop
+                    beq done        ; 向前分支
+                    sta PPUCTRL
+                    sta ZP+1
+                    lda #<table
+                    ldy #>table
+            done:   jmp start
+            table:  .byte 1, $02, %11, -1, \"A;,\"
+                    .word table, done - 1
+            .org $FFFC
+                    .word start
+            ",
+        )
+        .unwrap();
+        assert_eq!(assembly.label("loop"), Some(0xC002));
+        assert_eq!(assembly.label("done"), Some(0xC010));
+        assert_eq!(assembly.label("table"), Some(0xC013));
+        let segments = assembly.segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(
+            segments[0].bytes,
+            [
+                0xA2, 0x00, 0xE8, 0xD0, 0xFD, 0xF0, 0x09, 0x8D, 0x00, 0x20, 0x85, 0x11, 0xA9, 0x13,
+                0xA0, 0xC0, 0x4C, 0x00, 0xC0, 0x01, 0x02, 0x03, 0xFF, 0x41, 0x3B, 0x2C, 0x13, 0xC0,
+                0x0F, 0xC0,
+            ][..]
+        );
+        assert_eq!(segments[1].origin, 0xFFFC);
+        assert_eq!(segments[1].bytes, [0x00, 0xC0]);
+
+        let mut prg = vec![0; 0x4000];
+        assembly.write_to(&mut prg, 0xC000).unwrap();
+        assert_eq!(prg[0x3FFC..], [0x00, 0xC0, 0x00, 0x00]);
+        assert_eq!(
+            assembly.write_to(&mut prg, 0xD000),
+            Err(AssemblerError::OutOfImage(0xC000))
+        );
+    }
+
+    #[test]
+    fn forward_reference_test() {
+        // 向前引用的零页标签按绝对寻址汇编，两遍的长度保持一致
+        let assembly = assemble(
+            "
+            lda value
+            value = $10
+            lda value
+            ",
+        )
+        .unwrap();
+        assert_eq!(assembly.bytes(), [0xAD, 0x10, 0x00, 0xA5, 0x10]);
+    }
+
+    #[test]
+    fn unofficial_test() {
+        let assembly = assemble(
+            "
+            sbc #$01
+            nop $10
+            nop #$10
+            lax ($10),y
+            sax $10
+            isb $1234,x
+            jam
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            assembly.bytes(),
+            [0xE9, 0x01, 0x04, 0x10, 0x80, 0x10, 0xB3, 0x10, 0x87, 0x10, 0xFF, 0x34, 0x12, 0x02]
+        );
+    }
+
+    /// 所有操作码反汇编后再汇编得到相同的指令
+    #[test]
+    fn round_trip_test() {
+        for code in 0..=0xFF {
+            let expected = disassemble(&[code, 0x12, 0x34], 0x8000).remove(0);
+            let source = format!(".org $8000\n{} {}", expected.mnemonic, expected.operand);
+            let assembly = assemble(&source).unwrap();
+            let actual = disassemble(&assembly.bytes(), 0x8000).remove(0);
+            assert_eq!(actual.mnemonic, expected.mnemonic);
+            assert_eq!(actual.mode, expected.mode);
+            assert_eq!(actual.operand, expected.operand);
+            if expected.official {
+                assert_eq!(actual.bytes, expected.bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn error_test() {
+        assert_eq!(
+            assemble("nop\nfoo $10").unwrap_err(),
+            AssemblerError::UnknownMnemonic {
+                line: 2,
+                mnemonic: "FOO".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("sta #$10").unwrap_err(),
+            AssemblerError::UnsupportedMode {
+                line: 1,
+                mnemonic: "STA".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("jmp nowhere").unwrap_err(),
+            AssemblerError::UndefinedLabel {
+                line: 1,
+                label: "nowhere".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("a:\na:").unwrap_err(),
+            AssemblerError::DuplicateLabel {
+                line: 2,
+                label: "a".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("lda #$100").unwrap_err(),
+            AssemblerError::OutOfRange {
+                line: 1,
+                value: 0x100
+            }
+        );
+        assert_eq!(
+            assemble(".org $8000\nbne $8100").unwrap_err(),
+            AssemblerError::BranchOutOfRange {
+                line: 2,
+                target: 0x8100
+            }
+        );
+        assert!(matches!(
+            assemble(".fill 10").unwrap_err(),
+            AssemblerError::Syntax { line: 1, .. }
+        ));
+    }
+
+    /// 把程序写入 RAM 后执行
+    #[test]
+    fn run_from_ram_test() {
+        let assembly = assemble(
+            "
+            .org $0300
+                    ldx #10
+                    lda #0
+                    clc
+            loop:   adc count
+                    dex
+                    bne loop
+                    sta result
+            halt:   jmp halt
+            count:  .byte 3
+            result: .byte 0
+            ",
+        )
+        .unwrap();
+        let mut nes = Nes::new(
+            make_mapper(0, vec![0; 0x4000], vec![0; 0x2000], Mirroring::Horizontal).unwrap(),
+        );
+        assembly.load(nes.bus_mut()).unwrap();
+        for mode in [CpuMode::Instruction, CpuMode::Cycle] {
+            nes.cpu_mut().set_mode(mode);
+            nes.reset().unwrap();
+            nes.bus_mut().registers_mut().pc = 0x0300;
+            let halt = assembly.label("halt").unwrap();
+            while !nes.cpu().at_instruction_boundary() || nes.bus().registers().pc != halt {
+                nes.clock().unwrap();
+            }
+            let result = assembly.label("result").unwrap();
+            assert_eq!(nes.bus().cpu_peek(result).unwrap(), 30);
+            nes.bus_mut().cpu_write(result, 0).unwrap();
+        }
+    }
+}
@@ -0,0 +1,492 @@
+use super::addressing::AddressingMode;
+use super::instruction::{unstable_store, Instruction, InstructionInfo, Operation};
+use super::interrupt::InterruptLines;
+use super::{Bus, Interrupt};
+use crate::memory::Result;
+use crate::register::P_FLAGS_B;
+
+const ADDRESS_CPU_STACK_START: u16 = 0x0100;
+
+#[derive(Debug, Clone)]
+enum MicroKind {
+    Instruction(InstructionInfo),
+    /// NMI/IRQ 中断序列，第一个周期取到的操作码被丢弃
+    Interrupt(Interrupt),
+}
+
+/// 逐周期执行的指令，每次 `clock` 恰好访问一次总线
+#[derive(Debug, Clone)]
+pub(super) struct MicroOp {
+    kind: MicroKind,
+    /// 已执行的周期数，取操作码为第 1 个周期
+    cycle: u8,
+    /// 正在计算的有效地址
+    address: u16,
+    /// 变址前的基址，用于处理跨页
+    base: u16,
+    /// 零页指针
+    pointer: u8,
+    data: u8,
+    crossed: bool,
+}
+
+impl MicroOp {
+    /// 第 1 个周期：取操作码
+    pub fn fetch(bus: &mut Bus) -> Result<Self> {
+        let pc = bus.registers().pc;
+        let code = bus.cpu_read(pc)?;
+        bus.registers_mut().pc = pc.wrapping_add(1);
+        Ok(Self::new(MicroKind::Instruction(
+            InstructionInfo::from_code(code),
+        )))
+    }
+
+    /// 第 1 个周期：中断序列读取操作码但不增加 PC
+    pub fn interrupt(bus: &mut Bus, interrupt: Interrupt) -> Result<Self> {
+        bus.cpu_read(bus.registers().pc)?;
+        Ok(Self::new(MicroKind::Interrupt(interrupt)))
+    }
+
+    fn new(kind: MicroKind) -> Self {
+        Self {
+            kind,
+            cycle: 1,
+            address: 0,
+            base: 0,
+            pointer: 0,
+            data: 0,
+            crossed: false,
+        }
+    }
+
+    /// 正在执行的指令，中断序列返回 `None`
+    pub fn instruction(&self) -> Option<Instruction> {
+        match &self.kind {
+            MicroKind::Instruction(info) => Some(info.ins),
+            MicroKind::Interrupt(_) => None,
+        }
+    }
+
+    /// 是否为 BRK/NMI/IRQ 中断序列
+    pub fn is_interrupt(&self) -> bool {
+        matches!(
+            self.kind,
+            MicroKind::Interrupt(_)
+                | MicroKind::Instruction(InstructionInfo {
+                    ins: Instruction::Brk,
+                    ..
+                })
+        )
+    }
+
+    /// 执行下一个周期，指令执行完毕时返回 `true`。
+    ///
+    /// 中断序列在压入状态寄存器时检查 `interrupts` 中的 NMI，检测到则改用 NMI 向量
+    pub fn clock(&mut self, bus: &mut Bus, interrupts: &mut InterruptLines) -> Result<bool> {
+        self.cycle += 1;
+        let info = match &self.kind {
+            MicroKind::Interrupt(interrupt) => {
+                let interrupt = *interrupt;
+                return self.interrupt_cycle(bus, interrupt, interrupts);
+            }
+            MicroKind::Instruction(info) => info.clone(),
+        };
+        match info.ins.operation() {
+            Operation::Read | Operation::Write | Operation::ReadModifyWrite => {
+                self.memory_cycle(bus, &info)
+            }
+            Operation::Implied => {
+                // 读取下一字节但不使用
+                bus.cpu_read(bus.registers().pc)?;
+                info.ins.implied(bus.registers_mut());
+                Ok(true)
+            }
+            Operation::Branch => self.branch_cycle(bus, &info, interrupts),
+            Operation::Control => match info.ins {
+                Instruction::Jmp => self.jmp_cycle(bus, &info),
+                Instruction::Jsr => self.jsr_cycle(bus),
+                Instruction::Rts => self.rts_cycle(bus),
+                Instruction::Rti => self.rti_cycle(bus),
+                Instruction::Pha | Instruction::Php => self.push_cycle(bus, &info),
+                Instruction::Pla | Instruction::Plp => self.pull_cycle(bus, &info),
+                Instruction::Brk => {
+                    if self.cycle == 2 {
+                        // 跳过 BRK 后的填充字节
+                        Self::read_pc(bus)?;
+                        Ok(false)
+                    } else {
+                        self.interrupt_cycle(bus, Interrupt::Brk, interrupts)
+                    }
+                }
+                Instruction::Jam => {
+                    bus.cpu_read(bus.registers().pc)?;
+                    // PC 停在 JAM 指令上，直到复位
+                    bus.registers_mut().pc -= 1;
+                    Ok(true)
+                }
+                _ => unreachable!("{:?} is not a control instruction", info.ins),
+            },
+        }
+    }
+
+    /// 读取 PC 处的字节并增加 PC
+    fn read_pc(bus: &mut Bus) -> Result<u8> {
+        let pc = bus.registers().pc;
+        bus.registers_mut().pc = pc.wrapping_add(1);
+        bus.cpu_read(pc)
+    }
+
+    /// 读取栈顶但不移动栈指针
+    fn read_stack(bus: &mut Bus) -> Result<u8> {
+        bus.cpu_read(ADDRESS_CPU_STACK_START + bus.registers().sp as u16)
+    }
+
+    /// 有效地址计算完毕后进入执行阶段的周期
+    fn execute_cycle(mode: &AddressingMode) -> u8 {
+        match mode {
+            AddressingMode::Immediate | AddressingMode::Accumulator => 2,
+            AddressingMode::ZeroPage => 3,
+            AddressingMode::ZeroPageX | AddressingMode::ZeroPageY | AddressingMode::Absolute => 4,
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => 5,
+            AddressingMode::IndirectX | AddressingMode::IndirectY => 6,
+            _ => unreachable!("{:?} is not a memory addressing mode", mode),
+        }
+    }
+
+    fn memory_cycle(&mut self, bus: &mut Bus, info: &InstructionInfo) -> Result<bool> {
+        let operation = info.ins.operation();
+        match (&info.mode, self.cycle) {
+            (AddressingMode::Immediate, 2) => {
+                let data = Self::read_pc(bus)?;
+                info.ins.read(bus.registers_mut(), data);
+                return Ok(true);
+            }
+            (AddressingMode::Accumulator, 2) => {
+                bus.cpu_read(bus.registers().pc)?;
+                let a = bus.registers().a;
+                let result = info.ins.modify(bus.registers_mut(), a);
+                bus.registers_mut().a = result;
+                return Ok(true);
+            }
+            (
+                AddressingMode::ZeroPage
+                | AddressingMode::ZeroPageX
+                | AddressingMode::ZeroPageY
+                | AddressingMode::Absolute
+                | AddressingMode::AbsoluteX
+                | AddressingMode::AbsoluteY,
+                2,
+            ) => {
+                self.address = Self::read_pc(bus)? as u16;
+                return Ok(false);
+            }
+            (AddressingMode::ZeroPageX | AddressingMode::ZeroPageY, 3) => {
+                bus.cpu_read(self.address)?;
+                let index = info.mode.index(bus.registers());
+                self.address = (self.address + index as u16) & 0x00FF;
+                return Ok(false);
+            }
+            (AddressingMode::Absolute, 3) => {
+                self.address |= (Self::read_pc(bus)? as u16) << 8;
+                return Ok(false);
+            }
+            (AddressingMode::AbsoluteX | AddressingMode::AbsoluteY, 3) => {
+                self.base = self.address | ((Self::read_pc(bus)? as u16) << 8);
+                self.index(bus, &info.mode);
+                return Ok(false);
+            }
+            (AddressingMode::IndirectX | AddressingMode::IndirectY, 2) => {
+                self.pointer = Self::read_pc(bus)?;
+                return Ok(false);
+            }
+            (AddressingMode::IndirectX, 3) => {
+                bus.cpu_read(self.pointer as u16)?;
+                self.pointer = self.pointer.wrapping_add(bus.registers().x);
+                return Ok(false);
+            }
+            (AddressingMode::IndirectX, 4) | (AddressingMode::IndirectY, 3) => {
+                self.address = bus.cpu_read(self.pointer as u16)? as u16;
+                return Ok(false);
+            }
+            (AddressingMode::IndirectX, 5) => {
+                let high = bus.cpu_read(self.pointer.wrapping_add(1) as u16)? as u16;
+                self.address |= high << 8;
+                return Ok(false);
+            }
+            (AddressingMode::IndirectY, 4) => {
+                let high = bus.cpu_read(self.pointer.wrapping_add(1) as u16)? as u16;
+                self.base = self.address | (high << 8);
+                self.index(bus, &info.mode);
+                return Ok(false);
+            }
+            (AddressingMode::AbsoluteX | AddressingMode::AbsoluteY, 4)
+            | (AddressingMode::IndirectY, 5) => {
+                // 先用未进位的高字节读取，读指令未跨页时这就是最终结果
+                let data = bus.cpu_read((self.base & 0xFF00) | (self.address & 0x00FF))?;
+                if operation == Operation::Read && !self.crossed {
+                    info.ins.read(bus.registers_mut(), data);
+                    return Ok(true);
+                }
+                return Ok(false);
+            }
+            _ => {}
+        }
+
+        match (operation, self.cycle - Self::execute_cycle(&info.mode)) {
+            (Operation::Read, 0) => {
+                let data = bus.cpu_read(self.address)?;
+                info.ins.read(bus.registers_mut(), data);
+                Ok(true)
+            }
+            (Operation::Write, 0) => {
+                let data = info.ins.write(bus.registers_mut());
+                let (address, data) = if info.ins.is_unstable_store() {
+                    unstable_store(self.base, self.address, data)
+                } else {
+                    (self.address, data)
+                };
+                bus.cpu_write(address, data)?;
+                Ok(true)
+            }
+            (Operation::ReadModifyWrite, 0) => {
+                self.data = bus.cpu_read(self.address)?;
+                Ok(false)
+            }
+            (Operation::ReadModifyWrite, 1) => {
+                // 先写回原值，再写入新值
+                bus.cpu_write(self.address, self.data)?;
+                self.data = info.ins.modify(bus.registers_mut(), self.data);
+                Ok(false)
+            }
+            (Operation::ReadModifyWrite, 2) => {
+                bus.cpu_write(self.address, self.data)?;
+                Ok(true)
+            }
+            _ => unreachable!("{:?} has no cycle {}", info, self.cycle),
+        }
+    }
+
+    /// 基址加上变址寄存器
+    fn index(&mut self, bus: &Bus, mode: &AddressingMode) {
+        self.address = self.base.wrapping_add(mode.index(bus.registers()) as u16);
+        self.crossed = self.base & 0xFF00 != self.address & 0xFF00;
+    }
+
+    fn branch_cycle(
+        &mut self,
+        bus: &mut Bus,
+        info: &InstructionInfo,
+        interrupts: &mut InterruptLines,
+    ) -> Result<bool> {
+        match self.cycle {
+            2 => {
+                self.data = Self::read_pc(bus)?;
+                Ok(!info.ins.branch(bus.registers()))
+            }
+            3 => {
+                interrupts.delay_irq();
+                let pc = bus.registers().pc;
+                bus.cpu_read(pc)?;
+                self.address = pc.wrapping_add(self.data as i8 as u16);
+                bus.registers_mut().pc = (pc & 0xFF00) | (self.address & 0x00FF);
+                Ok(bus.registers().pc == self.address)
+            }
+            _ => {
+                // 跨页时先读取未修正高字节的地址
+                bus.cpu_read(bus.registers().pc)?;
+                bus.registers_mut().pc = self.address;
+                Ok(true)
+            }
+        }
+    }
+
+    fn jmp_cycle(&mut self, bus: &mut Bus, info: &InstructionInfo) -> Result<bool> {
+        match (&info.mode, self.cycle) {
+            (_, 2) => {
+                self.address = Self::read_pc(bus)? as u16;
+                Ok(false)
+            }
+            (AddressingMode::Absolute, _) => {
+                let high = bus.cpu_read(bus.registers().pc)? as u16;
+                bus.registers_mut().pc = self.address | (high << 8);
+                Ok(true)
+            }
+            (_, 3) => {
+                self.address |= (Self::read_pc(bus)? as u16) << 8;
+                Ok(false)
+            }
+            (_, 4) => {
+                self.data = bus.cpu_read(self.address)?;
+                Ok(false)
+            }
+            _ => {
+                // 指针低字节为 0xFF 时不会进位到高字节
+                let address = (self.address & 0xFF00) | (self.address.wrapping_add(1) & 0x00FF);
+                let high = bus.cpu_read(address)? as u16;
+                bus.registers_mut().pc = (high << 8) | self.data as u16;
+                Ok(true)
+            }
+        }
+    }
+
+    fn jsr_cycle(&mut self, bus: &mut Bus) -> Result<bool> {
+        match self.cycle {
+            2 => {
+                self.address = Self::read_pc(bus)? as u16;
+                Ok(false)
+            }
+            3 => {
+                Self::read_stack(bus)?;
+                Ok(false)
+            }
+            4 => {
+                bus.stack_push((bus.registers().pc >> 8) as u8)?;
+                Ok(false)
+            }
+            5 => {
+                bus.stack_push(bus.registers().pc as u8)?;
+                Ok(false)
+            }
+            _ => {
+                let high = bus.cpu_read(bus.registers().pc)? as u16;
+                bus.registers_mut().pc = self.address | (high << 8);
+                Ok(true)
+            }
+        }
+    }
+
+    fn rts_cycle(&mut self, bus: &mut Bus) -> Result<bool> {
+        match self.cycle {
+            2 => {
+                bus.cpu_read(bus.registers().pc)?;
+                Ok(false)
+            }
+            3 => {
+                Self::read_stack(bus)?;
+                Ok(false)
+            }
+            4 => {
+                self.address = bus.stack_pop()? as u16;
+                Ok(false)
+            }
+            5 => {
+                let high = bus.stack_pop()? as u16;
+                bus.registers_mut().pc = self.address | (high << 8);
+                Ok(false)
+            }
+            _ => {
+                Self::read_pc(bus)?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn rti_cycle(&mut self, bus: &mut Bus) -> Result<bool> {
+        match self.cycle {
+            2 => {
+                bus.cpu_read(bus.registers().pc)?;
+                Ok(false)
+            }
+            3 => {
+                Self::read_stack(bus)?;
+                Ok(false)
+            }
+            4 => {
+                let p = bus.stack_pop()?;
+                Instruction::pull_status_rti(bus.registers_mut(), p);
+                Ok(false)
+            }
+            5 => {
+                self.address = bus.stack_pop()? as u16;
+                Ok(false)
+            }
+            _ => {
+                let high = bus.stack_pop()? as u16;
+                bus.registers_mut().pc = self.address | (high << 8);
+                Ok(true)
+            }
+        }
+    }
+
+    fn push_cycle(&mut self, bus: &mut Bus, info: &InstructionInfo) -> Result<bool> {
+        if self.cycle == 2 {
+            bus.cpu_read(bus.registers().pc)?;
+            return Ok(false);
+        }
+        let data = match info.ins {
+            Instruction::Php => Instruction::push_status(bus.registers()),
+            _ => bus.registers().a,
+        };
+        bus.stack_push(data)?;
+        Ok(true)
+    }
+
+    fn pull_cycle(&mut self, bus: &mut Bus, info: &InstructionInfo) -> Result<bool> {
+        match self.cycle {
+            2 => {
+                bus.cpu_read(bus.registers().pc)?;
+                Ok(false)
+            }
+            3 => {
+                Self::read_stack(bus)?;
+                Ok(false)
+            }
+            _ => {
+                let data = bus.stack_pop()?;
+                match info.ins {
+                    Instruction::Plp => Instruction::pull_status(bus.registers_mut(), data),
+                    _ => Instruction::pull_a(bus.registers_mut(), data),
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// BRK/NMI/IRQ 共用的第 2~7 个周期
+    fn interrupt_cycle(
+        &mut self,
+        bus: &mut Bus,
+        interrupt: Interrupt,
+        interrupts: &mut InterruptLines,
+    ) -> Result<bool> {
+        match self.cycle {
+            2 => {
+                bus.cpu_read(bus.registers().pc)?;
+                Ok(false)
+            }
+            3 => {
+                bus.stack_push((bus.registers().pc >> 8) as u8)?;
+                Ok(false)
+            }
+            4 => {
+                bus.stack_push(bus.registers().pc as u8)?;
+                Ok(false)
+            }
+            5 => {
+                let p = Instruction::push_status(bus.registers());
+                let p = match interrupt {
+                    Interrupt::Brk => p,
+                    _ => p & !P_FLAGS_B,
+                };
+                bus.stack_push(p)?;
+                // 取向量之前出现的 NMI 会劫持 BRK/IRQ
+                self.address = if interrupts.take_nmi() {
+                    Interrupt::Nmi.vector()
+                } else {
+                    interrupt.vector()
+                };
+                Ok(false)
+            }
+            6 => {
+                self.data = bus.cpu_read(self.address)?;
+                bus.registers_mut().set_i_flag(true);
+                Ok(false)
+            }
+            _ => {
+                let high = bus.cpu_read(self.address + 1)? as u16;
+                bus.registers_mut().pc = (high << 8) | self.data as u16;
+                Ok(true)
+            }
+        }
+    }
+}
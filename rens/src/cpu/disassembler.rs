@@ -0,0 +1,264 @@
+use super::addressing::AddressingMode;
+use super::instruction::InstructionInfo;
+use super::Bus;
+use crate::memory::Result;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+
+/// 反汇编得到的一条指令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    pub address: u16,
+    /// 操作码和操作数
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    /// 非官方指令显示时按 nestest 的格式在助记符前加 `*`
+    pub official: bool,
+    pub mode: AddressingMode,
+    /// 格式化后的操作数，例如 `$0200,X`
+    pub operand: String,
+    /// 指令读写或跳转的地址。
+    ///
+    /// 变址和间接寻址依赖寄存器与内存，只有从总线反汇编时才能得到
+    pub effective_address: Option<u16>,
+}
+
+impl DisassembledInstruction {
+    fn decode(address: u16, bytes: &[u8]) -> Self {
+        let info = InstructionInfo::from_code(bytes[0]);
+        let next = address.wrapping_add(bytes.len() as u16);
+        let byte = bytes.get(1).copied().unwrap_or_default();
+        let word = bytes
+            .get(2)
+            .map_or(0, |&high| u16::from_le_bytes([byte, high]));
+        let (operand, effective_address) = match info.mode {
+            AddressingMode::Implicit => (String::new(), None),
+            AddressingMode::Accumulator => ("A".to_string(), None),
+            AddressingMode::Immediate => (format!("#${:02X}", byte), None),
+            AddressingMode::ZeroPage => (format!("${:02X}", byte), Some(byte as u16)),
+            AddressingMode::ZeroPageX => (format!("${:02X},X", byte), None),
+            AddressingMode::ZeroPageY => (format!("${:02X},Y", byte), None),
+            AddressingMode::Absolute => (format!("${:04X}", word), Some(word)),
+            AddressingMode::AbsoluteX => (format!("${:04X},X", word), None),
+            AddressingMode::AbsoluteY => (format!("${:04X},Y", word), None),
+            AddressingMode::Relative => {
+                let target = next.wrapping_add(byte as i8 as u16);
+                (format!("${:04X}", target), Some(target))
+            }
+            AddressingMode::Indirect => (format!("(${:04X})", word), None),
+            AddressingMode::IndirectX => (format!("(${:02X},X)", byte), None),
+            AddressingMode::IndirectY => (format!("(${:02X}),Y", byte), None),
+        };
+        Self {
+            address,
+            bytes: bytes.to_vec(),
+            mnemonic: info.ins.mnemonic(),
+            official: info.is_official(),
+            mode: info.mode,
+            operand,
+            effective_address,
+        }
+    }
+
+    /// 按总线上当前的寄存器和内存计算变址与间接寻址的地址
+    fn resolve(&mut self, bus: &Bus) -> Result<()> {
+        let registers = bus.registers();
+        let byte = self.bytes.get(1).copied().unwrap_or_default();
+        let word = self
+            .bytes
+            .get(2)
+            .map_or(0, |&high| u16::from_le_bytes([byte, high]));
+        let read_zero_page_word = |pointer: u8| -> Result<u16> {
+            let low = bus.cpu_peek(pointer as u16)?;
+            let high = bus.cpu_peek(pointer.wrapping_add(1) as u16)?;
+            Ok(u16::from_le_bytes([low, high]))
+        };
+        let address = match self.mode {
+            AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+                byte.wrapping_add(self.mode.index(registers)) as u16
+            }
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+                word.wrapping_add(self.mode.index(registers) as u16)
+            }
+            AddressingMode::Indirect => {
+                // 指针低字节为 0xFF 时不会进位到高字节
+                let high = (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF);
+                u16::from_le_bytes([bus.cpu_peek(word)?, bus.cpu_peek(high)?])
+            }
+            AddressingMode::IndirectX => read_zero_page_word(byte.wrapping_add(registers.x))?,
+            AddressingMode::IndirectY => {
+                read_zero_page_word(byte)?.wrapping_add(registers.y as u16)
+            }
+            _ => return Ok(()),
+        };
+        self.effective_address = Some(address);
+        Ok(())
+    }
+
+    /// 指令的下一条指令地址
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+}
+
+impl Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !self.official {
+            write!(f, "*")?;
+        }
+        write!(f, "{}", self.mnemonic)?;
+        if !self.operand.is_empty() {
+            write!(f, " {}", self.operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// 反汇编 `base` 开始的一段字节，末尾不完整的指令被忽略
+pub fn disassemble(bytes: &[u8], base: u16) -> Vec<DisassembledInstruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let len = 1 + InstructionInfo::from_code(bytes[offset]).mode.operand_len();
+        if offset + len > bytes.len() {
+            break;
+        }
+        let address = base.wrapping_add(offset as u16);
+        instructions.push(DisassembledInstruction::decode(
+            address,
+            &bytes[offset..offset + len],
+        ));
+        offset += len;
+    }
+    instructions
+}
+
+/// 反汇编总线上 `address` 处的一条指令。
+///
+/// 有效地址按当前寄存器计算，因此只对 PC 处的指令准确
+pub fn disassemble_at(bus: &Bus, address: u16) -> Result<DisassembledInstruction> {
+    let code = bus.cpu_peek(address)?;
+    let len = 1 + InstructionInfo::from_code(code).mode.operand_len();
+    let bytes = (0..len as u16)
+        .map(|offset| bus.cpu_peek(address.wrapping_add(offset)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut instruction = DisassembledInstruction::decode(address, &bytes);
+    instruction.resolve(bus)?;
+    Ok(instruction)
+}
+
+/// 反汇编起始地址落在 `range` 内的所有指令
+pub fn disassemble_range(
+    bus: &Bus,
+    range: RangeInclusive<u16>,
+) -> Result<Vec<DisassembledInstruction>> {
+    let mut instructions = Vec::new();
+    let mut address = *range.start();
+    while range.contains(&address) {
+        let instruction = disassemble_at(bus, address)?;
+        let next = instruction.next_address();
+        instructions.push(instruction);
+        // 回绕到 $0000 时结束
+        if next <= address {
+            break;
+        }
+        address = next;
+    }
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, disassemble_at, disassemble_range};
+    use crate::clock::Clock;
+    use crate::cpu::{AddressingMode, Bus};
+    use crate::nes::Nes;
+    use crate::ppu::Mirroring;
+    use crate::rom::{make_mapper, NesLoader};
+
+    #[test]
+    fn disassemble_slice_test() {
+        // LDA #$01; NOP $A9; BCC *; 不完整的 BNE
+        let instructions = disassemble(&[0xA9, 0x01, 0x04, 0xA9, 0x90, 0xFE, 0xD0], 0x8000);
+        let text = instructions
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(text, ["LDA #$01", "*NOP $A9", "BCC $8004"]);
+        assert_eq!(instructions[1].address, 0x8002);
+        assert_eq!(instructions[1].effective_address, Some(0x00A9));
+        assert_eq!(instructions[2].bytes, [0x90, 0xFE]);
+        assert_eq!(instructions[2].effective_address, Some(0x8004));
+        assert_eq!(instructions[2].next_address(), 0x8006);
+    }
+
+    #[test]
+    fn disassemble_range_test() {
+        let mut prg = vec![0xEA; 0x4000];
+        // JMP ($02FF); ASL A
+        prg[..4].copy_from_slice(&[0x6C, 0xFF, 0x02, 0x0A]);
+        prg[0x3FFE] = 0x4C;
+        let mut bus =
+            Bus::new(make_mapper(0, prg, vec![0; 0x2000], Mirroring::Horizontal).unwrap());
+        bus.cpu_write(0x02FF, 0x34).unwrap();
+        bus.cpu_write(0x0200, 0x12).unwrap();
+        let instructions = disassemble_range(&bus, 0xC000..=0xC004).unwrap();
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[0].to_string(), "JMP ($02FF)");
+        assert_eq!(instructions[0].effective_address, Some(0x1234));
+        assert_eq!(instructions[1].to_string(), "ASL A");
+        assert_eq!(instructions[2].address, 0xC004);
+
+        // 起始地址回绕时结束
+        assert_eq!(disassemble_range(&bus, 0xFFFE..=0xFFFF).unwrap().len(), 1);
+    }
+
+    /// 执行 nestest 时逐条对照日志中的指令
+    #[test]
+    fn nestest_disassemble_test() {
+        let loader =
+            NesLoader::from_slice(&std::fs::read("test_data/nestest.nes").unwrap()).unwrap();
+        let mut nes = Nes::from_loader(&loader).unwrap();
+        nes.reset().unwrap();
+        nes.bus_mut().registers_mut().pc = 0xC000;
+        let log = std::fs::read_to_string("test_data/nestest.log").unwrap();
+        for line in log.lines() {
+            while !nes.cpu().at_instruction_boundary() {
+                nes.clock().unwrap();
+            }
+            let instruction = disassemble_at(nes.bus(), nes.bus().registers().pc).unwrap();
+            let bytes = instruction
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            assert_eq!(line[..4], format!("{:04X}", instruction.address));
+            assert_eq!(line[6..15].trim_end(), bytes);
+            let text = line[15..48].trim();
+            // 去掉日志中附带的内存值
+            let index = [" = ", " @ "]
+                .iter()
+                .filter_map(|separator| text.find(separator))
+                .min();
+            let (text, detail) = match index {
+                Some(index) => (&text[..index], &text[index + 3..]),
+                None => (text, ""),
+            };
+            assert_eq!(text, instruction.to_string(), "{}", line);
+            let resolved = match instruction.mode {
+                AddressingMode::ZeroPageX
+                | AddressingMode::ZeroPageY
+                | AddressingMode::AbsoluteX
+                | AddressingMode::AbsoluteY => detail.split(' ').next(),
+                AddressingMode::IndirectX | AddressingMode::IndirectY => detail.split(' ').nth(2),
+                _ => None,
+            };
+            if let Some(resolved) = resolved {
+                let resolved = u16::from_str_radix(resolved, 16).unwrap();
+                assert_eq!(instruction.effective_address, Some(resolved), "{}", line);
+            }
+            nes.clock().unwrap();
+        }
+    }
+}
@@ -4,6 +4,26 @@ use thiserror::Error;
 pub enum CpuError {
     #[error("Memory error: {0}")]
     Memory(#[from] crate::memory::MemoryError),
-    #[error("Unavailable instruction: {0:#04X}")]
-    UnknownInstruction(u8),
+    #[error("Trace error: {0}")]
+    Trace(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AssemblerError {
+    #[error("Line {line}: syntax error near `{text}`")]
+    Syntax { line: usize, text: String },
+    #[error("Line {line}: unknown mnemonic {mnemonic}")]
+    UnknownMnemonic { line: usize, mnemonic: String },
+    #[error("Line {line}: {mnemonic} does not support this addressing mode")]
+    UnsupportedMode { line: usize, mnemonic: String },
+    #[error("Line {line}: undefined label {label}")]
+    UndefinedLabel { line: usize, label: String },
+    #[error("Line {line}: duplicate label {label}")]
+    DuplicateLabel { line: usize, label: String },
+    #[error("Line {line}: value {value} out of range")]
+    OutOfRange { line: usize, value: i32 },
+    #[error("Line {line}: branch target ${target:04X} out of range")]
+    BranchOutOfRange { line: usize, target: u16 },
+    #[error("Segment at {0:#06X} does not fit in the image")]
+    OutOfImage(u16),
 }
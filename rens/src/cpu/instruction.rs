@@ -1,29 +1,37 @@
-use super::{addressing::AddressingMode, Bus, CpuError};
+use super::{addressing::AddressingMode, Bus, CpuError, Interrupt};
 use crate::{
     memory::Result,
-    register::{P_FLAGS_B, P_FLAGS_C, P_FLAGS_U},
+    register::{CpuRegisters, P_FLAGS_B, P_FLAGS_C, P_FLAGS_U},
 };
 
-#[derive(Debug)]
+/// XAA/LXA 等不稳定指令中与 A 做或运算的常数
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+#[derive(Debug, Default)]
 pub(super) struct InstructionProcessor;
 
 impl InstructionProcessor {
+    pub const OPCODE_BRK: u8 = 0x00;
+
     pub fn process(&self, ins: u8, bus: &mut Bus) -> std::result::Result<u32, CpuError> {
-        let instruction =
-            InstructionInfo::from_code(ins).ok_or(CpuError::UnknownInstruction(ins))?;
+        let instruction = InstructionInfo::from_code(ins);
         instruction.invoke(bus).map_err(|e| e.into())
     }
+    /// 是否为使 CPU 停机的 JAM 指令
+    pub fn is_jam(&self, ins: u8) -> bool {
+        matches!(InstructionInfo::from_code(ins).ins, Instruction::Jam)
+    }
 }
 
-#[derive(Debug)]
-enum InstructionType {
+#[derive(Debug, Clone)]
+pub(super) enum InstructionType {
     Common,
     CrossingPage,
     Branch,
 }
 
-#[derive(Debug)]
-enum Instruction {
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Instruction {
     Jmp,
     Ldx,
     Stx,
@@ -88,20 +96,74 @@ enum Instruction {
     Rla,
     Sre,
     Rra,
+    Brk,
+    Cli,
+    Anc,
+    Alr,
+    Arr,
+    Xaa,
+    Lxa,
+    Axs,
+    Las,
+    Tas,
+    Shy,
+    Shx,
+    Ahx,
+    Jam,
 }
-#[derive(Debug)]
-struct InstructionInfo {
-    code: u8,
-    ins: Instruction,
-    mode: AddressingMode,
-    cycles: u32,
-    ins_type: InstructionType,
+#[derive(Debug, Clone)]
+pub(super) struct InstructionInfo {
+    pub code: u8,
+    pub ins: Instruction,
+    pub mode: AddressingMode,
+    pub cycles: u32,
+    pub ins_type: InstructionType,
 }
 
 impl InstructionInfo {
+    /// 是否为官方文档中的指令，非官方的 NOP/SBC 与官方指令同名但操作码不同
+    pub fn is_official(&self) -> bool {
+        match self.ins {
+            Instruction::Nop => self.code == 0xEA,
+            Instruction::Sbc => self.code != 0xEB,
+            Instruction::Dop
+            | Instruction::Top
+            | Instruction::Lax
+            | Instruction::Aax
+            | Instruction::Dcp
+            | Instruction::Isc
+            | Instruction::Slo
+            | Instruction::Rla
+            | Instruction::Sre
+            | Instruction::Rra
+            | Instruction::Anc
+            | Instruction::Alr
+            | Instruction::Arr
+            | Instruction::Xaa
+            | Instruction::Lxa
+            | Instruction::Axs
+            | Instruction::Las
+            | Instruction::Tas
+            | Instruction::Shy
+            | Instruction::Shx
+            | Instruction::Ahx
+            | Instruction::Jam => false,
+            _ => true,
+        }
+    }
+
     /// 返回寻址模式和时钟周期
-    fn from_code(ins: u8) -> Option<Self> {
-        Some(match ins {
+    pub fn from_code(ins: u8) -> Self {
+        match ins {
+            // BRK
+            InstructionProcessor::OPCODE_BRK => Self {
+                code: ins,
+                ins: Instruction::Brk,
+                mode: AddressingMode::Implicit,
+                cycles: 7,
+                ins_type: InstructionType::Common,
+            },
+
             //JMP
             0x4C => Self {
                 code: ins,
@@ -463,6 +525,15 @@ impl InstructionInfo {
                 ins_type: InstructionType::Common,
             },
 
+            // CLI
+            0x58 => Self {
+                code: ins,
+                ins: Instruction::Cli,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+
             // ASL
             0x0A => Self {
                 code: ins,
@@ -1844,79 +1915,262 @@ impl InstructionInfo {
                 ins_type: InstructionType::Common,
             },
 
-            _ => None?,
-        })
+            // ANC
+            0x0B => Self {
+                code: ins,
+                ins: Instruction::Anc,
+                mode: AddressingMode::Immediate,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0x2B => Self {
+                code: ins,
+                ins: Instruction::Anc,
+                mode: AddressingMode::Immediate,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+
+            // ALR
+            0x4B => Self {
+                code: ins,
+                ins: Instruction::Alr,
+                mode: AddressingMode::Immediate,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+
+            // ARR
+            0x6B => Self {
+                code: ins,
+                ins: Instruction::Arr,
+                mode: AddressingMode::Immediate,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+
+            // XAA
+            0x8B => Self {
+                code: ins,
+                ins: Instruction::Xaa,
+                mode: AddressingMode::Immediate,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+
+            // LXA
+            0xAB => Self {
+                code: ins,
+                ins: Instruction::Lxa,
+                mode: AddressingMode::Immediate,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+
+            // AXS
+            0xCB => Self {
+                code: ins,
+                ins: Instruction::Axs,
+                mode: AddressingMode::Immediate,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+
+            // LAS
+            0xBB => Self {
+                code: ins,
+                ins: Instruction::Las,
+                mode: AddressingMode::AbsoluteY,
+                cycles: 4,
+                ins_type: InstructionType::CrossingPage,
+            },
+
+            // TAS
+            0x9B => Self {
+                code: ins,
+                ins: Instruction::Tas,
+                mode: AddressingMode::AbsoluteY,
+                cycles: 5,
+                ins_type: InstructionType::Common,
+            },
+
+            // SHY
+            0x9C => Self {
+                code: ins,
+                ins: Instruction::Shy,
+                mode: AddressingMode::AbsoluteX,
+                cycles: 5,
+                ins_type: InstructionType::Common,
+            },
+
+            // SHX
+            0x9E => Self {
+                code: ins,
+                ins: Instruction::Shx,
+                mode: AddressingMode::AbsoluteY,
+                cycles: 5,
+                ins_type: InstructionType::Common,
+            },
+
+            // AHX
+            0x9F => Self {
+                code: ins,
+                ins: Instruction::Ahx,
+                mode: AddressingMode::AbsoluteY,
+                cycles: 5,
+                ins_type: InstructionType::Common,
+            },
+            0x93 => Self {
+                code: ins,
+                ins: Instruction::Ahx,
+                mode: AddressingMode::IndirectY,
+                cycles: 6,
+                ins_type: InstructionType::Common,
+            },
+
+            // JAM
+            0x02 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0x12 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0x22 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0x32 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0x42 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0x52 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0x62 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0x72 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0x92 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0xB2 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0xD2 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+            0xF2 => Self {
+                code: ins,
+                ins: Instruction::Jam,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                ins_type: InstructionType::Common,
+            },
+        }
     }
     /// 返回时钟周期
     fn invoke(self, bus: &mut Bus) -> Result<u32> {
         let (address, cross_page) = self.mode.addressing(bus)?;
         // 分支语句是否跳转成功
-        let extra_info = match self.ins {
-            Instruction::Jmp => Self::jmp(bus, self.mode, address),
-            Instruction::Ldx => Self::ldx(bus, self.mode, address),
-            Instruction::Stx => Self::stx(bus, self.mode, address),
-            Instruction::Jsr => Self::jsr(bus, self.mode, address),
-            Instruction::Nop => Self::nop(bus, self.mode, address),
-            Instruction::Sec => Self::sec(bus, self.mode, address),
-            Instruction::Bcs => Self::bcs(bus, self.mode, address),
-            Instruction::Clc => Self::clc(bus, self.mode, address),
-            Instruction::Bcc => Self::bcc(bus, self.mode, address),
-            Instruction::Lda => Self::lda(bus, self.mode, address),
-            Instruction::Beq => Self::beq(bus, self.mode, address),
-            Instruction::Bne => Self::bne(bus, self.mode, address),
-            Instruction::Sta => Self::sta(bus, self.mode, address),
-            Instruction::Bit => Self::bit(bus, self.mode, address),
-            Instruction::Bvs => Self::bvs(bus, self.mode, address),
-            Instruction::Bvc => Self::bvc(bus, self.mode, address),
-            Instruction::Bpl => Self::bpl(bus, self.mode, address),
-            Instruction::Rts => Self::rts(bus, self.mode, address),
-            Instruction::Asl => Self::asl(bus, self.mode, address),
-            Instruction::Sei => Self::sei(bus, self.mode, address),
-            Instruction::Sed => Self::sed(bus, self.mode, address),
-            Instruction::Php => Self::php(bus, self.mode, address),
-            Instruction::Pla => Self::pla(bus, self.mode, address),
-            Instruction::And => Self::and(bus, self.mode, address),
-            Instruction::Cmp => Self::cmp(bus, self.mode, address),
-            Instruction::Cld => Self::cld(bus, self.mode, address),
-            Instruction::Pha => Self::pha(bus, self.mode, address),
-            Instruction::Plp => Self::plp(bus, self.mode, address),
-            Instruction::Bmi => Self::bmi(bus, self.mode, address),
-            Instruction::Ora => Self::ora(bus, self.mode, address),
-            Instruction::Clv => Self::clv(bus, self.mode, address),
-            Instruction::Eor => Self::eor(bus, self.mode, address),
-            Instruction::Adc => Self::adc(bus, self.mode, address),
-            Instruction::Ldy => Self::ldy(bus, self.mode, address),
-            Instruction::Cpy => Self::cpy(bus, self.mode, address),
-            Instruction::Cpx => Self::cpx(bus, self.mode, address),
-            Instruction::Sbc => Self::sbc(bus, self.mode, address),
-            Instruction::Iny => Self::iny(bus, self.mode, address),
-            Instruction::Inx => Self::inx(bus, self.mode, address),
-            Instruction::Dey => Self::dey(bus, self.mode, address),
-            Instruction::Dex => Self::dex(bus, self.mode, address),
-            Instruction::Tay => Self::tay(bus, self.mode, address),
-            Instruction::Tax => Self::tax(bus, self.mode, address),
-            Instruction::Txa => Self::txa(bus, self.mode, address),
-            Instruction::Tya => Self::tya(bus, self.mode, address),
-            Instruction::Tsx => Self::tsx(bus, self.mode, address),
-            Instruction::Txs => Self::txs(bus, self.mode, address),
-            Instruction::Rti => Self::rti(bus, self.mode, address),
-            Instruction::Lsr => Self::lsr(bus, self.mode, address),
-            Instruction::Ror => Self::ror(bus, self.mode, address),
-            Instruction::Rol => Self::rol(bus, self.mode, address),
-            Instruction::Sty => Self::sty(bus, self.mode, address),
-            Instruction::Inc => Self::inc(bus, self.mode, address),
-            Instruction::Dec => Self::dec(bus, self.mode, address),
-            Instruction::Dop => Self::dop(bus, self.mode, address),
-            Instruction::Top => Self::top(bus, self.mode, address),
-            Instruction::Lax => Self::lax(bus, self.mode, address),
-            Instruction::Aax => Self::aax(bus, self.mode, address),
-            Instruction::Dcp => Self::dcp(bus, self.mode, address),
-            Instruction::Isc => Self::isc(bus, self.mode, address),
-            Instruction::Slo => Self::slo(bus, self.mode, address),
-            Instruction::Rla => Self::rla(bus, self.mode, address),
-            Instruction::Sre => Self::sre(bus, self.mode, address),
-            Instruction::Rra => Self::rra(bus, self.mode, address),
-        }?;
+        let extra_info = match self.ins.operation() {
+            Operation::Read => {
+                let data = self.mode.read(bus, address)?;
+                self.ins.read(bus.registers_mut(), data);
+                false
+            }
+            Operation::Write => {
+                let data = self.ins.write(bus.registers_mut());
+                let (address, data) = if self.ins.is_unstable_store() {
+                    let index = self.mode.index(bus.registers());
+                    unstable_store(address.wrapping_sub(index as u16), address, data)
+                } else {
+                    (address, data)
+                };
+                self.mode.write(bus, address, data)?;
+                false
+            }
+            Operation::ReadModifyWrite => {
+                let data = self.mode.read(bus, address)?;
+                // 和逐周期执行时一样，先写回原值，再写入新值
+                if self.mode != AddressingMode::Accumulator {
+                    bus.cpu_write(address, data)?;
+                }
+                let result = self.ins.modify(bus.registers_mut(), data);
+                self.mode.write(bus, address, result)?;
+                false
+            }
+            Operation::Implied => {
+                self.ins.implied(bus.registers_mut());
+                false
+            }
+            Operation::Branch => {
+                let jmp_success = self.ins.branch(bus.registers());
+                if jmp_success {
+                    bus.registers_mut().pc = address;
+                }
+                jmp_success
+            }
+            Operation::Control => match self.ins {
+                Instruction::Jmp => Self::jmp(bus, self.mode, address),
+                Instruction::Jsr => Self::jsr(bus, self.mode, address),
+                Instruction::Rts => Self::rts(bus, self.mode, address),
+                Instruction::Rti => Self::rti(bus, self.mode, address),
+                Instruction::Php => Self::php(bus, self.mode, address),
+                Instruction::Pha => Self::pha(bus, self.mode, address),
+                Instruction::Plp => Self::plp(bus, self.mode, address),
+                Instruction::Pla => Self::pla(bus, self.mode, address),
+                Instruction::Brk => Self::brk(bus, self.mode, address),
+                Instruction::Jam => Self::jam(bus, self.mode, address),
+                _ => unreachable!("{:?} is not a control instruction", self.ins),
+            }?,
+        };
         Ok(match self.ins_type {
             InstructionType::Common => get_cross_page_cycles(self.cycles, false),
             InstructionType::CrossingPage => get_cross_page_cycles(self.cycles, cross_page),
@@ -1928,456 +2182,567 @@ impl InstructionInfo {
         bus.registers_mut().pc = address;
         Ok(false)
     }
-    fn ldx(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        bus.registers_mut().x = data;
-        bus.registers_mut().set_z_n_flags(data);
-        Ok(false)
-    }
-    fn stx(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        mode.write(bus, address, bus.registers().x)?;
-        Ok(false)
-    }
     fn jsr(bus: &mut Bus, _mode: AddressingMode, address: u16) -> Result<bool> {
         bus.stack_push_word(bus.registers().pc - 1)?;
         bus.registers_mut().pc = address;
         Ok(false)
     }
-
-    fn nop(_bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        Ok(false)
-    }
-    fn sec(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        bus.registers_mut().set_c_flag(true);
-        Ok(false)
-    }
-    fn bcs(bus: &mut Bus, _mode: AddressingMode, address: u16) -> Result<bool> {
-        let jmp_success = bus.registers().has_c_flag();
-        if jmp_success {
-            bus.registers_mut().pc = address;
-        }
-        Ok(jmp_success)
-    }
-
-    fn clc(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        bus.registers_mut().set_c_flag(false);
-        Ok(false)
-    }
-    fn bcc(bus: &mut Bus, _mode: AddressingMode, address: u16) -> Result<bool> {
-        let jmp_success = !bus.registers().has_c_flag();
-        if jmp_success {
-            bus.registers_mut().pc = address;
-        }
-        Ok(jmp_success)
-    }
-
-    fn lda(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        bus.registers_mut().a = data;
-        bus.registers_mut().set_z_n_flags(data);
-        Ok(false)
-    }
-    fn beq(bus: &mut Bus, _mode: AddressingMode, address: u16) -> Result<bool> {
-        let jmp_success = bus.registers().has_z_flag();
-        if jmp_success {
-            bus.registers_mut().pc = address;
-        }
-        Ok(jmp_success)
-    }
-    fn bne(bus: &mut Bus, _mode: AddressingMode, address: u16) -> Result<bool> {
-        let jmp_success = !bus.registers().has_z_flag();
-        if jmp_success {
-            bus.registers_mut().pc = address;
-        }
-        Ok(jmp_success)
-    }
-    fn sta(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        mode.write(bus, address, bus.registers().a)?;
-        Ok(false)
-    }
-    fn bit(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let a = bus.registers().a;
-        bus.registers_mut().set_z_flag(data & a == 0);
-        bus.registers_mut()
-            .set_v_flag(data & 0b01000000 == 0b01000000);
-        bus.registers_mut().set_n_flag(data >> 7 == 1);
-        Ok(false)
-    }
-    fn bvs(bus: &mut Bus, _mode: AddressingMode, address: u16) -> Result<bool> {
-        let jmp_success = bus.registers().has_v_flag();
-        if jmp_success {
-            bus.registers_mut().pc = address;
-        }
-        Ok(jmp_success)
-    }
-    fn bvc(bus: &mut Bus, _mode: AddressingMode, address: u16) -> Result<bool> {
-        let jmp_success = !bus.registers().has_v_flag();
-        if jmp_success {
-            bus.registers_mut().pc = address;
-        }
-        Ok(jmp_success)
-    }
-    fn bpl(bus: &mut Bus, _mode: AddressingMode, address: u16) -> Result<bool> {
-        let jmp_success = !bus.registers().has_n_flag();
-
-        if jmp_success {
-            bus.registers_mut().pc = address;
-        }
-        Ok(jmp_success)
-    }
     fn rts(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
         let address = bus.stack_pop_word()?;
         bus.registers_mut().pc = address + 1;
         Ok(false)
     }
-
-    fn sei(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        bus.registers_mut().set_i_flag(true);
-        Ok(false)
-    }
-    fn asl(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let mut data = mode.read(bus, address)?;
-        bus.registers_mut().set_c_flag(data >> 7 == 1);
-        data <<= 1;
-        bus.registers_mut().set_z_n_flags(data);
-        mode.write(bus, address, data)?;
-        Ok(false)
-    }
-
-    fn sed(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        bus.registers_mut().set_d_flag(true);
+    fn rti(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
+        let p = bus.stack_pop()?;
+        Instruction::pull_status_rti(bus.registers_mut(), p);
+        bus.registers_mut().pc = bus.stack_pop_word()?;
         Ok(false)
     }
-
     fn php(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        bus.stack_push(bus.registers().p | P_FLAGS_U | P_FLAGS_B)?;
-        Ok(false)
-    }
-
-    fn pla(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        bus.registers_mut().a = bus.stack_pop()?;
-        let a = bus.registers().a;
-        bus.registers_mut().set_z_n_flags(a);
-        Ok(false)
-    }
-
-    fn and(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result = bus.registers().a & data;
-        bus.registers_mut().a = result;
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
-    }
-    fn cmp(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result = bus.registers().a as i16 - data as i16;
-        bus.registers_mut().set_z_n_flags(result as u8);
-        bus.registers_mut().set_c_flag(result >= 0);
-        Ok(false)
-    }
-
-    fn cld(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        bus.registers_mut().set_d_flag(false);
+        bus.stack_push(Instruction::push_status(bus.registers()))?;
         Ok(false)
     }
-
     fn pha(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
         bus.stack_push(bus.registers().a)?;
         Ok(false)
     }
-
     fn plp(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
         let p = bus.stack_pop()?;
-        bus.registers_mut().p = p;
-        bus.registers_mut().set_u_flag(true);
-        bus.registers_mut().set_b_flag(false);
-        Ok(false)
-    }
-
-    fn bmi(bus: &mut Bus, _mode: AddressingMode, address: u16) -> Result<bool> {
-        let jmp_success = bus.registers().has_n_flag();
-
-        if jmp_success {
-            bus.registers_mut().pc = address;
-        }
-        Ok(jmp_success)
-    }
-
-    fn ora(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result = bus.registers().a | data;
-        bus.registers_mut().a = result;
-        bus.registers_mut().set_z_n_flags(result);
+        Instruction::pull_status(bus.registers_mut(), p);
         Ok(false)
     }
-
-    fn clv(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        bus.registers_mut().set_v_flag(false);
-        Ok(false)
-    }
-
-    fn eor(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result = bus.registers().a ^ data;
-        bus.registers_mut().a = result;
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
-    }
-
-    fn adc(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result =
-            bus.registers().a as u16 + data as u16 + (bus.registers().p & P_FLAGS_C) as u16;
-        let af = bus.registers().a >> 7;
-        let bf = data >> 7;
-        let cf = ((result >> 7) & 1) as u8;
-        bus.registers_mut().set_v_flag(af == bf && af != cf);
-        bus.registers_mut().set_c_flag((result >> 8) & 1 == 1);
-        bus.registers_mut().a = result as u8;
-        bus.registers_mut().set_z_n_flags(result as u8);
-        Ok(false)
-    }
-
-    fn ldy(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        bus.registers_mut().y = data;
-        bus.registers_mut().set_z_n_flags(data);
-        Ok(false)
-    }
-    fn cpy(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let y = bus.registers().y;
-        let m = mode.read(bus, address)?;
-        let result = (y as i16 - m as i16) as u8;
-        bus.registers_mut().set_c_flag(y >= m);
-        bus.registers_mut().set_z_flag(y == m);
-        bus.registers_mut().set_n_flag(result >> 7 == 1);
-        Ok(false)
-    }
-    fn cpx(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let x = bus.registers().x;
-        let m = mode.read(bus, address)?;
-        let result = (x as i16 - m as i16) as u8;
-        bus.registers_mut().set_c_flag(x >= m);
-        bus.registers_mut().set_z_flag(x == m);
-        bus.registers_mut().set_n_flag(result >> 7 == 1);
-        Ok(false)
-    }
-
-    fn sbc(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result =
-            bus.registers().a as i16 - data as i16 - (1 - (bus.registers().p & P_FLAGS_C)) as i16;
-        let af = bus.registers().a >> 7;
-        let bf = data >> 7;
-        let cf = ((result >> 7) & 1) as u8;
-        bus.registers_mut()
-            .set_v_flag((af == 1 && cf == 0) | (af == 0 && bf == 1 && cf == 1));
-        bus.registers_mut().set_c_flag((result >> 8) & 1 == 0);
-        bus.registers_mut().a = result as u8;
-        bus.registers_mut().set_z_n_flags(result as u8);
-        Ok(false)
-    }
-
-    fn iny(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = (bus.registers().y as i16 + 1) as u8;
-        bus.registers_mut().y = result;
-        bus.registers_mut().set_z_n_flags(result);
+    fn pla(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
+        let a = bus.stack_pop()?;
+        Instruction::pull_a(bus.registers_mut(), a);
         Ok(false)
     }
-
-    fn inx(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = (bus.registers().x as i16 + 1) as u8;
-        bus.registers_mut().x = result;
-        bus.registers_mut().set_z_n_flags(result);
+    fn brk(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
+        // BRK 后有一个填充字节，返回地址为 PC + 2
+        let pc = bus.registers().pc + 1;
+        Interrupt::Brk.invoke(bus, pc)?;
         Ok(false)
     }
-
-    fn dex(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = (bus.registers().x as i16 - 1) as u8;
-        bus.registers_mut().x = result;
-        bus.registers_mut().set_z_n_flags(result);
+    fn jam(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
+        // PC 停在 JAM 指令上，直到复位
+        bus.registers_mut().pc -= 1;
         Ok(false)
     }
+}
 
-    fn dey(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = (bus.registers().y as i16 - 1) as u8;
-        bus.registers_mut().y = result;
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
-    }
+/// 指令访问总线的方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Operation {
+    /// 只读取操作数
+    Read,
+    /// 只写入内存
+    Write,
+    /// 读取、修改后写回
+    ReadModifyWrite,
+    /// 只访问寄存器
+    Implied,
+    /// 条件分支
+    Branch,
+    /// 跳转、栈操作等有专门时序的指令
+    Control,
+}
 
-    fn tax(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = bus.registers().a;
-        bus.registers_mut().x = result;
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
+impl Instruction {
+    /// 助记符，非官方指令采用 nestest 中的名称
+    pub(super) fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Jmp => "JMP",
+            Self::Ldx => "LDX",
+            Self::Stx => "STX",
+            Self::Jsr => "JSR",
+            Self::Nop | Self::Dop | Self::Top => "NOP",
+            Self::Sec => "SEC",
+            Self::Bcs => "BCS",
+            Self::Clc => "CLC",
+            Self::Bcc => "BCC",
+            Self::Lda => "LDA",
+            Self::Beq => "BEQ",
+            Self::Bne => "BNE",
+            Self::Sta => "STA",
+            Self::Bit => "BIT",
+            Self::Bvs => "BVS",
+            Self::Bvc => "BVC",
+            Self::Bpl => "BPL",
+            Self::Rts => "RTS",
+            Self::Sei => "SEI",
+            Self::Asl => "ASL",
+            Self::Sed => "SED",
+            Self::Php => "PHP",
+            Self::Pla => "PLA",
+            Self::And => "AND",
+            Self::Cmp => "CMP",
+            Self::Cld => "CLD",
+            Self::Pha => "PHA",
+            Self::Plp => "PLP",
+            Self::Bmi => "BMI",
+            Self::Ora => "ORA",
+            Self::Clv => "CLV",
+            Self::Eor => "EOR",
+            Self::Adc => "ADC",
+            Self::Ldy => "LDY",
+            Self::Cpy => "CPY",
+            Self::Cpx => "CPX",
+            Self::Sbc => "SBC",
+            Self::Iny => "INY",
+            Self::Inx => "INX",
+            Self::Dey => "DEY",
+            Self::Dex => "DEX",
+            Self::Tay => "TAY",
+            Self::Tax => "TAX",
+            Self::Txa => "TXA",
+            Self::Tya => "TYA",
+            Self::Tsx => "TSX",
+            Self::Txs => "TXS",
+            Self::Rti => "RTI",
+            Self::Lsr => "LSR",
+            Self::Ror => "ROR",
+            Self::Rol => "ROL",
+            Self::Sty => "STY",
+            Self::Inc => "INC",
+            Self::Dec => "DEC",
+            Self::Lax => "LAX",
+            Self::Aax => "SAX",
+            Self::Dcp => "DCP",
+            Self::Isc => "ISB",
+            Self::Slo => "SLO",
+            Self::Rla => "RLA",
+            Self::Sre => "SRE",
+            Self::Rra => "RRA",
+            Self::Brk => "BRK",
+            Self::Cli => "CLI",
+            Self::Anc => "ANC",
+            Self::Alr => "ALR",
+            Self::Arr => "ARR",
+            Self::Xaa => "XAA",
+            Self::Lxa => "LXA",
+            Self::Axs => "AXS",
+            Self::Las => "LAS",
+            Self::Tas => "TAS",
+            Self::Shy => "SHY",
+            Self::Shx => "SHX",
+            Self::Ahx => "AHX",
+            Self::Jam => "JAM",
+        }
     }
 
-    fn tay(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = bus.registers().a;
-        bus.registers_mut().y = result;
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
+    pub(super) fn operation(&self) -> Operation {
+        match self {
+            Self::Lda
+            | Self::Ldx
+            | Self::Ldy
+            | Self::And
+            | Self::Ora
+            | Self::Eor
+            | Self::Adc
+            | Self::Sbc
+            | Self::Cmp
+            | Self::Cpx
+            | Self::Cpy
+            | Self::Bit
+            | Self::Dop
+            | Self::Top
+            | Self::Lax
+            | Self::Anc
+            | Self::Alr
+            | Self::Arr
+            | Self::Xaa
+            | Self::Lxa
+            | Self::Axs
+            | Self::Las => Operation::Read,
+            Self::Sta
+            | Self::Stx
+            | Self::Sty
+            | Self::Aax
+            | Self::Tas
+            | Self::Shy
+            | Self::Shx
+            | Self::Ahx => Operation::Write,
+            Self::Asl
+            | Self::Lsr
+            | Self::Rol
+            | Self::Ror
+            | Self::Inc
+            | Self::Dec
+            | Self::Slo
+            | Self::Rla
+            | Self::Sre
+            | Self::Rra
+            | Self::Dcp
+            | Self::Isc => Operation::ReadModifyWrite,
+            Self::Nop
+            | Self::Sec
+            | Self::Clc
+            | Self::Sei
+            | Self::Cli
+            | Self::Sed
+            | Self::Cld
+            | Self::Clv
+            | Self::Iny
+            | Self::Inx
+            | Self::Dey
+            | Self::Dex
+            | Self::Tay
+            | Self::Tax
+            | Self::Txa
+            | Self::Tya
+            | Self::Tsx
+            | Self::Txs => Operation::Implied,
+            Self::Bcs
+            | Self::Bcc
+            | Self::Beq
+            | Self::Bne
+            | Self::Bvs
+            | Self::Bvc
+            | Self::Bpl
+            | Self::Bmi => Operation::Branch,
+            Self::Jmp
+            | Self::Jsr
+            | Self::Rts
+            | Self::Rti
+            | Self::Php
+            | Self::Pha
+            | Self::Plp
+            | Self::Pla
+            | Self::Brk
+            | Self::Jam => Operation::Control,
+        }
     }
 
-    fn txa(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = bus.registers().x;
-        bus.registers_mut().a = result;
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
+    /// SHX/SHY/AHX/TAS 写入的值与目标地址都受基址高字节影响
+    pub(super) fn is_unstable_store(&self) -> bool {
+        matches!(self, Self::Tas | Self::Shy | Self::Shx | Self::Ahx)
+    }
+
+    /// 读类指令，处理读到的数据
+    pub(super) fn read(&self, registers: &mut CpuRegisters, data: u8) {
+        match self {
+            Self::Lda => Self::lda(registers, data),
+            Self::Ldx => Self::ldx(registers, data),
+            Self::Ldy => Self::ldy(registers, data),
+            Self::And => Self::and(registers, data),
+            Self::Ora => Self::ora(registers, data),
+            Self::Eor => Self::eor(registers, data),
+            Self::Adc => Self::adc(registers, data),
+            Self::Sbc => Self::sbc(registers, data),
+            Self::Cmp => Self::cmp(registers, data),
+            Self::Cpx => Self::cpx(registers, data),
+            Self::Cpy => Self::cpy(registers, data),
+            Self::Bit => Self::bit(registers, data),
+            Self::Dop | Self::Top => {}
+            Self::Lax => Self::lax(registers, data),
+            Self::Anc => Self::anc(registers, data),
+            Self::Alr => Self::alr(registers, data),
+            Self::Arr => Self::arr(registers, data),
+            Self::Xaa => Self::xaa(registers, data),
+            Self::Lxa => Self::lxa(registers, data),
+            Self::Axs => Self::axs(registers, data),
+            Self::Las => Self::las(registers, data),
+            _ => unreachable!("{:?} is not a read instruction", self),
+        }
     }
 
-    fn tya(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = bus.registers().y;
-        bus.registers_mut().a = result;
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
+    /// 写类指令，返回要写入的数据
+    pub(super) fn write(&self, registers: &mut CpuRegisters) -> u8 {
+        match self {
+            Self::Sta => registers.a,
+            Self::Stx | Self::Shx => registers.x,
+            Self::Sty | Self::Shy => registers.y,
+            Self::Aax | Self::Ahx => registers.a & registers.x,
+            Self::Tas => {
+                registers.sp = registers.a & registers.x;
+                registers.sp
+            }
+            _ => unreachable!("{:?} is not a write instruction", self),
+        }
     }
 
-    fn tsx(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = bus.registers().sp;
-        bus.registers_mut().x = result;
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
+    /// 读-改-写类指令，返回写回的数据
+    pub(super) fn modify(&self, registers: &mut CpuRegisters, data: u8) -> u8 {
+        match self {
+            Self::Asl => Self::asl(registers, data),
+            Self::Lsr => Self::lsr(registers, data),
+            Self::Rol => Self::rol(registers, data),
+            Self::Ror => Self::ror(registers, data),
+            Self::Inc => Self::inc(registers, data),
+            Self::Dec => Self::dec(registers, data),
+            Self::Slo => Self::slo(registers, data),
+            Self::Rla => Self::rla(registers, data),
+            Self::Sre => Self::sre(registers, data),
+            Self::Rra => Self::rra(registers, data),
+            Self::Dcp => Self::dcp(registers, data),
+            Self::Isc => Self::isc(registers, data),
+            _ => unreachable!("{:?} is not a read-modify-write instruction", self),
+        }
     }
 
-    fn txs(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = bus.registers().x;
-        bus.registers_mut().sp = result;
-        Ok(false)
+    /// 只访问寄存器的指令
+    pub(super) fn implied(&self, registers: &mut CpuRegisters) {
+        match self {
+            Self::Nop => {}
+            Self::Sec => registers.set_c_flag(true),
+            Self::Clc => registers.set_c_flag(false),
+            Self::Sei => registers.set_i_flag(true),
+            Self::Cli => registers.set_i_flag(false),
+            Self::Sed => registers.set_d_flag(true),
+            Self::Cld => registers.set_d_flag(false),
+            Self::Clv => registers.set_v_flag(false),
+            Self::Iny => {
+                registers.y = registers.y.wrapping_add(1);
+                registers.set_z_n_flags(registers.y);
+            }
+            Self::Inx => {
+                registers.x = registers.x.wrapping_add(1);
+                registers.set_z_n_flags(registers.x);
+            }
+            Self::Dey => {
+                registers.y = registers.y.wrapping_sub(1);
+                registers.set_z_n_flags(registers.y);
+            }
+            Self::Dex => {
+                registers.x = registers.x.wrapping_sub(1);
+                registers.set_z_n_flags(registers.x);
+            }
+            Self::Tay => {
+                registers.y = registers.a;
+                registers.set_z_n_flags(registers.y);
+            }
+            Self::Tax => {
+                registers.x = registers.a;
+                registers.set_z_n_flags(registers.x);
+            }
+            Self::Txa => {
+                registers.a = registers.x;
+                registers.set_z_n_flags(registers.a);
+            }
+            Self::Tya => {
+                registers.a = registers.y;
+                registers.set_z_n_flags(registers.a);
+            }
+            Self::Tsx => {
+                registers.x = registers.sp;
+                registers.set_z_n_flags(registers.x);
+            }
+            Self::Txs => registers.sp = registers.x,
+            _ => unreachable!("{:?} is not an implied instruction", self),
+        }
     }
 
-    fn rti(bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        let result = bus.stack_pop()? | P_FLAGS_U;
-        bus.registers_mut().p = result;
-        bus.registers_mut().pc = bus.stack_pop_word()?;
-        Ok(false)
+    /// 分支条件是否成立
+    pub(super) fn branch(&self, registers: &CpuRegisters) -> bool {
+        match self {
+            Self::Bcs => registers.has_c_flag(),
+            Self::Bcc => !registers.has_c_flag(),
+            Self::Beq => registers.has_z_flag(),
+            Self::Bne => !registers.has_z_flag(),
+            Self::Bvs => registers.has_v_flag(),
+            Self::Bvc => !registers.has_v_flag(),
+            Self::Bmi => registers.has_n_flag(),
+            Self::Bpl => !registers.has_n_flag(),
+            _ => unreachable!("{:?} is not a branch instruction", self),
+        }
     }
 
-    fn lsr(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result = data >> 1;
-        mode.write(bus, address, result)?;
-        bus.registers_mut().set_c_flag(data & 1 == 1);
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
+    /// PHP/BRK 压栈的状态寄存器
+    pub(super) fn push_status(registers: &CpuRegisters) -> u8 {
+        registers.p | P_FLAGS_U | P_FLAGS_B
     }
-    fn ror(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result = (data >> 1) | ((bus.registers().p & P_FLAGS_C) << 7);
-        mode.write(bus, address, result)?;
-        bus.registers_mut().set_c_flag(data & 1 == 1);
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
+    /// PLP 出栈的状态寄存器
+    pub(super) fn pull_status(registers: &mut CpuRegisters, data: u8) {
+        registers.p = data;
+        registers.set_u_flag(true);
+        registers.set_b_flag(false);
     }
-    fn rol(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result = (data << 1) | (bus.registers().p & P_FLAGS_C);
-        mode.write(bus, address, result)?;
-        bus.registers_mut().set_c_flag(data >> 7 == 1);
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
-    }
-
-    fn sty(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        mode.write(bus, address, bus.registers().y)?;
-        Ok(false)
+    /// RTI 出栈的状态寄存器
+    pub(super) fn pull_status_rti(registers: &mut CpuRegisters, data: u8) {
+        registers.p = data | P_FLAGS_U;
     }
-    fn inc(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let data = ((data as i16) + 1) as u8;
-        mode.write(bus, address, data)?;
-        bus.registers_mut().set_z_n_flags(data);
-        Ok(false)
+    pub(super) fn pull_a(registers: &mut CpuRegisters, data: u8) {
+        registers.a = data;
+        registers.set_z_n_flags(data);
     }
 
-    fn dec(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let result = ((data as i16) - 1) as u8;
-        mode.write(bus, address, result)?;
-        bus.registers_mut().set_z_n_flags(result);
-        Ok(false)
+    fn lda(registers: &mut CpuRegisters, data: u8) {
+        registers.a = data;
+        registers.set_z_n_flags(data);
     }
-
-    fn dop(_bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        Ok(false)
+    fn ldx(registers: &mut CpuRegisters, data: u8) {
+        registers.x = data;
+        registers.set_z_n_flags(data);
     }
-    fn top(_bus: &mut Bus, _mode: AddressingMode, _address: u16) -> Result<bool> {
-        Ok(false)
+    fn ldy(registers: &mut CpuRegisters, data: u8) {
+        registers.y = data;
+        registers.set_z_n_flags(data);
     }
-
-    fn lax(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        bus.registers_mut().a = data;
-        bus.registers_mut().x = data;
-        bus.registers_mut().set_z_n_flags(data);
-        Ok(false)
+    fn and(registers: &mut CpuRegisters, data: u8) {
+        registers.a &= data;
+        registers.set_z_n_flags(registers.a);
     }
-
-    fn aax(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = bus.registers().a & bus.registers().x;
-        mode.write(bus, address, data)?;
-        Ok(false)
+    fn ora(registers: &mut CpuRegisters, data: u8) {
+        registers.a |= data;
+        registers.set_z_n_flags(registers.a);
     }
-    fn dcp(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let tmp = (data as i16 - 1) as u8;
-        mode.write(bus, address, tmp)?;
-        let result = (bus.registers().a as i16 - tmp as i16) as u16;
-        bus.registers_mut().set_c_flag(result < 0x0100);
-        bus.registers_mut().set_z_n_flags(result as u8);
-        Ok(false)
+    fn eor(registers: &mut CpuRegisters, data: u8) {
+        registers.a ^= data;
+        registers.set_z_n_flags(registers.a);
     }
-    fn isc(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let mut data = mode.read(bus, address)?;
-        data = (data as i16 + 1) as u8;
-        bus.cpu_write(address, data)?;
-        let result =
-            bus.registers().a as i16 - data as i16 - 1 + ((bus.registers().p & P_FLAGS_C) as i16);
-        let af = bus.registers().a >> 7;
+    fn adc(registers: &mut CpuRegisters, data: u8) {
+        let result = registers.a as u16 + data as u16 + (registers.p & P_FLAGS_C) as u16;
+        let af = registers.a >> 7;
         let bf = data >> 7;
-        let cf = (result >> 7) & 1;
-        bus.registers_mut()
-            .set_v_flag((af == 1 && cf == 0) || (af == 0 && bf == 1 && cf == 1));
-        bus.registers_mut().set_c_flag((result >> 8) & 1 != 1);
-        let a = result as u8;
-        bus.registers_mut().a = a;
-        bus.registers_mut().set_z_n_flags(a);
-        Ok(false)
-    }
-    fn slo(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        bus.registers_mut().set_c_flag(data >> 7 == 1);
-        let data = ((data as u16) << 1) as u8;
-        mode.write(bus, address, data)?;
-        bus.registers_mut().a |= data;
-        let a = bus.registers().a;
-        bus.registers_mut().set_z_n_flags(a);
-        Ok(false)
-    }
-    fn rla(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let data = mode.read(bus, address)?;
-        let new = (data << 1) | (bus.registers().p & P_FLAGS_C);
-        mode.write(bus, address, new)?;
-        bus.registers_mut().set_c_flag(data >> 7 == 1);
-        bus.registers_mut().a &= new;
-        let a = bus.registers().a;
-        bus.registers_mut().set_z_n_flags(a);
-        Ok(false)
-    }
-    fn sre(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        let mut data = mode.read(bus, address)?;
-        bus.registers_mut().set_c_flag((data & 1) == 1);
-        data >>= 1;
-        mode.write(bus, address, data)?;
-        bus.registers_mut().a ^= data;
-        let a = bus.registers().a;
-        bus.registers_mut().set_z_n_flags(a);
-        Ok(false)
+        let cf = ((result >> 7) & 1) as u8;
+        registers.set_v_flag(af == bf && af != cf);
+        registers.set_c_flag((result >> 8) & 1 == 1);
+        registers.a = result as u8;
+        registers.set_z_n_flags(result as u8);
+    }
+    fn sbc(registers: &mut CpuRegisters, data: u8) {
+        let result = registers.a as i16 - data as i16 - (1 - (registers.p & P_FLAGS_C)) as i16;
+        let af = registers.a >> 7;
+        let bf = data >> 7;
+        let cf = ((result >> 7) & 1) as u8;
+        registers.set_v_flag((af == 1 && cf == 0) | (af == 0 && bf == 1 && cf == 1));
+        registers.set_c_flag((result >> 8) & 1 == 0);
+        registers.a = result as u8;
+        registers.set_z_n_flags(result as u8);
+    }
+    fn cmp(registers: &mut CpuRegisters, data: u8) {
+        Self::compare(registers, registers.a, data);
+    }
+    fn cpx(registers: &mut CpuRegisters, data: u8) {
+        Self::compare(registers, registers.x, data);
+    }
+    fn cpy(registers: &mut CpuRegisters, data: u8) {
+        Self::compare(registers, registers.y, data);
+    }
+    fn compare(registers: &mut CpuRegisters, register: u8, data: u8) {
+        registers.set_c_flag(register >= data);
+        registers.set_z_n_flags(register.wrapping_sub(data));
+    }
+    fn bit(registers: &mut CpuRegisters, data: u8) {
+        registers.set_z_flag(data & registers.a == 0);
+        registers.set_v_flag(data & 0b01000000 == 0b01000000);
+        registers.set_n_flag(data >> 7 == 1);
+    }
+    fn lax(registers: &mut CpuRegisters, data: u8) {
+        registers.a = data;
+        registers.x = data;
+        registers.set_z_n_flags(data);
+    }
+    fn anc(registers: &mut CpuRegisters, data: u8) {
+        Self::and(registers, data);
+        registers.set_c_flag(registers.a >> 7 == 1);
+    }
+    fn alr(registers: &mut CpuRegisters, data: u8) {
+        let data = registers.a & data;
+        registers.a = Self::lsr(registers, data);
+    }
+    fn arr(registers: &mut CpuRegisters, data: u8) {
+        let data = registers.a & data;
+        let result = (data >> 1) | ((registers.p & P_FLAGS_C) << 7);
+        registers.a = result;
+        registers.set_z_n_flags(result);
+        registers.set_c_flag((result >> 6) & 1 == 1);
+        registers.set_v_flag(((result >> 6) ^ (result >> 5)) & 1 == 1);
+    }
+    /// 不稳定指令，结果与芯片有关，这里取常见的常数 0xEE
+    fn xaa(registers: &mut CpuRegisters, data: u8) {
+        registers.a = (registers.a | UNSTABLE_MAGIC) & registers.x & data;
+        registers.set_z_n_flags(registers.a);
+    }
+    /// 不稳定指令，与 XAA 使用相同的常数
+    fn lxa(registers: &mut CpuRegisters, data: u8) {
+        Self::lax(registers, (registers.a | UNSTABLE_MAGIC) & data);
+    }
+    fn axs(registers: &mut CpuRegisters, data: u8) {
+        let ax = registers.a & registers.x;
+        registers.x = ax.wrapping_sub(data);
+        registers.set_c_flag(ax >= data);
+        registers.set_z_n_flags(registers.x);
+    }
+    fn las(registers: &mut CpuRegisters, data: u8) {
+        let result = data & registers.sp;
+        registers.sp = result;
+        Self::lax(registers, result);
+    }
+
+    fn asl(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = data << 1;
+        registers.set_c_flag(data >> 7 == 1);
+        registers.set_z_n_flags(result);
+        result
+    }
+    fn lsr(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = data >> 1;
+        registers.set_c_flag(data & 1 == 1);
+        registers.set_z_n_flags(result);
+        result
+    }
+    fn rol(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = (data << 1) | (registers.p & P_FLAGS_C);
+        registers.set_c_flag(data >> 7 == 1);
+        registers.set_z_n_flags(result);
+        result
+    }
+    fn ror(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = (data >> 1) | ((registers.p & P_FLAGS_C) << 7);
+        registers.set_c_flag(data & 1 == 1);
+        registers.set_z_n_flags(result);
+        result
+    }
+    fn inc(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = data.wrapping_add(1);
+        registers.set_z_n_flags(result);
+        result
+    }
+    fn dec(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = data.wrapping_sub(1);
+        registers.set_z_n_flags(result);
+        result
+    }
+    fn slo(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = Self::asl(registers, data);
+        Self::ora(registers, result);
+        result
+    }
+    fn rla(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = Self::rol(registers, data);
+        Self::and(registers, result);
+        result
+    }
+    fn sre(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = Self::lsr(registers, data);
+        Self::eor(registers, result);
+        result
+    }
+    fn rra(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = Self::ror(registers, data);
+        Self::adc(registers, result);
+        result
+    }
+    fn dcp(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = data.wrapping_sub(1);
+        Self::cmp(registers, result);
+        result
+    }
+    fn isc(registers: &mut CpuRegisters, data: u8) -> u8 {
+        let result = data.wrapping_add(1);
+        Self::sbc(registers, result);
+        result
     }
-    fn rra(bus: &mut Bus, mode: AddressingMode, address: u16) -> Result<bool> {
-        Self::ror(bus, mode.clone(), address).and(Self::adc(bus, mode, address))
+}
+
+/// SHX/SHY/AHX/TAS 写入 `data & (H + 1)`，H 为变址前基址的高字节。
+/// 跨页时写入的值同时替换目标地址的高字节，返回 `(地址, 数据)`
+pub(super) fn unstable_store(base: u16, address: u16, data: u8) -> (u16, u8) {
+    let data = data & ((base >> 8) as u8).wrapping_add(1);
+    if base & 0xFF00 != address & 0xFF00 {
+        (((data as u16) << 8) | (address & 0x00FF), data)
+    } else {
+        (address, data)
     }
 }
 
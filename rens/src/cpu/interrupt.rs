@@ -0,0 +1,135 @@
+use super::{Bus, Cpu};
+use crate::memory::Result;
+use crate::register::{P_FLAGS_B, P_FLAGS_U};
+
+/// 中断类型
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+    /// 软件中断，与 IRQ 共用中断向量
+    Brk,
+}
+
+impl Interrupt {
+    /// 中断序列所需时钟周期
+    pub const CYCLES: u32 = 7;
+
+    pub fn vector(&self) -> u16 {
+        match self {
+            Interrupt::Nmi => Cpu::VECTOR_NMI,
+            Interrupt::Irq | Interrupt::Brk => Cpu::VECTOR_IRQ_OR_BRK,
+        }
+    }
+
+    /// 压入返回地址与状态寄存器，设置 I 标志并跳转到中断向量。
+    ///
+    /// 只有 BRK 压入的状态寄存器带 B 标志
+    pub(super) fn invoke(&self, bus: &mut Bus, return_address: u16) -> Result<()> {
+        let p = bus.registers().p | P_FLAGS_U;
+        let p = match self {
+            Interrupt::Brk => p | P_FLAGS_B,
+            _ => p & !P_FLAGS_B,
+        };
+        bus.stack_push_word(return_address)?;
+        bus.stack_push(p)?;
+        let pc = bus.cpu_read_word(self.vector())?;
+        let registers = bus.registers_mut();
+        registers.set_i_flag(true);
+        registers.pc = pc;
+        Ok(())
+    }
+}
+
+/// 可以拉低 IRQ 线的中断源，任意一个有效时 IRQ 线有效
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IrqSource {
+    /// APU 帧计数器
+    FrameCounter,
+    /// APU DMC 通道
+    Dmc,
+    /// 卡带上的 Mapper
+    Mapper,
+}
+
+impl IrqSource {
+    fn mask(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+/// CPU 的中断输入线。
+///
+/// NMI 为边沿触发，IRQ 为电平触发。每个周期结束时采样一次，
+/// 指令在倒数第二个周期结束时的采样结果决定指令结束后是否进入中断序列
+#[derive(Debug, Default, Clone)]
+pub(super) struct InterruptLines {
+    nmi_line: bool,
+    prev_nmi_line: bool,
+    /// 检测到 NMI 边沿，直到进入中断序列前保持
+    need_nmi: bool,
+    prev_need_nmi: bool,
+    irq_lines: u8,
+    run_irq: bool,
+    prev_run_irq: bool,
+}
+
+impl InterruptLines {
+    pub fn set_nmi(&mut self, asserted: bool) {
+        self.nmi_line = asserted;
+    }
+
+    pub fn set_irq(&mut self, source: IrqSource, asserted: bool) {
+        if asserted {
+            self.irq_lines |= source.mask();
+        } else {
+            self.irq_lines &= !source.mask();
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq_lines != 0
+    }
+
+    /// 周期结束时采样，`i_flag` 为此时生效的 I 标志
+    pub fn poll(&mut self, i_flag: bool) {
+        self.prev_need_nmi = self.need_nmi;
+        if self.nmi_line && !self.prev_nmi_line {
+            self.need_nmi = true;
+        }
+        self.prev_nmi_line = self.nmi_line;
+        self.prev_run_irq = self.run_irq;
+        self.run_irq = self.irq() && !i_flag;
+    }
+
+    /// 倒数第二个周期结束时有中断等待响应
+    pub fn pending(&self) -> bool {
+        self.prev_need_nmi || self.prev_run_irq
+    }
+
+    /// 中断序列压入状态寄存器时检查 NMI，检测到则改用 NMI 向量
+    pub fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.need_nmi)
+    }
+
+    /// 不跨页的分支指令忽略在其最后一个周期前才出现的 IRQ
+    pub fn delay_irq(&mut self) {
+        if self.run_irq && !self.prev_run_irq {
+            self.run_irq = false;
+        }
+    }
+
+    /// 中断序列结束后，中断处理程序的第一条指令总会执行
+    pub fn finish_interrupt(&mut self) {
+        self.prev_need_nmi = false;
+    }
+
+    /// 复位只清除 CPU 内部的状态，外部中断源保持不变
+    pub fn reset(&mut self) {
+        self.prev_nmi_line = self.nmi_line;
+        self.need_nmi = false;
+        self.prev_need_nmi = false;
+        self.run_irq = false;
+        self.prev_run_irq = false;
+    }
+}
@@ -12,7 +12,8 @@ impl CpuMemory {
 
     const ADDRESS_CPU_MEMORY_START: u16 = 0x0;
     const ADDRESS_CPU_MEMORY_END: u16 = 0x2000 - 1;
-    const ADDRESS_IO_REGISTER_START: u16 = 0x2000;
+    /// $2000-$3FFF 的 PPU 寄存器由总线转发给 PPU
+    const ADDRESS_IO_REGISTER_START: u16 = 0x4000;
     const ADDRESS_IO_REGISTER_END: u16 = 0x4020 - 1;
     pub fn new() -> Self {
         Self {
@@ -1,44 +1,84 @@
 mod addressing;
+mod assembler;
+mod cycle;
+mod disassembler;
 mod error;
 mod instruction;
+mod interrupt;
 mod memory;
 pub mod stack;
+mod trace;
 
 pub use crate::bus::*;
+pub use addressing::AddressingMode;
+pub use assembler::*;
+pub use disassembler::*;
 pub use error::*;
+pub use interrupt::*;
 pub use memory::*;
+pub use trace::*;
 
-use crate::clock::Clock;
 use crate::memory::Result;
 use crate::register::*;
+use cycle::MicroOp;
 use instruction::*;
-use std::{cell::RefCell, rc::Weak};
 
-#[derive(Debug)]
+/// CPU 的执行方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CpuMode {
+    /// 在指令的第一个周期执行完整条指令，剩余周期空转
+    #[default]
+    Instruction,
+    /// 每个周期恰好访问一次总线，包括虚读与读-改-写指令的两次写入
+    Cycle,
+}
+
+#[derive(Debug, Default)]
 pub struct Cpu {
-    bus: Weak<RefCell<Bus>>,
     processor: InstructionProcessor,
+    mode: CpuMode,
     cycles: u32,
     defer_cycles: u32,
+    interrupts: InterruptLines,
+    /// 正在执行的 BRK/NMI/IRQ 中断序列及其开始时的周期数
+    interrupt: Option<(Interrupt, u32)>,
+    /// `CpuMode::Instruction` 下 CLI/SEI/PLP 修改 I 标志前的值，
+    /// 在指令的最后一个周期之前用于采样 IRQ
+    delayed_i: Option<bool>,
+    /// `CpuMode::Instruction` 下正在执行不跨页的分支
+    branch_delay: bool,
+    /// 执行 JAM 指令后停机，只有复位能恢复
+    jammed: bool,
+    /// `CpuMode::Cycle` 下正在执行的指令
+    micro: Option<MicroOp>,
+    /// 被 DMA 挂起
+    dma: bool,
+    tracer: Option<Tracer>,
 }
 
 impl Cpu {
     const VECTOR_RESET: u16 = 0xFFFC;
     const VECTOR_NMI: u16 = 0xFFFA;
     const VECTOR_IRQ_OR_BRK: u16 = 0xFFFE;
-    pub fn new(bus: Weak<RefCell<Bus>>) -> Self {
-        Self {
-            bus,
-            processor: InstructionProcessor,
-            cycles: 0,
-            defer_cycles: 0,
-        }
+    /// BRK/IRQ 序列的前 4 个周期内出现 NMI，会被劫持到 NMI 向量
+    const HIJACK_CYCLES: u32 = 4;
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn mode(&self) -> CpuMode {
+        self.mode
+    }
+    /// 切换执行方式，只应在指令边界调用
+    pub fn set_mode(&mut self, mode: CpuMode) {
+        self.mode = mode;
+    }
+    /// 上一条指令已经执行完毕，下一个周期将取新的操作码
+    pub fn at_instruction_boundary(&self) -> bool {
+        self.defer_cycles == 0 && self.micro.is_none() && !self.dma
     }
-    pub fn reset(&mut self) -> Result<()> {
-        let bus = self.bus.upgrade().unwrap();
-        let mut bus = bus.borrow_mut();
+    pub fn reset(&mut self, bus: &mut Bus) -> Result<()> {
         let pc = bus.cpu_read_word(Self::VECTOR_RESET)?;
-        let mut registers = bus.registers_mut();
+        let registers = bus.registers_mut();
         registers.a = 0;
         registers.x = 0;
         registers.y = 0;
@@ -48,109 +88,797 @@ impl Cpu {
         registers.sp = 0xFD;
         registers.pc = pc;
         self.defer_cycles = 7;
+        self.interrupts.reset();
+        self.interrupt = None;
+        self.delayed_i = None;
+        self.branch_delay = false;
+        self.jammed = false;
+        self.micro = None;
+        self.dma = false;
         Ok(())
     }
-    pub fn nmi(&mut self) -> Result<()> {
-        let bus = self.bus.upgrade().unwrap();
-        let mut bus = bus.borrow_mut();
-        let nmi_pc = bus.cpu_read_word(Self::VECTOR_NMI)?;
-        let pc = bus.registers().pc;
-        let p = bus.registers().p;
-
-        bus.stack_push_word(pc)?;
-        bus.stack_push((p | P_FLAGS_U) & !P_FLAGS_B)?;
-
-        let registers = bus.registers_mut();
-        registers.set_i_flag(true);
-        registers.pc = nmi_pc;
-        self.defer_cycles += 7;
-        Ok(())
+    /// 复位以来执行的周期数
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+    pub fn is_jammed(&self) -> bool {
+        self.jammed
+    }
+    /// 设置 NMI 输入线，由无效变为有效时触发 NMI
+    pub fn set_nmi_line(&mut self, asserted: bool) {
+        self.interrupts.set_nmi(asserted);
+    }
+    /// 设置某个中断源的 IRQ 输入，中断源需要自行撤销
+    pub fn set_irq_line(&mut self, source: IrqSource, asserted: bool) {
+        self.interrupts.set_irq(source, asserted);
+    }
+    /// 是否有中断源使 IRQ 线有效
+    pub fn irq_line(&self) -> bool {
+        self.interrupts.irq()
+    }
+    /// 在每条指令执行前记录一行跟踪日志
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
     }
-    pub fn irq(&mut self) -> Result<()> {
-        let bus = self.bus.upgrade().unwrap();
-        let mut bus = bus.borrow_mut();
-        if bus.registers().p.has_flag(P_FLAGS_I) {
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// 执行一个周期的微操作
+    fn step_cycle(&mut self, bus: &mut Bus) -> std::result::Result<(), CpuError> {
+        if self.jammed {
             return Ok(());
         }
-        let irq_pc = bus.cpu_read_word(Self::VECTOR_IRQ_OR_BRK)?;
-        let pc = bus.registers().pc;
-        let p = bus.registers().p;
-        bus.stack_push_word(pc)?;
-        bus.stack_push((p | P_FLAGS_U) & !P_FLAGS_B)?;
-        let registers = bus.registers_mut();
-        registers.set_i_flag(true);
-        registers.pc = irq_pc;
-        self.defer_cycles += 7;
+        match self.micro.as_mut() {
+            None => {
+                // NMI 与 IRQ 共用中断序列，压入状态寄存器时才根据 NMI 决定向量
+                let micro = if self.interrupts.pending() {
+                    MicroOp::interrupt(bus, Interrupt::Irq)?
+                } else {
+                    if let Some(tracer) = self.tracer.as_mut() {
+                        tracer.trace(bus, self.cycles)?;
+                    }
+                    MicroOp::fetch(bus)?
+                };
+                if micro.is_interrupt() {
+                    self.interrupt = Some((Interrupt::Irq, self.cycles));
+                }
+                self.micro = Some(micro);
+            }
+            Some(micro) => {
+                if micro.clock(bus, &mut self.interrupts)? {
+                    self.jammed = micro.instruction() == Some(Instruction::Jam);
+                    self.micro = None;
+                }
+            }
+        }
         Ok(())
     }
 
-    fn step(&mut self) -> std::result::Result<(), CpuError> {
-        let bus = self.bus.upgrade().unwrap();
-        let mut bus = bus.borrow_mut();
+    /// 当前是否处在 BRK/IRQ 序列取中断向量之前
+    fn is_hijackable(&self) -> bool {
+        matches!(self.interrupt,
+            Some((Interrupt::Brk | Interrupt::Irq, start))
+                if self.cycles.wrapping_sub(start) <= Self::HIJACK_CYCLES)
+    }
+
+    fn step(&mut self, bus: &mut Bus) -> std::result::Result<(), CpuError> {
+        if self.jammed {
+            self.defer_cycles = 1;
+            return Ok(());
+        }
+        self.delayed_i = None;
+        self.branch_delay = false;
+        if self.interrupts.pending() {
+            let interrupt = if self.interrupts.take_nmi() {
+                Interrupt::Nmi
+            } else {
+                Interrupt::Irq
+            };
+            let pc = bus.registers().pc;
+            interrupt.invoke(bus, pc)?;
+            self.defer_cycles = Interrupt::CYCLES;
+            self.interrupt = Some((interrupt, self.cycles));
+            return Ok(());
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(bus, self.cycles)?;
+        }
         let pc = bus.registers().pc;
         bus.registers_mut().pc += 1;
         let op = bus.cpu_read(pc)?;
-        self.defer_cycles = self.processor.process(op, &mut bus)?;
+        let i_flag = bus.registers().has_i_flag();
+        self.defer_cycles = self.processor.process(op, bus)?;
+        let ins = InstructionInfo::from_code(op).ins;
+        match ins {
+            // 新的 I 标志在最后一个周期才生效，中断响应因此推迟一条指令
+            Instruction::Cli | Instruction::Sei | Instruction::Plp => self.delayed_i = Some(i_flag),
+            Instruction::Brk => self.interrupt = Some((Interrupt::Brk, self.cycles)),
+            _ => {}
+        }
+        self.branch_delay = ins.operation() == Operation::Branch && self.defer_cycles == 3;
+        self.jammed = self.processor.is_jam(op);
         Ok(())
     }
-}
 
-impl Clock for Cpu {
-    type Error = CpuError;
-    fn clock(&mut self) -> std::result::Result<(), CpuError> {
-        if self.defer_cycles == 0 {
-            self.step()?;
+    /// 周期结束时采样中断输入线
+    fn end_cycle(&mut self, bus: &mut Bus) -> Result<()> {
+        let i_flag = match self.delayed_i {
+            Some(i_flag) if self.defer_cycles > 0 => i_flag,
+            _ => bus.registers().has_i_flag(),
+        };
+        self.interrupts.poll(i_flag);
+        if self.mode == CpuMode::Instruction && self.is_hijackable() && self.interrupts.take_nmi() {
+            // 已压栈的返回地址和状态寄存器（包括 BRK 的 B 标志）保持不变，只改用 NMI 向量
+            let nmi_pc = bus.cpu_read_word(Self::VECTOR_NMI)?;
+            bus.registers_mut().pc = nmi_pc;
+            self.interrupt = self.interrupt.map(|(_, start)| (Interrupt::Nmi, start));
+        }
+        if self.at_instruction_boundary() && self.interrupt.take().is_some() {
+            self.interrupts.finish_interrupt();
+        }
+        Ok(())
+    }
+
+    /// 执行一个 CPU 周期
+    pub fn clock(&mut self, bus: &mut Bus) -> std::result::Result<(), CpuError> {
+        // DMA 在指令边界挂起 CPU，每个周期读或写一次总线
+        let halted =
+            self.dma || (self.at_instruction_boundary() && !self.jammed && bus.dma_pending());
+        if halted {
+            self.dma = bus.clock_dma(self.cycles)?;
+            self.cycles += 1;
+            self.end_cycle(bus)?;
+            return Ok(());
+        }
+        match self.mode {
+            CpuMode::Instruction => {
+                if self.defer_cycles == 0 {
+                    self.step(bus)?;
+                } else if self.defer_cycles == 1 && self.branch_delay {
+                    self.interrupts.delay_irq();
+                }
+                self.defer_cycles -= 1;
+            }
+            CpuMode::Cycle => {
+                if self.defer_cycles > 0 {
+                    self.defer_cycles -= 1;
+                } else {
+                    self.step_cycle(bus)?;
+                }
+            }
         }
         self.cycles += 1;
-        self.defer_cycles -= 1;
+        self.end_cycle(bus)?;
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Bus, Cpu, CpuRegisters};
+    use super::{CpuMode, CpuRegisters, IrqSource};
     use crate::clock::Clock;
-    use crate::rom::{make_mapper, NesLoader};
+    use crate::memory::{MemoryError, Result};
+    use crate::nes::{run_blargg, Nes};
+    use crate::ppu::Mirroring;
+    use crate::rom::{make_mapper, Mapper, NesLoader};
     use regex::{Captures, Regex};
-    use std::cell::RefCell;
-    use std::rc::Rc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn cpu_test() {
+        run_nestest(CpuMode::Instruction);
+    }
+
+    #[test]
+    fn cpu_cycle_test() {
+        run_nestest(CpuMode::Cycle);
+    }
+
+    fn run_nestest(mode: CpuMode) {
         let loader =
             NesLoader::from_slice(&std::fs::read("test_data/nestest.nes").unwrap()).unwrap();
-        let bus = Rc::new(RefCell::new(Bus::new(
-            make_mapper(
-                loader.header().mapper_number(),
-                loader.prg().to_vec(),
-                loader.chr().to_vec(),
-            )
-            .unwrap(),
-        )));
-        let mut cpu = Cpu::new(Rc::downgrade(&bus));
-        cpu.reset().unwrap();
-        bus.borrow_mut().registers_mut().pc = 0xC000;
+        let mut nes = Nes::from_loader(&loader).unwrap();
+        nes.cpu_mut().set_mode(mode);
+        nes.reset().unwrap();
+        nes.bus_mut().registers_mut().pc = 0xC000;
         let regex = Regex::new(
             r"(?P<ADDR>[A-Z0-9]{4})\s+([A-Z0-9]{2} )+\s*[*#$=@,()A-Z0-9 ]+A:(?P<A>[A-Z0-9]{2}) X:(?P<X>[A-Z0-9]{2}) Y:(?P<Y>[A-Z0-9]{2}) P:(?P<P>[A-Z0-9]{2}) SP:(?P<SP>[A-Z0-9]{2}) PPU:\s*(?P<PPU>\d+,\s*\d+) CYC:(?P<CYC>\d+)",
         ).unwrap();
         let log = std::fs::read_to_string("test_data/nestest.log").unwrap();
         let mut captures = regex.captures_iter(&log);
         loop {
-            if cpu.defer_cycles == 0u32 {
+            if nes.cpu().at_instruction_boundary() {
                 let capture = match captures.next() {
                     None => break,
                     Some(capture) => capture,
                 };
-                assert!(check(capture, cpu.cycles, bus.borrow().registers()));
+                assert!(check(capture, nes.cpu().cycles, nes.bus().registers()));
             }
 
-            if let Err(error) = cpu.clock() {
+            if let Err(error) = nes.clock() {
                 panic!("{}", error);
             }
         }
     }
 
+    const NMI_HANDLER: u16 = 0xC100;
+    const IRQ_HANDLER: u16 = 0xC200;
+
+    /// 用一段从 $C000 开始的程序构造 NROM-128 卡带
+    fn make_nrom(program: &[u8]) -> Box<dyn Mapper> {
+        let mut prg = vec![0xEA; 0x4000];
+        prg[..program.len()].copy_from_slice(program);
+        prg[0x3FFA..].copy_from_slice(&[
+            NMI_HANDLER as u8,
+            (NMI_HANDLER >> 8) as u8,
+            0x00,
+            0xC0,
+            IRQ_HANDLER as u8,
+            (IRQ_HANDLER >> 8) as u8,
+        ]);
+        make_mapper(0, prg, vec![0; 0x2000], Mirroring::Horizontal).unwrap()
+    }
+
+    fn make_nes(program: &[u8]) -> Nes {
+        start(Nes::new(make_nrom(program)))
+    }
+
+    /// 卡带的 IRQ 输出由返回的标志控制
+    fn make_irq_nes(program: &[u8]) -> (Nes, Arc<AtomicBool>) {
+        let line = Arc::new(AtomicBool::new(false));
+        let mapper = IrqMapper {
+            mapper: make_nrom(program),
+            line: line.clone(),
+        };
+        (start(Nes::new(Box::new(mapper))), line)
+    }
+
+    /// 相当于卡带在上一个周期结束时拉低 IRQ 线
+    fn assert_irq(nes: &mut Nes, line: &AtomicBool) {
+        line.store(true, Ordering::Relaxed);
+        nes.cpu_mut().set_irq_line(IrqSource::Mapper, true);
+    }
+
+    fn start(mut nes: Nes) -> Nes {
+        nes.reset().unwrap();
+        run_cycles(&mut nes, 7);
+        nes
+    }
+
+    fn run_cycles(nes: &mut Nes, cycles: u32) {
+        for _ in 0..cycles {
+            nes.clock().unwrap();
+        }
+    }
+
+    fn stack_frame(nes: &Nes) -> (u8, u16) {
+        let bus = nes.bus();
+        let sp = bus.registers().sp as u16;
+        let p = bus.cpu_peek(0x0100 + sp + 1).unwrap();
+        let pc = u16::from_le_bytes([
+            bus.cpu_peek(0x0100 + sp + 2).unwrap(),
+            bus.cpu_peek(0x0100 + sp + 3).unwrap(),
+        ]);
+        (p, pc)
+    }
+
+    const MODES: [CpuMode; 2] = [CpuMode::Instruction, CpuMode::Cycle];
+
+    /// 执行一条指令
+    fn run_instruction(nes: &mut Nes) {
+        run_cycles(nes, 1);
+        while !nes.cpu().at_instruction_boundary() {
+            run_cycles(nes, 1);
+        }
+    }
+
+    #[test]
+    fn brk_test() {
+        for mode in MODES {
+            // BRK; 填充字节
+            let mut nes = make_nes(&[0x00, 0xFF]);
+            nes.cpu_mut().set_mode(mode);
+            nes.bus_mut().registers_mut().p = 0xE3;
+            run_cycles(&mut nes, 7);
+            assert!(nes.cpu().at_instruction_boundary());
+            assert_eq!(nes.cpu().cycles(), 14);
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER);
+            assert_eq!(nes.bus().registers().sp, 0xFA);
+            assert!(nes.bus().registers().has_i_flag());
+            assert_eq!(stack_frame(&nes), (0xF3, 0xC002));
+        }
+    }
+
+    #[test]
+    fn irq_test() {
+        for mode in MODES {
+            let (mut nes, irq) = make_irq_nes(&[]);
+            nes.cpu_mut().set_mode(mode);
+            nes.bus_mut().registers_mut().set_i_flag(false);
+            assert_irq(&mut nes, &irq);
+            // 正在执行的 NOP 先完成
+            run_cycles(&mut nes, 2);
+            assert_eq!(nes.bus().registers().pc, 0xC001);
+            run_cycles(&mut nes, 7);
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER);
+            assert_eq!(stack_frame(&nes), (0x20, 0xC001));
+
+            // I 标志置位时 IRQ 被屏蔽
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER + 1);
+            assert_eq!(nes.bus().registers().sp, 0xFA);
+        }
+    }
+
+    #[test]
+    fn oam_dma_test() {
+        // LDA #$02; STA $4014 在奇数周期开始 DMA，LDA $00; STA $4014 在偶数周期开始
+        for (program, stall) in [
+            ([0xA9, 0x02, 0x8D, 0x14, 0x40], 514),
+            ([0xA5, 0x00, 0x8D, 0x14, 0x40], 513),
+        ] {
+            for mode in MODES {
+                let mut nes = make_nes(&program);
+                nes.cpu_mut().set_mode(mode);
+                for offset in 0..=0xFF {
+                    nes.bus_mut()
+                        .cpu_write(0x0200 + offset, offset as u8)
+                        .unwrap();
+                }
+                nes.bus_mut().cpu_write(0x0000, 0x02).unwrap();
+                // 从 OAMADDR 开始写入
+                nes.bus_mut().cpu_write(0x2003, 0x10).unwrap();
+                run_instruction(&mut nes);
+                run_instruction(&mut nes);
+                let cycles = nes.cpu().cycles();
+                // 挂起和对齐之后每两个周期复制一个字节
+                run_cycles(&mut nes, stall - 512 + 4);
+                assert_eq!(nes.bus().ppu().oam()[0x11], 0x01);
+                assert_eq!(nes.bus().ppu().oam()[0x12], 0x00);
+                run_instruction(&mut nes);
+                assert_eq!(nes.cpu().cycles() - cycles, stall);
+                assert_eq!(nes.bus().registers().pc, 0xC005);
+                let oam = nes.bus().ppu().oam();
+                assert_eq!(oam[0x10], 0x00);
+                assert_eq!(oam[0x11], 0x01);
+                // 属性字节的第 2-4 位不存在
+                assert_eq!(oam[0x12], 0x02 & 0xE3);
+                assert_eq!(oam[0x0F], 0xFF);
+            }
+        }
+    }
+
+    #[test]
+    fn dmc_dma_test() {
+        let mut nes = make_nes(&[]);
+        // 开启中断，最快速率，从 $C400 读取 1 字节
+        for (address, data) in [
+            (0x4010, 0x8F),
+            (0x4012, 0x10),
+            (0x4013, 0x00),
+            (0x4015, 0x10),
+        ] {
+            nes.bus_mut().cpu_write(address, data).unwrap();
+        }
+        // DMC 在 NOP 执行期间请求样本，下一条指令之前读取，在奇数周期开始时不需要对齐
+        run_instruction(&mut nes);
+        let cycles = nes.cpu().cycles();
+        run_instruction(&mut nes);
+        assert_eq!(nes.cpu().cycles() - cycles, 4 - cycles % 2);
+        assert_eq!(nes.bus().cpu_peek(0x4015).unwrap() & 0x90, 0x80);
+        assert!(nes.cpu().irq_line());
+
+        // 与 OAM DMA 重叠时挂起和虚读周期与 OAM DMA 共用，只多占用 2 个周期
+        let mut nes = make_nes(&[]);
+        for (address, data) in [(0x4010, 0x8F), (0x4015, 0x10), (0x4014, 0x03)] {
+            nes.bus_mut().cpu_write(address, data).unwrap();
+        }
+        nes.bus_mut().clock_apu();
+        let cycles = nes.cpu().cycles();
+        run_instruction(&mut nes);
+        assert_eq!(nes.cpu().cycles() - cycles, 513 + cycles % 2 + 2);
+        assert_eq!(nes.bus().cpu_peek(0x4015).unwrap() & 0x90, 0x80);
+    }
+
+    #[test]
+    #[ignore = "需要把 blargg 的 cpu_interrupts_v2 ROM 放到 test_data/cpu_interrupts_v2"]
+    fn blargg_cpu_interrupts_test() {
+        for name in [
+            "1-cli_latency",
+            "2-nmi_and_brk",
+            "3-nmi_and_irq",
+            "4-irq_and_dma",
+            "5-branch_delays_irq",
+        ] {
+            let (result, text) = run_blargg(&format!("test_data/cpu_interrupts_v2/{name}.nes"));
+            assert_eq!(result, 0, "{name}: {text}");
+        }
+    }
+
+    #[test]
+    fn irq_sources_test() {
+        let mut nes = make_nes(&[]);
+        nes.cpu_mut().set_irq_line(IrqSource::FrameCounter, true);
+        nes.cpu_mut().set_irq_line(IrqSource::Dmc, true);
+        nes.cpu_mut().set_irq_line(IrqSource::FrameCounter, false);
+        assert!(nes.cpu().irq_line());
+        nes.cpu_mut().set_irq_line(IrqSource::Dmc, false);
+        assert!(!nes.cpu().irq_line());
+    }
+
+    #[test]
+    fn nmi_test() {
+        for mode in MODES {
+            let mut nes = make_nes(&[]);
+            nes.cpu_mut().set_mode(mode);
+            nes.cpu_mut().set_nmi_line(true);
+            run_cycles(&mut nes, 9);
+            assert!(nes.cpu().at_instruction_boundary());
+            assert_eq!(nes.bus().registers().pc, NMI_HANDLER);
+            assert_eq!(stack_frame(&nes), (0x24, 0xC001));
+
+            // NMI 只在边沿触发
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, NMI_HANDLER + 1);
+            nes.cpu_mut().set_nmi_line(false);
+            run_instruction(&mut nes);
+            nes.cpu_mut().set_nmi_line(true);
+            run_instruction(&mut nes);
+            run_cycles(&mut nes, 7);
+            assert_eq!(nes.bus().registers().pc, NMI_HANDLER);
+            assert_eq!(stack_frame(&nes), (0x24, NMI_HANDLER + 3));
+        }
+    }
+
+    #[test]
+    fn cli_latency_test() {
+        for mode in MODES {
+            // CLI; NOP
+            let (mut nes, irq) = make_irq_nes(&[0x58, 0xEA]);
+            nes.cpu_mut().set_mode(mode);
+            assert_irq(&mut nes, &irq);
+            run_instruction(&mut nes);
+            // CLI 之后的一条指令先执行
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, 0xC002);
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER);
+            assert_eq!(stack_frame(&nes), (0x20, 0xC002));
+        }
+    }
+
+    #[test]
+    fn sei_latency_test() {
+        for mode in MODES {
+            // SEI
+            let (mut nes, irq) = make_irq_nes(&[0x78]);
+            nes.cpu_mut().set_mode(mode);
+            nes.bus_mut().registers_mut().set_i_flag(false);
+            assert_irq(&mut nes, &irq);
+            run_instruction(&mut nes);
+            // SEI 之后仍然响应 IRQ，压入的状态寄存器已带 I 标志
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER);
+            assert_eq!(stack_frame(&nes), (0x24, 0xC001));
+        }
+    }
+
+    #[test]
+    fn plp_latency_test() {
+        for mode in MODES {
+            // LDA #$00; PHA; PLP; NOP
+            let (mut nes, irq) = make_irq_nes(&[0xA9, 0x00, 0x48, 0x28, 0xEA]);
+            nes.cpu_mut().set_mode(mode);
+            assert_irq(&mut nes, &irq);
+            for _ in 0..4 {
+                run_instruction(&mut nes);
+            }
+            assert_eq!(nes.bus().registers().pc, 0xC005);
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER);
+            assert_eq!(stack_frame(&nes), (0x20, 0xC005));
+        }
+    }
+
+    #[test]
+    fn branch_delay_test() {
+        for mode in MODES {
+            // BCC +0; NOP
+            let (mut nes, irq) = make_irq_nes(&[0x90, 0x00, 0xEA]);
+            nes.cpu_mut().set_mode(mode);
+            nes.bus_mut().registers_mut().set_i_flag(false);
+            run_cycles(&mut nes, 1);
+            assert_irq(&mut nes, &irq);
+            run_cycles(&mut nes, 2);
+            // 不跨页的分支在最后一个周期前出现的 IRQ 推迟到下一条指令之后
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, 0xC003);
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER);
+            assert_eq!(stack_frame(&nes), (0x20, 0xC003));
+
+            // 分支开始前出现的 IRQ 在分支之后立即响应
+            let (mut nes, irq) = make_irq_nes(&[0x90, 0x00, 0xEA]);
+            nes.cpu_mut().set_mode(mode);
+            nes.bus_mut().registers_mut().set_i_flag(false);
+            assert_irq(&mut nes, &irq);
+            run_instruction(&mut nes);
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER);
+            assert_eq!(stack_frame(&nes), (0x20, 0xC002));
+        }
+    }
+
+    #[test]
+    fn nmi_hijack_brk_test() {
+        for mode in MODES {
+            let mut nes = make_nes(&[0x00, 0xFF]);
+            nes.cpu_mut().set_mode(mode);
+            run_cycles(&mut nes, 3);
+            nes.cpu_mut().set_nmi_line(true);
+            run_cycles(&mut nes, 4);
+            assert!(nes.cpu().at_instruction_boundary());
+            assert_eq!(nes.bus().registers().pc, NMI_HANDLER);
+            assert_eq!(nes.bus().registers().sp, 0xFA);
+            // 被劫持的 BRK 仍然压入带 B 标志的状态寄存器
+            assert_eq!(stack_frame(&nes), (0x34, 0xC002));
+
+            // NMI 已被 BRK 消耗，不会再次进入
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, NMI_HANDLER + 1);
+        }
+    }
+
+    #[test]
+    fn nmi_hijack_irq_test() {
+        for mode in MODES {
+            let (mut nes, irq) = make_irq_nes(&[]);
+            nes.cpu_mut().set_mode(mode);
+            nes.bus_mut().registers_mut().set_i_flag(false);
+            assert_irq(&mut nes, &irq);
+            run_cycles(&mut nes, 4);
+            nes.cpu_mut().set_nmi_line(true);
+            run_cycles(&mut nes, 5);
+            assert!(nes.cpu().at_instruction_boundary());
+            assert_eq!(nes.bus().registers().pc, NMI_HANDLER);
+            assert_eq!(nes.bus().registers().sp, 0xFA);
+            assert_eq!(stack_frame(&nes), (0x20, 0xC001));
+        }
+    }
+
+    #[test]
+    fn nmi_after_brk_vector_test() {
+        for mode in MODES {
+            let mut nes = make_nes(&[0x00, 0xFF]);
+            nes.cpu_mut().set_mode(mode);
+            run_cycles(&mut nes, 4);
+            nes.cpu_mut().set_nmi_line(true);
+            run_cycles(&mut nes, 3);
+            // 已经取过 IRQ 向量，中断处理程序的第一条指令执行后才响应 NMI
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER);
+            run_instruction(&mut nes);
+            assert_eq!(nes.bus().registers().pc, IRQ_HANDLER + 1);
+            run_cycles(&mut nes, 7);
+            assert_eq!(nes.bus().registers().pc, NMI_HANDLER);
+            assert_eq!(nes.bus().registers().sp, 0xF7);
+            assert_eq!(stack_frame(&nes), (0x24, IRQ_HANDLER + 1));
+        }
+    }
+
+    /// 记录每一次总线访问的卡带，覆盖 $4020 以上的地址空间
+    struct LogMapper {
+        memory: Vec<u8>,
+        /// (是否为写入, 地址, 数据)
+        log: Arc<Mutex<Vec<(bool, u16, u8)>>>,
+    }
+
+    impl Mapper for LogMapper {
+        fn number(&self) -> u8 {
+            0
+        }
+        fn cpu_read(&self, address: u16) -> Result<u8> {
+            let data = self.memory[address as usize];
+            self.log.lock().unwrap().push((false, address, data));
+            Ok(data)
+        }
+        fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+            self.memory[address as usize] = data;
+            self.log.lock().unwrap().push((true, address, data));
+            Ok(())
+        }
+        fn ppu_read(&self, address: u16) -> Result<u8> {
+            Err(MemoryError::ReadMemory(address))
+        }
+        fn ppu_write(&mut self, address: u16, _data: u8) -> Result<()> {
+            Err(MemoryError::WriteMemory(address))
+        }
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Horizontal
+        }
+    }
+
+    /// 可以从外部控制 IRQ 输出的卡带
+    struct IrqMapper {
+        mapper: Box<dyn Mapper>,
+        line: Arc<AtomicBool>,
+    }
+
+    impl Mapper for IrqMapper {
+        fn number(&self) -> u8 {
+            self.mapper.number()
+        }
+        fn cpu_read(&self, address: u16) -> Result<u8> {
+            self.mapper.cpu_read(address)
+        }
+        fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+            self.mapper.cpu_write(address, data)
+        }
+        fn ppu_read(&self, address: u16) -> Result<u8> {
+            self.mapper.ppu_read(address)
+        }
+        fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+            self.mapper.ppu_write(address, data)
+        }
+        fn mirroring(&self) -> Mirroring {
+            self.mapper.mirroring()
+        }
+        fn irq(&self) -> bool {
+            self.line.load(Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn cycle_bus_access_test() {
+        let program = [
+            0xA2, 0x01, // LDX #$01
+            0xBD, 0xFF, 0x60, // LDA $60FF,X
+            0xFE, 0x00, 0x60, // INC $6000,X
+            0x9D, 0x00, 0x60, // STA $6000,X
+        ];
+        let mut memory = vec![0; 0x10000];
+        memory[0x8000..0x8000 + program.len()].copy_from_slice(&program);
+        memory[0xFFFC] = 0x00;
+        memory[0xFFFD] = 0x80;
+        memory[0x6000] = 0x11;
+        memory[0x6001] = 0x41;
+        memory[0x6100] = 0x99;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut nes = Nes::new(Box::new(LogMapper {
+            memory,
+            log: log.clone(),
+        }));
+        nes.cpu_mut().set_mode(CpuMode::Cycle);
+        nes.reset().unwrap();
+        run_cycles(&mut nes, 7);
+        log.lock().unwrap().clear();
+
+        for cycle in 1..=19 {
+            run_cycles(&mut nes, 1);
+            assert_eq!(log.lock().unwrap().len(), cycle);
+        }
+        assert!(nes.cpu().at_instruction_boundary());
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                (false, 0x8000, 0xA2),
+                (false, 0x8001, 0x01),
+                (false, 0x8002, 0xBD),
+                (false, 0x8003, 0xFF),
+                (false, 0x8004, 0x60),
+                // 跨页前先读取未进位的地址
+                (false, 0x6000, 0x11),
+                (false, 0x6100, 0x99),
+                (false, 0x8005, 0xFE),
+                (false, 0x8006, 0x00),
+                (false, 0x8007, 0x60),
+                (false, 0x6001, 0x41),
+                (false, 0x6001, 0x41),
+                // 读-改-写指令先写回原值
+                (true, 0x6001, 0x41),
+                (true, 0x6001, 0x42),
+                (false, 0x8008, 0x9D),
+                (false, 0x8009, 0x00),
+                (false, 0x800A, 0x60),
+                (false, 0x6001, 0x42),
+                (true, 0x6001, 0x99),
+            ]
+        );
+    }
+
+    #[test]
+    fn jam_test() {
+        let mut nes = make_nes(&[0xEA, 0x02]);
+        run_instruction(&mut nes);
+        run_instruction(&mut nes);
+        assert!(nes.cpu().is_jammed());
+        assert_eq!(nes.bus().registers().pc, 0xC001);
+
+        nes.cpu_mut().set_nmi_line(true);
+        run_cycles(&mut nes, 100);
+        assert_eq!(nes.bus().registers().pc, 0xC001);
+        assert_eq!(nes.bus().registers().sp, 0xFD);
+
+        nes.reset().unwrap();
+        assert!(!nes.cpu().is_jammed());
+        run_cycles(&mut nes, 7);
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().registers().pc, 0xC001);
+    }
+
+    #[test]
+    fn unofficial_immediate_test() {
+        let mut nes = make_nes(&[
+            0xA9, 0xC3, // LDA #$C3
+            0x0B, 0x81, // ANC #$81
+            0x4B, 0x03, // ALR #$03
+            0x38, // SEC
+            0xA9, 0xFF, // LDA #$FF
+            0x6B, 0xC0, // ARR #$C0
+            0xA2, 0x0F, // LDX #$0F
+            0xCB, 0x10, // AXS #$10
+            0xEB, 0x01, // SBC #$01
+        ]);
+        run_instruction(&mut nes);
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().registers().a, 0x81);
+        assert!(nes.bus().registers().has_c_flag());
+        assert!(nes.bus().registers().has_n_flag());
+
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().registers().a, 0x00);
+        assert!(nes.bus().registers().has_c_flag());
+        assert!(nes.bus().registers().has_z_flag());
+
+        run_instruction(&mut nes);
+        run_instruction(&mut nes);
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().registers().a, 0xE0);
+        assert!(nes.bus().registers().has_c_flag());
+        assert!(!nes.bus().registers().has_v_flag());
+
+        run_instruction(&mut nes);
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().registers().x, 0xF0);
+        assert!(!nes.bus().registers().has_c_flag());
+
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().registers().a, 0xDE);
+    }
+
+    #[test]
+    fn unofficial_store_test() {
+        let mut nes = make_nes(&[
+            0xA2, 0xF5, // LDX #$F5
+            0xA0, 0x01, // LDY #$01
+            0x9E, 0x00, 0x02, // SHX $0200,Y
+            0x9E, 0xFF, 0x02, // SHX $02FF,Y
+            0xA9, 0x0F, // LDA #$0F
+            0x9B, 0x00, 0x05, // TAS $0500,Y
+            0xBB, 0x00, 0x05, // LAS $0500,Y
+        ]);
+        run_instruction(&mut nes);
+        run_instruction(&mut nes);
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().cpu_peek(0x0201).unwrap(), 0x01);
+
+        // 跨页时高字节被替换为写入的值
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().cpu_peek(0x0300).unwrap(), 0x00);
+        assert_eq!(nes.bus().cpu_peek(0x0100).unwrap(), 0x01);
+
+        run_instruction(&mut nes);
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().registers().sp, 0x05);
+        assert_eq!(nes.bus().cpu_peek(0x0501).unwrap(), 0x04);
+
+        run_instruction(&mut nes);
+        assert_eq!(nes.bus().registers().a, 0x04);
+        assert_eq!(nes.bus().registers().x, 0x04);
+        assert_eq!(nes.bus().registers().sp, 0x04);
+    }
+
     fn check(capture: Captures, cycles: u32, registers: &CpuRegisters) -> bool {
         let result = capture["ADDR"] == format!("{:04X}", registers.pc)
             && capture["A"] == format!("{:02X}", registers.a)
@@ -3,6 +3,10 @@ use crate::memory::{Memory, Result};
 use super::{memory::CpuMemory, CpuRegisters};
 
 const ADDRESS_CPU_STACK_START: u16 = 0x0100;
+/// 栈指针对应的内存地址
+pub fn address(sp: u8) -> u16 {
+    ADDRESS_CPU_STACK_START + sp as u16
+}
 pub fn push(memory: &mut CpuMemory, registers: &mut CpuRegisters, data: u8) -> Result<()> {
     let sp = registers.sp as u16;
     memory.write(ADDRESS_CPU_STACK_START + sp, data)?;
@@ -0,0 +1,248 @@
+use super::{disassemble_at, AddressingMode, Bus, CpuError, DisassembledInstruction};
+use crate::memory::Result;
+use std::fmt::{Debug, Formatter};
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+/// 开始或停止跟踪的条件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceCondition {
+    /// 即将执行的指令地址落在范围内
+    Pc(RangeInclusive<u16>),
+    /// 到达指定的帧
+    Frame(u32),
+}
+
+impl TraceCondition {
+    fn matches(&self, pc: u16, frame: u32) -> bool {
+        match self {
+            TraceCondition::Pc(range) => range.contains(&pc),
+            TraceCondition::Frame(target) => frame >= *target,
+        }
+    }
+}
+
+/// 按 nestest.log 的格式逐条记录执行的指令
+pub struct Tracer {
+    writer: Box<dyn Write + Send>,
+    start: Option<TraceCondition>,
+    stop: Option<TraceCondition>,
+    started: bool,
+    stopped: bool,
+}
+
+impl Tracer {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            start: None,
+            stop: None,
+            started: false,
+            stopped: false,
+        }
+    }
+
+    /// 满足条件后才开始记录，默认立即开始
+    pub fn start_when(mut self, condition: TraceCondition) -> Self {
+        self.start = Some(condition);
+        self
+    }
+
+    /// 满足条件后停止记录，不再恢复
+    pub fn stop_when(mut self, condition: TraceCondition) -> Self {
+        self.stop = Some(condition);
+        self
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.started && !self.stopped
+    }
+
+    /// 在指令执行前调用，`cycles` 为已经执行的 CPU 周期数
+    pub(super) fn trace(&mut self, bus: &Bus, cycles: u32) -> std::result::Result<(), CpuError> {
+        if self.stopped {
+            return Ok(());
+        }
+        let pc = bus.registers().pc;
+        let frame = bus.ppu().frame_number();
+        if !self.started {
+            self.started = self.start.as_ref().is_none_or(|c| c.matches(pc, frame));
+            if !self.started {
+                return Ok(());
+            }
+        }
+        if matches!(&self.stop, Some(condition) if condition.matches(pc, frame)) {
+            self.stopped = true;
+            self.writer.flush()?;
+            return Ok(());
+        }
+        writeln!(self.writer, "{}", trace_line(bus, cycles)?)?;
+        Ok(())
+    }
+}
+
+impl Debug for Tracer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tracer")
+            .field("start", &self.start)
+            .field("stop", &self.stop)
+            .field("started", &self.started)
+            .field("stopped", &self.stopped)
+            .finish()
+    }
+}
+
+/// 生成 PC 处指令的一行跟踪记录，格式与 nestest.log 相同
+pub fn trace_line(bus: &Bus, cycles: u32) -> Result<String> {
+    let registers = bus.registers();
+    let instruction = disassemble_at(bus, registers.pc)?;
+    let bytes = instruction
+        .bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut text = instruction.mnemonic.to_string();
+    if !instruction.operand.is_empty() {
+        text.push(' ');
+        text.push_str(&instruction.operand);
+    }
+    text.push_str(&annotation(bus, &instruction)?);
+    let (scanline, dot) = (bus.ppu().scanline(), bus.ppu().dot());
+    Ok(format!(
+        "{:04X}  {:<9}{}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        instruction.address,
+        bytes,
+        if instruction.official { ' ' } else { '*' },
+        text,
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.p,
+        registers.sp,
+        scanline,
+        dot,
+        cycles
+    ))
+}
+
+/// 操作数之后附带的中间地址和内存值
+fn annotation(bus: &Bus, instruction: &DisassembledInstruction) -> Result<String> {
+    let address = match instruction.effective_address {
+        Some(address) => address,
+        None => return Ok(String::new()),
+    };
+    let registers = bus.registers();
+    let pointer = instruction.bytes.get(1).copied().unwrap_or_default();
+    Ok(match instruction.mode {
+        AddressingMode::Absolute if matches!(instruction.mnemonic, "JMP" | "JSR") => String::new(),
+        AddressingMode::ZeroPage | AddressingMode::Absolute => {
+            format!(" = {:02X}", peek(bus, address)?)
+        }
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+            format!(" @ {:02X} = {:02X}", address, peek(bus, address)?)
+        }
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+            format!(" @ {:04X} = {:02X}", address, peek(bus, address)?)
+        }
+        AddressingMode::Indirect => format!(" = {:04X}", address),
+        AddressingMode::IndirectX => format!(
+            " @ {:02X} = {:04X} = {:02X}",
+            pointer.wrapping_add(registers.x),
+            address,
+            peek(bus, address)?
+        ),
+        AddressingMode::IndirectY => format!(
+            " = {:04X} @ {:04X} = {:02X}",
+            address.wrapping_sub(registers.y as u16),
+            address,
+            peek(bus, address)?
+        ),
+        _ => String::new(),
+    })
+}
+
+/// 读取内存用于显示，IO 寄存器有读取副作用，显示为 `FF`
+fn peek(bus: &Bus, address: u16) -> Result<u8> {
+    match address {
+        0x2000..=0x401F => Ok(0xFF),
+        _ => bus.cpu_peek(address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TraceCondition, Tracer};
+    use crate::clock::Clock;
+    use crate::cpu::CpuMode;
+    use crate::nes::Nes;
+    use crate::rom::NesLoader;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// 测试结束后仍能读取内容的输出
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 运行 nestest 直到日志的最后一条指令执行完，返回跟踪输出
+    fn trace_nestest(mode: CpuMode, tracer: impl FnOnce(SharedWriter) -> Tracer) -> String {
+        let loader =
+            NesLoader::from_slice(&std::fs::read("test_data/nestest.nes").unwrap()).unwrap();
+        let mut nes = Nes::from_loader(&loader).unwrap();
+        nes.cpu_mut().set_mode(mode);
+        let writer = SharedWriter::default();
+        nes.cpu_mut().set_tracer(tracer(writer.clone()));
+        nes.reset().unwrap();
+        nes.bus_mut().registers_mut().pc = 0xC000;
+        while nes.cpu().cycles() < 26554 {
+            nes.clock().unwrap();
+        }
+        let output = writer.0.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn nestest_trace_test() {
+        let log = std::fs::read_to_string("test_data/nestest.log").unwrap();
+        for mode in [CpuMode::Instruction, CpuMode::Cycle] {
+            let trace = trace_nestest(mode, Tracer::new);
+            assert_eq!(trace.lines().count(), log.lines().count() - 1);
+            for (line, expected) in trace.lines().zip(log.lines()) {
+                assert_eq!(line, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn trace_condition_test() {
+        let log = std::fs::read_to_string("test_data/nestest.log").unwrap();
+        let trace = trace_nestest(CpuMode::Instruction, |writer| {
+            Tracer::new(writer)
+                .start_when(TraceCondition::Pc(0xC72D..=0xC72D))
+                .stop_when(TraceCondition::Pc(0xC735..=0xC736))
+        });
+        let start = log
+            .lines()
+            .position(|line| line.starts_with("C72D"))
+            .unwrap();
+        let expected = log.lines().skip(start).take(3).collect::<Vec<_>>();
+        assert_eq!(trace.lines().collect::<Vec<_>>(), expected);
+
+        // nestest 在第一帧内结束
+        let trace = trace_nestest(CpuMode::Instruction, |writer| {
+            Tracer::new(writer).start_when(TraceCondition::Frame(1))
+        });
+        assert!(trace.is_empty());
+    }
+}
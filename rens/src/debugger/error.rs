@@ -0,0 +1,10 @@
+use crate::cpu::CpuError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum GdbError {
+    #[error("Connection error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Cpu error: {0}")]
+    Cpu(#[from] CpuError),
+}
@@ -0,0 +1,419 @@
+use super::{Access, Debugger, GdbError, RunMode, StopEvent, Watchpoint};
+use crate::nes::Nes;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+/// 继续执行时每隔多少周期检查一次客户端的中断请求，约为一帧
+const CHUNK_CYCLES: u32 = 29781;
+/// 客户端请求中断执行
+const INTERRUPT: u8 = 0x03;
+
+/// GDB 远程串行协议服务器。
+///
+/// 寄存器按 A、X、Y、P、SP 各 1 字节，PC 2 字节小端排列
+#[derive(Debug)]
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+impl GdbServer {
+    pub fn bind(address: impl ToSocketAddrs) -> Result<Self, GdbError> {
+        Ok(Self {
+            listener: TcpListener::bind(address)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, GdbError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// 接受一个客户端，处理到客户端断开、detach 或 kill 为止
+    pub fn serve(&self, debugger: &mut Debugger, nes: &mut Nes) -> Result<(), GdbError> {
+        let (stream, _) = self.listener.accept()?;
+        let mut session = Session { stream };
+        while let Some(packet) = session.read_packet()? {
+            let reply = match packet {
+                Packet::Interrupt => "S02".to_string(),
+                Packet::Command(command) => match session.handle(&command, debugger, nes)? {
+                    Some(reply) => reply,
+                    None => {
+                        session.send("OK")?;
+                        break;
+                    }
+                },
+            };
+            session.send(&reply)?;
+        }
+        Ok(())
+    }
+}
+
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+struct Session {
+    stream: TcpStream,
+}
+
+impl Session {
+    /// 客户端断开时返回 `None`
+    fn read_byte(&mut self) -> Result<Option<u8>, GdbError> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn read_packet(&mut self) -> Result<Option<Packet>, GdbError> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(INTERRUPT) => return Ok(Some(Packet::Interrupt)),
+                Some(b'$') => {}
+                // 应答和其他噪声
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            if expected == Some(checksum_of(&data)) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(Packet::Command(
+                    String::from_utf8_lossy(&data).into_owned(),
+                )));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn send(&mut self, data: &str) -> Result<(), GdbError> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())?;
+        Ok(())
+    }
+
+    /// 返回应答，`None` 表示结束会话
+    fn handle(
+        &mut self,
+        command: &str,
+        debugger: &mut Debugger,
+        nes: &mut Nes,
+    ) -> Result<Option<String>, GdbError> {
+        let (kind, arguments) = command.split_at(command.len().min(1));
+        let reply = match kind {
+            "?" => "S05".to_string(),
+            "g" => {
+                let bus = nes.bus();
+                let registers = bus.registers();
+                let [low, high] = registers.pc.to_le_bytes();
+                to_hex(&[
+                    registers.a,
+                    registers.x,
+                    registers.y,
+                    registers.p,
+                    registers.sp,
+                    low,
+                    high,
+                ])
+            }
+            "G" => match from_hex(arguments) {
+                Some(bytes) if bytes.len() == 7 => {
+                    let registers = nes.bus_mut().registers_mut();
+                    registers.a = bytes[0];
+                    registers.x = bytes[1];
+                    registers.y = bytes[2];
+                    registers.p = bytes[3];
+                    registers.sp = bytes[4];
+                    registers.pc = u16::from_le_bytes([bytes[5], bytes[6]]);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "m" => match parse_range(arguments) {
+                Some((address, len)) => {
+                    let bus = nes.bus();
+                    let bytes = (0..len)
+                        .map(|offset| bus.cpu_peek(address.wrapping_add(offset)))
+                        .collect::<Result<Vec<_>, _>>();
+                    match bytes {
+                        Ok(bytes) => to_hex(&bytes),
+                        Err(_) => "E03".to_string(),
+                    }
+                }
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = arguments.split_once(':').and_then(|(range, data)| {
+                    let (address, len) = parse_range(range)?;
+                    let data = from_hex(data)?;
+                    (data.len() == len as usize).then_some((address, data))
+                });
+                match parsed {
+                    Some((address, data)) => {
+                        let bus = nes.bus_mut();
+                        let written = data.iter().enumerate().try_for_each(|(offset, byte)| {
+                            bus.cpu_write(address.wrapping_add(offset as u16), *byte)
+                        });
+                        match written {
+                            Ok(()) => "OK".to_string(),
+                            Err(_) => "E03".to_string(),
+                        }
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            "Z" | "z" => match parse_point(arguments) {
+                Some((point, address, len)) => {
+                    let insert = kind == "Z";
+                    let range = address..=address.wrapping_add(len.max(1) - 1);
+                    let watchpoint = match point {
+                        0 | 1 => None,
+                        2 => Some(Watchpoint::write(range)),
+                        3 => Some(Watchpoint::read(range)),
+                        _ => Some(Watchpoint::access(range)),
+                    };
+                    match (watchpoint, insert) {
+                        (None, true) => {
+                            debugger.add_breakpoint(address);
+                        }
+                        (None, false) => {
+                            debugger.remove_breakpoint(address);
+                        }
+                        (Some(watchpoint), true) => debugger.add_watchpoint(watchpoint),
+                        (Some(watchpoint), false) => {
+                            debugger.remove_watchpoint(&watchpoint);
+                        }
+                    }
+                    "OK".to_string()
+                }
+                None => String::new(),
+            },
+            "c" | "s" => {
+                if let Ok(address) = u16::from_str_radix(arguments, 16) {
+                    nes.bus_mut().registers_mut().pc = address;
+                }
+                let event = if kind == "s" {
+                    Some(debugger.run(nes, RunMode::StepInto, u32::MAX)?)
+                } else {
+                    self.resume(debugger, nes)?
+                };
+                match event {
+                    Some(event) => stop_reply(&event),
+                    None => "S02".to_string(),
+                }
+            }
+            "H" => "OK".to_string(),
+            "q" if arguments.starts_with("Supported") => "PacketSize=1000".to_string(),
+            "q" if arguments == "Attached" => "1".to_string(),
+            "D" | "k" => return Ok(None),
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    /// 继续执行，客户端请求中断时返回 `None`
+    fn resume(
+        &mut self,
+        debugger: &Debugger,
+        nes: &mut Nes,
+    ) -> Result<Option<StopEvent>, GdbError> {
+        loop {
+            let event = debugger.run(nes, RunMode::Continue, CHUNK_CYCLES)?;
+            if event != StopEvent::CycleLimit {
+                return Ok(Some(event));
+            }
+            self.stream.set_nonblocking(true)?;
+            let mut byte = [0];
+            let read = self.stream.read(&mut byte);
+            self.stream.set_nonblocking(false)?;
+            match read {
+                Ok(0) => return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into()),
+                Ok(_) if byte[0] == INTERRUPT => return Ok(None),
+                Ok(_) => {}
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+}
+
+fn stop_reply(event: &StopEvent) -> String {
+    match event {
+        StopEvent::Watchpoint { hit, .. } => {
+            let kind = match hit.access {
+                Access::Read => "rwatch",
+                Access::Write => "watch",
+            };
+            format!("T05{}:{:04x};", kind, hit.address)
+        }
+        StopEvent::Jammed => "S04".to_string(),
+        _ => "S05".to_string(),
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+/// 解析 `地址,长度`
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (address, len) = text.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        u16::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// 解析 `类型,地址,长度`
+fn parse_point(text: &str) -> Option<(u8, u16, u16)> {
+    let (point, range) = text.split_once(',')?;
+    let (address, len) = parse_range(range)?;
+    Some((point.parse().ok()?, address, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum_of, GdbServer, INTERRUPT};
+    use crate::cpu::assemble;
+    use crate::debugger::Debugger;
+    use crate::nes::Nes;
+    use crate::ppu::Mirroring;
+    use crate::rom::make_mapper;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::Duration;
+
+    const PROGRAM: &str = "
+        .org $C000
+        reset:  lda #$01
+                sta $0200
+        stop:   nop
+        loop:   jmp loop
+        .org $FFFC
+                .word reset
+    ";
+
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn read_byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+
+        fn send_raw(&mut self, packet: &str) -> u8 {
+            self.stream.write_all(packet.as_bytes()).unwrap();
+            self.read_byte()
+        }
+
+        fn send(&mut self, data: &str) {
+            let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+            assert_eq!(self.send_raw(&packet), b'+');
+        }
+
+        fn reply(&mut self) -> String {
+            assert_eq!(self.read_byte(), b'$');
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte() {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let checksum = [self.read_byte(), self.read_byte()];
+            let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16);
+            assert_eq!(checksum, Ok(checksum_of(&data)));
+            self.stream.write_all(b"+").unwrap();
+            String::from_utf8(data).unwrap()
+        }
+
+        fn command(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+    }
+
+    #[test]
+    fn loopback_test() {
+        let assembly = assemble(PROGRAM).unwrap();
+        let mut prg = vec![0; 0x4000];
+        assembly.write_to(&mut prg, 0xC000).unwrap();
+        let mut nes =
+            Nes::new(make_mapper(0, prg, vec![0; 0x2000], Mirroring::Horizontal).unwrap());
+        nes.reset().unwrap();
+
+        let server = GdbServer::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut client = Client {
+                stream: TcpStream::connect(address).unwrap(),
+            };
+            assert_eq!(client.command("qSupported:swbreak+"), "PacketSize=1000");
+            assert_eq!(client.command("?"), "S05");
+            assert_eq!(client.command("g"), "00000024fd00c0");
+            assert_eq!(client.command("mc000,5"), "a9018d0002");
+            assert_eq!(client.command("M0300,2:abcd"), "OK");
+            assert_eq!(client.command("m0300,2"), "abcd");
+            // 校验和错误
+            assert_eq!(client.send_raw("$?#00"), b'-');
+
+            assert_eq!(client.command("Z0,c005,1"), "OK");
+            assert_eq!(client.command("c"), "S05");
+            assert_eq!(client.command("g"), "01000024fd05c0");
+            assert_eq!(client.command("m0200,1"), "01");
+            assert_eq!(client.command("z0,c005,1"), "OK");
+            assert_eq!(client.command("s"), "S05");
+            assert_eq!(client.command("g"), "01000024fd06c0");
+
+            assert_eq!(client.command("G02000024fd02c0"), "OK");
+            assert_eq!(client.command("Z2,0200,1"), "OK");
+            assert_eq!(client.command("c"), "T05watch:0200;");
+            assert_eq!(client.command("m0200,1"), "02");
+            assert_eq!(client.command("z2,0200,1"), "OK");
+
+            client.send("c");
+            thread::sleep(Duration::from_millis(50));
+            client.stream.write_all(&[INTERRUPT]).unwrap();
+            assert_eq!(client.reply(), "S02");
+            assert_eq!(client.command("D"), "OK");
+        });
+
+        let mut debugger = Debugger::new();
+        let result = server.serve(&mut debugger, &mut nes);
+        client.join().unwrap();
+        result.unwrap();
+        assert_eq!(debugger.breakpoints().count(), 0);
+        assert!(debugger.watchpoints().is_empty());
+    }
+}
@@ -0,0 +1,449 @@
+mod error;
+mod gdb;
+
+pub use error::*;
+pub use gdb::*;
+
+use crate::clock::Clock;
+use crate::cpu::CpuError;
+use crate::nes::Nes;
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+const OPCODE_JSR: u8 = 0x20;
+const OPCODE_RTS: u8 = 0x60;
+const OPCODE_RTI: u8 = 0x40;
+
+/// 总线访问方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// 监视点对读写值的要求
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueCondition {
+    Equal(u8),
+    NotEqual(u8),
+    Range(RangeInclusive<u8>),
+}
+
+impl ValueCondition {
+    fn matches(&self, data: u8) -> bool {
+        match self {
+            ValueCondition::Equal(value) => data == *value,
+            ValueCondition::NotEqual(value) => data != *value,
+            ValueCondition::Range(range) => range.contains(&data),
+        }
+    }
+}
+
+/// 在 CPU 总线上读写某段地址时停止，包括栈操作，`CpuMode::Cycle` 下还包括虚读
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+    pub condition: Option<ValueCondition>,
+}
+
+impl Watchpoint {
+    pub fn read(range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            read: true,
+            write: false,
+            condition: None,
+        }
+    }
+
+    pub fn write(range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            read: false,
+            write: true,
+            condition: None,
+        }
+    }
+
+    /// 读写都会触发
+    pub fn access(range: RangeInclusive<u16>) -> Self {
+        Self {
+            range,
+            read: true,
+            write: true,
+            condition: None,
+        }
+    }
+
+    /// 只在读写的值满足条件时触发
+    pub fn when(mut self, condition: ValueCondition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    pub fn matches(&self, access: Access, address: u16, data: u8) -> bool {
+        let access = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+        access
+            && self.range.contains(&address)
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.matches(data))
+    }
+}
+
+/// 命中监视点的一次总线访问
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+    pub access: Access,
+    pub address: u16,
+    pub data: u8,
+}
+
+/// 执行方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RunMode {
+    /// 一直运行到断点或监视点
+    Continue,
+    /// 执行一条指令，进入子程序或中断
+    StepInto,
+    /// 执行一条指令，JSR 调用的子程序整体执行完
+    StepOver,
+    /// 运行到当前子程序或中断处理程序返回
+    StepOut,
+    /// 运行到 PPU 下一次进入指定扫描线
+    ToScanline(u32),
+}
+
+/// 停止执行的原因，停止时 CPU 总是处于指令边界
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopEvent {
+    /// 即将执行断点处的指令
+    Breakpoint(u16),
+    /// `pc` 处的指令访问了监视的地址，该指令已经执行完
+    Watchpoint { pc: u16, hit: WatchHit },
+    /// 单步、步过或步出完成
+    Step,
+    /// 到达指定扫描线
+    Scanline(u32),
+    /// CPU 执行 JAM 指令后停机
+    Jammed,
+    /// 执行的周期数达到上限
+    CycleLimit,
+}
+
+/// 断点、监视点和单步执行。
+///
+/// 监视点只在 `run` 期间安装到总线上
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 已存在时返回 `false`
+    pub fn add_breakpoint(&mut self, pc: u16) -> bool {
+        self.breakpoints.insert(pc)
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: u16) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// 按 `mode` 执行，最多执行约 `max_cycles` 个周期。
+    ///
+    /// 开始时 PC 处的断点不会立即触发，以便从断点继续执行
+    pub fn run(
+        &self,
+        nes: &mut Nes,
+        mode: RunMode,
+        max_cycles: u32,
+    ) -> Result<StopEvent, CpuError> {
+        nes.bus_mut().set_watchpoints(self.watchpoints.clone());
+        let result = self.run_instructions(nes, mode, max_cycles);
+        nes.bus_mut().set_watchpoints(Vec::new());
+        result
+    }
+
+    fn run_instructions(
+        &self,
+        nes: &mut Nes,
+        mode: RunMode,
+        max_cycles: u32,
+    ) -> Result<StopEvent, CpuError> {
+        let start_cycles = nes.cpu().cycles();
+        while !nes.cpu().at_instruction_boundary() {
+            nes.clock()?;
+        }
+        let (pc, sp) = {
+            let bus = nes.bus();
+            (bus.registers().pc, bus.registers().sp)
+        };
+        // 步过 JSR 时的返回地址
+        let return_address = match nes.bus().cpu_peek(pc)? {
+            OPCODE_JSR => Some(pc.wrapping_add(3)),
+            _ => None,
+        };
+        let scanline = |nes: &Nes| nes.bus().ppu().scanline() as u32;
+        let mut last_scanline = scanline(nes);
+        let mut returned = false;
+        let mut first = true;
+        loop {
+            let (current_pc, current_sp) = {
+                let bus = nes.bus();
+                (bus.registers().pc, bus.registers().sp)
+            };
+            if !first {
+                if self.breakpoints.contains(&current_pc) {
+                    return Ok(StopEvent::Breakpoint(current_pc));
+                }
+                let stop = match mode {
+                    RunMode::Continue => None,
+                    RunMode::StepInto => Some(StopEvent::Step),
+                    RunMode::StepOver => match return_address {
+                        Some(address) if current_pc != address || current_sp < sp => None,
+                        _ => Some(StopEvent::Step),
+                    },
+                    RunMode::StepOut if returned && current_sp > sp => Some(StopEvent::Step),
+                    RunMode::StepOut => None,
+                    RunMode::ToScanline(target) => {
+                        let current = scanline(nes);
+                        let reached = current == target && last_scanline != target;
+                        last_scanline = current;
+                        reached.then_some(StopEvent::Scanline(target))
+                    }
+                };
+                if let Some(stop) = stop {
+                    return Ok(stop);
+                }
+            }
+            if nes.cpu().is_jammed() {
+                return Ok(StopEvent::Jammed);
+            }
+            if nes.cpu().cycles().wrapping_sub(start_cycles) >= max_cycles {
+                return Ok(StopEvent::CycleLimit);
+            }
+            first = false;
+            returned = matches!(nes.bus().cpu_peek(current_pc)?, OPCODE_RTS | OPCODE_RTI);
+            nes.clock()?;
+            while !nes.cpu().at_instruction_boundary() {
+                nes.clock()?;
+            }
+            if let Some(hit) = nes.bus().take_watch_hit() {
+                return Ok(StopEvent::Watchpoint {
+                    pc: current_pc,
+                    hit,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, Debugger, RunMode, StopEvent, ValueCondition, WatchHit, Watchpoint};
+    use crate::cpu::{assemble, Assembly, CpuMode};
+    use crate::nes::Nes;
+    use crate::ppu::Mirroring;
+    use crate::rom::make_mapper;
+
+    const PROGRAM: &str = "
+        .org $C000
+        reset:  lda #$01
+                jsr sub
+        after:  sta $0200
+                pha
+                ldy #$01
+        load:   lda ($10),y
+        loop:   inc $0201
+                jmp loop
+        sub:    lda #$02
+        call:   jsr inner
+        return: rts
+        inner:  nop
+                rts
+        .org $FFFC
+                .word reset
+    ";
+
+    fn make_nes(source: &str) -> (Nes, Assembly) {
+        let assembly = assemble(source).unwrap();
+        let mut prg = vec![0; 0x4000];
+        assembly.write_to(&mut prg, 0xC000).unwrap();
+        let mut nes =
+            Nes::new(make_mapper(0, prg, vec![0; 0x2000], Mirroring::Horizontal).unwrap());
+        nes.reset().unwrap();
+        (nes, assembly)
+    }
+
+    fn pc(nes: &Nes) -> u16 {
+        nes.bus().registers().pc
+    }
+
+    #[test]
+    fn step_test() {
+        let (mut nes, assembly) = make_nes(PROGRAM);
+        let label = |name| assembly.label(name).unwrap();
+        let debugger = Debugger::new();
+        let mut step = |mode| {
+            let event = debugger.run(&mut nes, mode, u32::MAX).unwrap();
+            (event, pc(&nes))
+        };
+
+        assert_eq!(step(RunMode::StepInto), (StopEvent::Step, 0xC002));
+        assert_eq!(step(RunMode::StepInto), (StopEvent::Step, label("sub")));
+        assert_eq!(step(RunMode::StepOver), (StopEvent::Step, label("call")));
+        assert_eq!(step(RunMode::StepInto), (StopEvent::Step, label("inner")));
+        assert_eq!(step(RunMode::StepOut), (StopEvent::Step, label("return")));
+        assert_eq!(step(RunMode::StepOut), (StopEvent::Step, label("after")));
+
+        let (mut nes, _) = make_nes(PROGRAM);
+        let debugger = Debugger::new();
+        debugger.run(&mut nes, RunMode::StepInto, u32::MAX).unwrap();
+        // 步过整个子程序
+        assert_eq!(
+            debugger.run(&mut nes, RunMode::StepOver, u32::MAX).unwrap(),
+            StopEvent::Step
+        );
+        assert_eq!(pc(&nes), label("after"));
+        assert_eq!(nes.bus().registers().a, 0x02);
+    }
+
+    #[test]
+    fn breakpoint_test() {
+        let (mut nes, assembly) = make_nes(PROGRAM);
+        let mut debugger = Debugger::new();
+        let after = assembly.label("after").unwrap();
+        let lp = assembly.label("loop").unwrap();
+        assert!(debugger.add_breakpoint(after));
+        assert!(debugger.add_breakpoint(lp));
+        assert!(!debugger.add_breakpoint(lp));
+        assert_eq!(debugger.breakpoints().collect::<Vec<_>>(), [after, lp]);
+
+        let mut resume = || debugger.run(&mut nes, RunMode::Continue, u32::MAX).unwrap();
+        assert_eq!(resume(), StopEvent::Breakpoint(after));
+        assert_eq!(resume(), StopEvent::Breakpoint(lp));
+        // 从断点继续执行时不会立即停止
+        assert_eq!(resume(), StopEvent::Breakpoint(lp));
+        assert_eq!(nes.bus().cpu_peek(0x0201).unwrap(), 1);
+
+        assert!(debugger.remove_breakpoint(lp));
+        assert_eq!(
+            debugger.run(&mut nes, RunMode::Continue, 100).unwrap(),
+            StopEvent::CycleLimit
+        );
+    }
+
+    #[test]
+    fn watchpoint_test() {
+        for mode in [CpuMode::Instruction, CpuMode::Cycle] {
+            let (mut nes, assembly) = make_nes(PROGRAM);
+            nes.cpu_mut().set_mode(mode);
+            let label = |name| assembly.label(name).unwrap();
+            let mut debugger = Debugger::new();
+
+            // 栈操作
+            debugger.add_watchpoint(Watchpoint::write(0x0100..=0x01FF));
+            assert_eq!(
+                debugger.run(&mut nes, RunMode::Continue, u32::MAX).unwrap(),
+                StopEvent::Watchpoint {
+                    pc: 0xC002,
+                    hit: WatchHit {
+                        access: Access::Write,
+                        address: 0x01FD,
+                        data: 0xC0
+                    }
+                }
+            );
+            assert_eq!(pc(&nes), label("sub"));
+            assert!(debugger.remove_watchpoint(&Watchpoint::write(0x0100..=0x01FF)));
+
+            // 间接变址寻址
+            nes.bus_mut().cpu_write(0x0010, 0xFF).unwrap();
+            nes.bus_mut().cpu_write(0x0011, 0x02).unwrap();
+            debugger.add_watchpoint(Watchpoint::read(0x0300..=0x03FF));
+            let event = debugger.run(&mut nes, RunMode::Continue, u32::MAX);
+            assert_eq!(
+                event.unwrap(),
+                StopEvent::Watchpoint {
+                    pc: label("load"),
+                    hit: WatchHit {
+                        access: Access::Read,
+                        address: 0x0300,
+                        data: 0x00
+                    }
+                }
+            );
+
+            // 带值条件
+            debugger
+                .add_watchpoint(Watchpoint::access(0x0201..=0x0201).when(ValueCondition::Equal(3)));
+            assert_eq!(
+                debugger.run(&mut nes, RunMode::Continue, u32::MAX).unwrap(),
+                StopEvent::Watchpoint {
+                    pc: label("loop"),
+                    hit: WatchHit {
+                        access: Access::Write,
+                        address: 0x0201,
+                        data: 3
+                    }
+                }
+            );
+            assert_eq!(debugger.watchpoints().len(), 2);
+        }
+    }
+
+    #[test]
+    fn scanline_test() {
+        let (mut nes, _) = make_nes(PROGRAM);
+        let debugger = Debugger::new();
+        assert_eq!(
+            debugger
+                .run(&mut nes, RunMode::ToScanline(2), u32::MAX)
+                .unwrap(),
+            StopEvent::Scanline(2)
+        );
+        assert!(nes.cpu().at_instruction_boundary());
+        assert_eq!(nes.bus().ppu().scanline(), 2);
+        assert!(nes.bus().ppu().dot() < 21);
+    }
+
+    #[test]
+    fn jammed_test() {
+        let (mut nes, _) = make_nes(".org $C000\nreset: nop\njam\n.org $FFFC\n.word reset");
+        let debugger = Debugger::new();
+        assert_eq!(
+            debugger.run(&mut nes, RunMode::Continue, u32::MAX).unwrap(),
+            StopEvent::Jammed
+        );
+        assert_eq!(pc(&nes), 0xC001);
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt::Debug;
+
+/// 控制器端口，$4016 读取 1 号端口，$4017 读取 2 号端口
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Port {
+    One,
+    Two,
+}
+
+/// 标准手柄上 8 个按键是否按下
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ButtonState {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl ButtonState {
+    /// 按读出的顺序排列，A 在最低位
+    pub fn bits(&self) -> u8 {
+        [
+            self.a,
+            self.b,
+            self.select,
+            self.start,
+            self.up,
+            self.down,
+            self.left,
+            self.right,
+        ]
+        .iter()
+        .rev()
+        .fold(0, |bits, pressed| bits << 1 | *pressed as u8)
+    }
+}
+
+/// 接在控制器端口上的外设
+pub trait InputDevice: Debug + Send {
+    /// 写入 $4016 的低 3 位，第 0 位是选通信号
+    fn write(&mut self, data: u8);
+    /// 读取端口，只有低 5 位由外设驱动
+    fn read(&mut self) -> u8;
+    /// 读取端口但不移位，供调试工具使用
+    fn peek(&self) -> u8;
+    /// 更新按键，不是手柄的外设可以忽略
+    fn set_buttons(&mut self, _buttons: ButtonState) {}
+}
+
+/// 标准手柄：选通时锁存按键，之后每次读取移出一位，8 位之后一直读到 1
+#[derive(Debug, Default, Clone)]
+pub struct Joypad {
+    buttons: ButtonState,
+    shift: u8,
+    strobe: bool,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl InputDevice for Joypad {
+    fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.buttons.bits();
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        let data = self.peek();
+        if !self.strobe {
+            self.shift = self.shift >> 1 | 0x80;
+        }
+        data
+    }
+
+    fn peek(&self) -> u8 {
+        // 选通期间一直读到 A 键的当前状态
+        match self.strobe {
+            true => self.buttons.a as u8,
+            false => self.shift & 0x01,
+        }
+    }
+
+    fn set_buttons(&mut self, buttons: ButtonState) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift = buttons.bits();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ButtonState, InputDevice, Joypad};
+
+    #[test]
+    fn joypad_test() {
+        let mut joypad = Joypad::new();
+        joypad.set_buttons(ButtonState {
+            a: true,
+            start: true,
+            left: true,
+            ..Default::default()
+        });
+        // 未选通之前移位寄存器为空
+        assert_eq!(joypad.read(), 0);
+        joypad.write(1);
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+        joypad.write(0);
+        let bits = (0..10).map(|_| joypad.read()).collect::<Vec<_>>();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 1, 0, 1, 1]);
+
+        // 选通结束后按键的变化要等下次选通才能读到
+        joypad.write(1);
+        joypad.write(0);
+        joypad.set_buttons(ButtonState::default());
+        assert_eq!(joypad.peek(), 1);
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 0);
+    }
+}
@@ -1,7 +1,12 @@
+pub mod apu;
 mod bus;
 pub mod clock;
 pub mod cpu;
+pub mod debugger;
+pub mod input;
 pub mod memory;
+pub mod nes;
 pub mod ppu;
 pub mod register;
 pub mod rom;
+pub mod wav;
@@ -7,6 +7,9 @@ pub enum MemoryError {
     WriteMemory(u16),
     #[error("Address out of range: {0:#010X}")]
     AddressOutOfRange(u16),
+    /// 卡带没有在这个地址上驱动数据总线
+    #[error("Nothing mapped in {0:#010X}")]
+    Unmapped(u16),
 }
 
 pub type Result<T> = std::result::Result<T, MemoryError>;
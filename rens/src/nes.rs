@@ -0,0 +1,424 @@
+use crate::apu::Resampler;
+use crate::clock::Clock;
+use crate::cpu::{Bus, Cpu, CpuError, IrqSource};
+use crate::input::{ButtonState, Port};
+use crate::memory::Result;
+use crate::rom::{make_mapper_with_board, Mapper, NesError, NesLoader};
+
+/// 整台主机，拥有 CPU 和总线（内存、卡带），可以在线程间移动
+#[derive(Debug)]
+pub struct Nes {
+    cpu: Cpu,
+    bus: Bus,
+    audio: Resampler,
+}
+
+impl Nes {
+    const DOTS_PER_CYCLE: u32 = 3;
+    /// NTSC 主机的 CPU 频率
+    pub const CLOCK_RATE: f64 = 39_375_000.0 / 22.0;
+    pub fn new(mapper: Box<dyn Mapper>) -> Self {
+        Self {
+            cpu: Cpu::new(),
+            bus: Bus::new(mapper),
+            audio: Resampler::new(Self::CLOCK_RATE, Resampler::DEFAULT_SAMPLE_RATE),
+        }
+    }
+    pub fn from_loader(loader: &NesLoader) -> std::result::Result<Self, NesError> {
+        let header = loader.header();
+        let number = header.mapper_number();
+        let mapper = make_mapper_with_board(
+            number,
+            header.board(),
+            loader.prg().to_vec(),
+            loader.chr().to_vec(),
+            header.mirroring(),
+        )
+        .ok_or(NesError::UnsupportedMapper(number))?;
+        Ok(Self::new(mapper))
+    }
+    pub fn reset(&mut self) -> Result<()> {
+        self.bus.apu_mut().reset();
+        self.cpu.reset(&mut self.bus)
+    }
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+    /// 更新接在 `port` 上的手柄的按键
+    pub fn set_buttons(&mut self, port: Port, buttons: ButtonState) {
+        self.bus.set_buttons(port, buttons);
+    }
+    /// 运行到 PPU 开始下一帧，完成的画面见 `Ppu::frame`
+    pub fn run_frame(&mut self) -> std::result::Result<(), CpuError> {
+        let frame_number = self.bus.ppu().frame_number();
+        while self.bus.ppu().frame_number() == frame_number {
+            self.clock()?;
+        }
+        Ok(())
+    }
+    /// 音频输出，可以设置采样率、立体声和速率调整
+    pub fn audio(&self) -> &Resampler {
+        &self.audio
+    }
+    pub fn audio_mut(&mut self) -> &mut Resampler {
+        &mut self.audio
+    }
+    /// 取出已经生成的音频采样，返回写入的值的个数
+    pub fn take_audio(&mut self, buffer: &mut [f32]) -> usize {
+        self.audio.take(buffer)
+    }
+    /// 同时借用 CPU 和总线
+    pub fn split_mut(&mut self) -> (&mut Cpu, &mut Bus) {
+        (&mut self.cpu, &mut self.bus)
+    }
+}
+
+/// 运行 blargg 格式的测试 ROM，返回 $6000 上的结果（0 表示通过）和 $6004 开始的文字
+#[cfg(test)]
+pub(crate) fn run_blargg(path: &str) -> (u8, String) {
+    /// 最多运行约 20 秒
+    const MAX_FRAMES: u32 = 1200;
+    let rom = std::fs::read(path).unwrap();
+    let mut nes = Nes::from_loader(&NesLoader::from_slice(&rom).unwrap()).unwrap();
+    nes.reset().unwrap();
+    for _ in 0..MAX_FRAMES {
+        nes.run_frame().unwrap();
+        let bus = nes.bus();
+        let signature = [0x6001, 0x6002, 0x6003].map(|a| bus.cpu_peek(a).unwrap());
+        if signature != [0xDE, 0xB0, 0x61] {
+            continue;
+        }
+        match bus.cpu_peek(0x6000).unwrap() {
+            // 仍在运行
+            0x80 => {}
+            // 需要按复位键
+            0x81 => nes.reset().unwrap(),
+            result => {
+                let text = (0x6004..0x7000)
+                    .map(|address| bus.cpu_peek(address).unwrap())
+                    .take_while(|&byte| byte != 0)
+                    .map(char::from)
+                    .collect();
+                return (result, text);
+            }
+        }
+    }
+    panic!("{path} 没有结束");
+}
+
+/// 运行 2005 年格式的 blargg 测试 ROM `frames` 帧，返回写在 $F8 上的结果代码（1 表示通过）。
+///
+/// 这些 ROM 没有 $6000 的状态协议，只能运行足够长的时间后读取结果
+#[cfg(test)]
+pub(crate) fn run_blargg_2005(path: &str, frames: u32) -> u8 {
+    let rom = std::fs::read(path).unwrap();
+    let mut nes = Nes::from_loader(&NesLoader::from_slice(&rom).unwrap()).unwrap();
+    nes.reset().unwrap();
+    for _ in 0..frames {
+        nes.run_frame().unwrap();
+    }
+    nes.bus().cpu_peek(0x00F8).unwrap()
+}
+
+impl Clock for Nes {
+    type Error = CpuError;
+    /// 执行一个 CPU 周期，APU 和卡带同时执行一个周期，PPU 执行 3 个点
+    fn clock(&mut self) -> std::result::Result<(), CpuError> {
+        self.cpu.clock(&mut self.bus)?;
+        self.bus.clock_apu();
+        self.bus.clock_mapper();
+        self.audio.clock(self.bus.apu().output());
+        for _ in 0..Self::DOTS_PER_CYCLE {
+            self.bus.clock_ppu()?;
+        }
+        self.cpu.set_nmi_line(self.bus.ppu().nmi_line());
+        let apu = self.bus.apu();
+        self.cpu
+            .set_irq_line(IrqSource::FrameCounter, apu.frame_irq());
+        self.cpu.set_irq_line(IrqSource::Dmc, apu.dmc_irq());
+        self.cpu
+            .set_irq_line(IrqSource::Mapper, self.bus.mapper_irq());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Nes;
+    use crate::clock::Clock;
+    use crate::input::{ButtonState, Port};
+    use crate::memory::Result;
+    use crate::ppu::Mirroring;
+    use crate::rom::{make_mapper, Mapper, NesLoader};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[test]
+    fn parallel_test() {
+        let rom = std::fs::read("test_data/nestest.nes").unwrap();
+        let loader = NesLoader::from_slice(&rom).unwrap();
+        let handles = (0..8)
+            .map(|_| {
+                let mut nes = Nes::from_loader(&loader).unwrap();
+                thread::spawn(move || {
+                    nes.reset().unwrap();
+                    nes.bus_mut().registers_mut().pc = 0xC000;
+                    // nestest 自动模式在 $C66E 结束
+                    while nes.bus().registers().pc != 0xC66E {
+                        nes.clock().unwrap();
+                    }
+                    (
+                        nes.bus().cpu_peek(0x02).unwrap(),
+                        nes.bus().cpu_peek(0x03).unwrap(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), (0, 0));
+        }
+    }
+
+    #[test]
+    fn ppu_register_test() {
+        let mut nes = Nes::new(
+            make_mapper(0, vec![0; 0x4000], vec![0; 0x2000], Mirroring::Horizontal).unwrap(),
+        );
+        let bus = nes.bus_mut();
+        // 寄存器每 8 字节镜像到 $3FFF
+        bus.cpu_write(0x3FFE, 0x3F).unwrap();
+        bus.cpu_write(0x200E, 0x10).unwrap();
+        bus.cpu_write(0x2FFF, 0x0F).unwrap();
+        assert_eq!(bus.ppu().registers().v, 0x3F11);
+        // $3F10 是 $3F00 的镜像
+        assert_eq!(bus.ppu_read(0x3F00).unwrap(), 0x0F);
+
+        // CHR ROM 不能写入
+        bus.cpu_write(0x2006, 0x00).unwrap();
+        bus.cpu_write(0x2006, 0x10).unwrap();
+        bus.cpu_write(0x2007, 0x5A).unwrap();
+        assert_eq!(bus.ppu_read(0x0010).unwrap(), 0x00);
+
+        bus.ppu_mut().registers_mut().status = 0x80;
+        bus.cpu_write(0x2000, 0x80).unwrap();
+        assert!(bus.ppu().nmi_line());
+        // 调试读取没有副作用
+        assert_eq!(bus.cpu_peek(0x2002).unwrap(), 0x80);
+        assert_eq!(bus.cpu_peek(0x2002).unwrap(), 0x80);
+        assert_eq!(bus.cpu_read(0x300A).unwrap(), 0x80);
+        assert_eq!(bus.cpu_read(0x2002).unwrap(), 0x00);
+        assert!(!bus.ppu().nmi_line());
+
+        // 没有 CHR ROM 的 NROM 使用 8K CHR RAM
+        let mut nes =
+            Nes::new(make_mapper(0, vec![0; 0x4000], Vec::new(), Mirroring::Horizontal).unwrap());
+        let bus = nes.bus_mut();
+        bus.cpu_write(0x2006, 0x1F).unwrap();
+        bus.cpu_write(0x2006, 0xFF).unwrap();
+        bus.cpu_write(0x2007, 0x5A).unwrap();
+        assert_eq!(bus.ppu_read(0x1FFF).unwrap(), 0x5A);
+    }
+
+    /// 只有 16K PRG 和 8K CHR 的 NROM 映像
+    fn make_rom(flags6: u8) -> Vec<u8> {
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, flags6];
+        rom.resize(NesLoader::HEADER_SIZE + 0x4000 + 0x2000, 0);
+        rom
+    }
+
+    #[test]
+    fn mirroring_test() {
+        for (flags6, mirrors) in [(0x00, [0x2400, 0x2C00]), (0x01, [0x2800, 0x2C00])] {
+            let loader = NesLoader::from_slice(&make_rom(flags6)).unwrap();
+            let mut nes = Nes::from_loader(&loader).unwrap();
+            let bus = nes.bus_mut();
+            bus.ppu_write(0x2000, 0x11).unwrap();
+            bus.ppu_write(0x2C00, 0x22).unwrap();
+            assert_eq!(bus.ppu_read(mirrors[0]).unwrap(), 0x11);
+            assert_eq!(bus.ppu_read(mirrors[1]).unwrap(), 0x22);
+        }
+
+        // 四屏模式的后两个名称表在卡带上
+        let loader = NesLoader::from_slice(&make_rom(0x08)).unwrap();
+        let mut nes = Nes::from_loader(&loader).unwrap();
+        let bus = nes.bus_mut();
+        for (index, address) in [0x2000, 0x2400, 0x2800, 0x2C00].into_iter().enumerate() {
+            bus.cpu_write(0x2006, (address >> 8) as u8).unwrap();
+            bus.cpu_write(0x2006, address as u8).unwrap();
+            bus.cpu_write(0x2007, index as u8 + 1).unwrap();
+        }
+        let tables = [0x2000, 0x2400, 0x2800, 0x3C00].map(|address| bus.ppu_read(address).unwrap());
+        assert_eq!(tables, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn input_test() {
+        // LDA #1; STA $4016; LSR A; STA $4016; LDA $4016; STA $00; LDA $4016; STA $01; LDA $4017; STA $02
+        let program = [
+            0xA9, 0x01, 0x8D, 0x16, 0x40, 0x4A, 0x8D, 0x16, 0x40, 0xAD, 0x16, 0x40, 0x85, 0x00,
+            0xAD, 0x16, 0x40, 0x85, 0x01, 0xAD, 0x17, 0x40, 0x85, 0x02,
+        ];
+        let mut prg = vec![0xEA; 0x4000];
+        prg[..program.len()].copy_from_slice(&program);
+        prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        let mut nes =
+            Nes::new(make_mapper(0, prg, vec![0; 0x2000], Mirroring::Horizontal).unwrap());
+        nes.set_buttons(
+            Port::One,
+            ButtonState {
+                a: true,
+                ..Default::default()
+            },
+        );
+        nes.set_buttons(
+            Port::Two,
+            ButtonState {
+                start: true,
+                ..Default::default()
+            },
+        );
+        nes.reset().unwrap();
+        while nes.bus().registers().pc < 0xC000 + program.len() as u16 {
+            nes.clock().unwrap();
+        }
+        // 高 3 位是操作数的高字节 $40
+        let bus = nes.bus();
+        assert_eq!(bus.cpu_peek(0x00).unwrap(), 0x41);
+        assert_eq!(bus.cpu_peek(0x01).unwrap(), 0x40);
+        assert_eq!(bus.cpu_peek(0x02).unwrap(), 0x40);
+        assert_eq!(bus.input_device(Port::Two).peek(), 0x00);
+    }
+
+    #[test]
+    fn run_frame_test() {
+        let rom = std::fs::read("test_data/nestest.nes").unwrap();
+        let mut nes = Nes::from_loader(&NesLoader::from_slice(&rom).unwrap()).unwrap();
+        nes.reset().unwrap();
+        nes.run_frame().unwrap();
+        // 关闭渲染时每帧 341 * 262 个点
+        assert_eq!(nes.bus().ppu().frame_number(), 1);
+        assert_eq!(nes.cpu().cycles(), 29781);
+        assert_eq!(nes.bus().ppu().frame().len(), 256 * 240);
+    }
+
+    #[test]
+    fn audio_test() {
+        // LDA #$0F; STA $4015; LDA #$BF; STA $4000; LDA #$40; STA $4002; STA $4003; JMP $C00F
+        let program = [
+            0xA9, 0x0F, 0x8D, 0x15, 0x40, 0xA9, 0xBF, 0x8D, 0x00, 0x40, 0xA9, 0x40, 0x8D, 0x02,
+            0x40, 0x8D, 0x03, 0x40, 0x4C, 0x12, 0xC0,
+        ];
+        let mut prg = vec![0xEA; 0x4000];
+        prg[..program.len()].copy_from_slice(&program);
+        prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        let mut nes =
+            Nes::new(make_mapper(0, prg, vec![0; 0x2000], Mirroring::Horizontal).unwrap());
+        nes.audio_mut().set_sample_rate(48000);
+        nes.audio_mut().set_stereo(true);
+        nes.reset().unwrap();
+        nes.run_frame().unwrap();
+        // 一帧约 1/60 秒
+        let mut buffer = vec![0.0; 4096];
+        let count = nes.take_audio(&mut buffer);
+        assert_eq!(count, 2 * 798);
+        assert!(buffer[..count].iter().any(|sample| sample.abs() > 0.05));
+        assert_eq!(nes.take_audio(&mut buffer), 0);
+    }
+
+    /// 统计钩子调用次数的卡带，后两个名称表由卡带提供
+    struct HookMapper {
+        mapper: Box<dyn Mapper>,
+        counts: Arc<Mutex<[u32; 2]>>,
+        name_table: [u8; 0x800],
+    }
+
+    impl Mapper for HookMapper {
+        fn number(&self) -> u8 {
+            0
+        }
+        fn cpu_read(&self, address: u16) -> Result<u8> {
+            self.mapper.cpu_read(address)
+        }
+        fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+            self.mapper.cpu_write(address, data)
+        }
+        fn ppu_read(&self, address: u16) -> Result<u8> {
+            match address {
+                0x2000..=0x3EFF => Ok(self.name_table[address as usize & 0x07FF]),
+                _ => self.mapper.ppu_read(address),
+            }
+        }
+        fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+            match address {
+                0x2000..=0x3EFF => {
+                    self.name_table[address as usize & 0x07FF] = data;
+                    Ok(())
+                }
+                _ => self.mapper.ppu_write(address, data),
+            }
+        }
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Vertical
+        }
+        fn map_name_table(&self, address: u16) -> Option<u16> {
+            match address & 0x0800 {
+                0 => Some(address & 0x07FF),
+                _ => None,
+            }
+        }
+        fn ppu_a12_rise(&mut self) {
+            self.counts.lock().unwrap()[0] += 1;
+        }
+        fn scanline(&mut self) {
+            self.counts.lock().unwrap()[1] += 1;
+        }
+    }
+
+    #[test]
+    fn mapper_hook_test() {
+        // JMP $C000
+        let mut prg = vec![0xEA; 0x4000];
+        prg[..3].copy_from_slice(&[0x4C, 0x00, 0xC0]);
+        prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        let counts = Arc::new(Mutex::new([0; 2]));
+        let mapper = HookMapper {
+            mapper: make_mapper(0, prg, vec![0; 0x2000], Mirroring::Horizontal).unwrap(),
+            counts: counts.clone(),
+            name_table: [0; 0x800],
+        };
+        let mut nes = Nes::new(Box::new(mapper));
+        let bus = nes.bus_mut();
+        bus.ppu_write(0x2000, 0x11).unwrap();
+        bus.ppu_write(0x2800, 0x22).unwrap();
+        // 按垂直镜像 $2800 本应是 $2000 的镜像
+        assert_eq!(bus.ppu_read(0x2000).unwrap(), 0x11);
+        assert_eq!(bus.ppu_read(0x3800).unwrap(), 0x22);
+        assert_eq!(bus.ppu_read(0x0000).unwrap(), 0x00);
+
+        // 背景使用 $0000，精灵使用 $1000，每条渲染的扫描线只有一个有效的上升沿
+        bus.cpu_write(0x2000, 0x08).unwrap();
+        bus.cpu_write(0x2001, 0x18).unwrap();
+        nes.reset().unwrap();
+        nes.run_frame().unwrap();
+        *counts.lock().unwrap() = [0; 2];
+        nes.run_frame().unwrap();
+        assert_eq!(*counts.lock().unwrap(), [241, 262]);
+
+        // 背景使用 $1000 时，读取名称表造成的短暂低电平被过滤掉；
+        // 垂直消隐期间 A12 一直为低，预渲染扫描线第一次读取背景图案时多一个上升沿
+        nes.bus_mut().cpu_write(0x2000, 0x10).unwrap();
+        nes.run_frame().unwrap();
+        *counts.lock().unwrap() = [0; 2];
+        nes.run_frame().unwrap();
+        assert_eq!(*counts.lock().unwrap(), [242, 262]);
+    }
+}
@@ -1,18 +1,44 @@
 use crate::memory::{Memory, MemoryError, Result};
 
+/// 主机内的名称表内存（CIRAM）和调色板，名称表的映射方式由卡带决定
 pub struct PpuMemory {
-    memory: Box<[u8; Self::SIZE_PPU_MEMORY]>,
+    /// 2K 名称表内存（CIRAM）
+    name_table: Box<[u8; Self::SIZE_NAME_TABLE]>,
+    palette: [u8; Self::SIZE_PALETTE],
 }
 
 impl PpuMemory {
-    const SIZE_PPU_MEMORY: usize = 16 * 1024;
-    const ADDRESS_PPU_NAME_TABLE_MIRROR_START: u16 = 0x3000;
-    const ADDRESS_PPU_NAME_TABLE_MIRROR_END: u16 = 0x3EFF;
-    const ADDRESS_PPU_PALETTE_MIRROR_START: u16 = 0x3F20;
+    const SIZE_NAME_TABLE: usize = 2 * 1024;
+    const SIZE_PALETTE: usize = 32;
+    const ADDRESS_PPU_PALETTE_START: u16 = 0x3F00;
     const ADDRESS_PPU_PALETTE_MIRROR_END: u16 = 0x3FFF;
     pub fn new() -> Self {
         PpuMemory {
-            memory: Box::new([0; Self::SIZE_PPU_MEMORY]),
+            name_table: Box::new([0; Self::SIZE_NAME_TABLE]),
+            palette: [0; Self::SIZE_PALETTE],
+        }
+    }
+
+    /// 按 CIRAM 中的偏移读取名称表，偏移由卡带决定
+    pub fn read_name_table(&self, offset: u16) -> u8 {
+        self.name_table[offset as usize & (Self::SIZE_NAME_TABLE - 1)]
+    }
+    pub fn write_name_table(&mut self, offset: u16, data: u8) {
+        self.name_table[offset as usize & (Self::SIZE_NAME_TABLE - 1)] = data;
+    }
+
+    /// 调色板中的偏移，名称表和图案表不经过这里
+    fn locate(&self, address: u16) -> Result<usize> {
+        let address = address & 0x3FFF;
+        match address {
+            Self::ADDRESS_PPU_PALETTE_START..=Self::ADDRESS_PPU_PALETTE_MIRROR_END => {
+                // $3F10/$3F14/$3F18/$3F1C 是 $3F00/$3F04/$3F08/$3F0C 的镜像
+                match address & 0x1F {
+                    offset if offset & 0x13 == 0x10 => Ok(offset as usize & 0x0F),
+                    offset => Ok(offset as usize),
+                }
+            }
+            _ => Err(MemoryError::AddressOutOfRange(address)),
         }
     }
 }
@@ -25,40 +51,31 @@ impl Default for PpuMemory {
 
 impl Memory for PpuMemory {
     fn read(&self, address: u16) -> Result<u8> {
-        let address = address & 0x3FFF;
-        let offset = match address {
-            Self::ADDRESS_PPU_NAME_TABLE_MIRROR_START..=Self::ADDRESS_PPU_NAME_TABLE_MIRROR_END => {
-                0x1000
-            }
-            Self::ADDRESS_PPU_PALETTE_MIRROR_START..=Self::ADDRESS_PPU_PALETTE_MIRROR_END => 0x20,
-            _ => return Err(MemoryError::AddressOutOfRange(address)),
-        };
-        let address = match address - offset {
-            0x3F10 | 0x3F14 | 0x3F18 | 0x3F1C => address - offset - 0x10,
-            address => address,
-        };
-        self.memory
-            .get(address as usize)
-            .copied()
-            .ok_or(MemoryError::ReadMemory(address))
+        Ok(self.palette[self.locate(address)?])
     }
 
     fn write(&mut self, address: u16, data: u8) -> Result<()> {
-        let address = address & 0x3FFF;
-        let offset = match address {
-            Self::ADDRESS_PPU_NAME_TABLE_MIRROR_START..=Self::ADDRESS_PPU_NAME_TABLE_MIRROR_END => {
-                0x1000
-            }
-            Self::ADDRESS_PPU_PALETTE_MIRROR_START..=Self::ADDRESS_PPU_PALETTE_MIRROR_END => 0x20,
-            _ => return Err(MemoryError::AddressOutOfRange(address)),
-        };
-        let address = match address - offset {
-            0x3F10 | 0x3F14 | 0x3F18 | 0x3F1C => address - offset - 0x10,
-            address => address,
-        };
-        self.memory
-            .get_mut(address as usize)
-            .map(|value| *value = data)
-            .ok_or(MemoryError::WriteMemory(address))
+        self.palette[self.locate(address)?] = data;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PpuMemory;
+    use crate::memory::Memory;
+
+    #[test]
+    fn palette_test() {
+        let mut memory = PpuMemory::new();
+        memory.write(0x3F10, 0x0F).unwrap();
+        memory.write(0x3F25, 0x15).unwrap();
+        assert_eq!(memory.read(0x3F00).unwrap(), 0x0F);
+        assert_eq!(memory.read(0x3F05).unwrap(), 0x15);
+        assert!(memory.read(0x1000).is_err());
+        // 名称表由卡带映射到 CIRAM，见 `bus::video_read`
+        assert!(memory.read(0x2000).is_err());
+        memory.write_name_table(0x0C00, 0x22);
+        assert_eq!(memory.read_name_table(0x0400), 0x22);
     }
 }
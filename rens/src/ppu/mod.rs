@@ -1,15 +1,137 @@
-mod bus;
 mod memory;
+mod register;
+mod render;
+mod sprite;
 
+use crate::register::PpuRegister;
 pub use memory::*;
+pub use register::*;
+use render::Background;
+use sprite::Sprites;
 
-#[derive(Debug, Eq, PartialEq)]
+/// 图像处理器，寄存器通过 CPU 总线的 $2000-$3FFF 访问
+#[derive(Debug)]
+pub struct Ppu {
+    registers: PpuRegisters,
+    /// 64 个精灵的属性
+    oam: Box<[u8; Ppu::SIZE_OAM]>,
+    /// 下一条扫描线的 8 个精灵
+    secondary_oam: [u8; Ppu::SIZE_SECONDARY_OAM],
+    scanline: u16,
+    dot: u16,
+    /// 上电以来开始的帧数
+    frame_number: u32,
+    /// $2006 第二次写入后等待生效的 v 及剩余点数
+    pending_v: Option<(u16, u8)>,
+    background: Background,
+    sprites: Sprites,
+    /// 正在绘制的画面，每个像素是 64 色调色板中的索引
+    back_buffer: Box<[u8; Ppu::SIZE_FRAME]>,
+    /// 最近绘制完成的画面
+    front_buffer: Box<[u8; Ppu::SIZE_FRAME]>,
+}
+
+impl Ppu {
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 240;
+    pub const DOTS_PER_SCANLINE: u16 = 341;
+    pub const SCANLINES_PER_FRAME: u16 = 262;
+    const SIZE_OAM: usize = 256;
+    const SIZE_SECONDARY_OAM: usize = 32;
+    const SIZE_FRAME: usize = Self::WIDTH * Self::HEIGHT;
+    pub fn new() -> Self {
+        Self {
+            registers: PpuRegisters::new(),
+            oam: Box::new([0; Self::SIZE_OAM]),
+            secondary_oam: [0xFF; Self::SIZE_SECONDARY_OAM],
+            scanline: 0,
+            dot: 0,
+            frame_number: 0,
+            pending_v: None,
+            background: Background::default(),
+            sprites: Sprites::default(),
+            back_buffer: Box::new([0; Self::SIZE_FRAME]),
+            front_buffer: Box::new([0; Self::SIZE_FRAME]),
+        }
+    }
+    pub fn registers(&self) -> &PpuRegisters {
+        &self.registers
+    }
+    pub fn registers_mut(&mut self) -> &mut PpuRegisters {
+        &mut self.registers
+    }
+    pub fn oam(&self) -> &[u8; Ppu::SIZE_OAM] {
+        &self.oam
+    }
+    /// 按位解读 PPUCTRL、PPUMASK 和 PPUSTATUS
+    pub fn register(&self) -> PpuRegister<'_> {
+        PpuRegister { ppu: self }
+    }
+    /// NMI 输出线，垂直消隐期间且 PPUCTRL 允许时有效
+    pub fn nmi_line(&self) -> bool {
+        self.registers.ctrl & CTRL_NMI != 0 && self.registers.status & STATUS_VBLANK != 0
+    }
+    /// 当前扫描线，0-239 可见，241 开始垂直消隐，261 为预渲染扫描线
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+    pub fn frame_number(&self) -> u32 {
+        self.frame_number
+    }
+    /// 最近完成的一帧，按行排列的调色板索引
+    pub fn frame(&self) -> &[u8] {
+        &self.front_buffer[..]
+    }
+    /// 最近完成的一帧，每个像素按 R、G、B、A 排列
+    pub fn frame_rgba(&self) -> Vec<u8> {
+        self.front_buffer
+            .iter()
+            .flat_map(|index| {
+                let color = &STD_PALETTE[*index as usize & 0x3F];
+                [color.r(), color.g(), color.b(), color.a()]
+            })
+            .collect()
+    }
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 名称表的镜像方式，由卡带决定，部分 Mapper 可以在运行时切换
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Mirroring {
     Horizontal,
     Vertical,
+    /// 四个名称表都使用 CIRAM 的前 1K
+    SingleScreenA,
+    /// 四个名称表都使用 CIRAM 的后 1K
+    SingleScreenB,
+    /// 后两个名称表使用卡带上的内存
     FourScreen,
 }
 
+impl Mirroring {
+    /// 名称表地址在 2K CIRAM 中的偏移，由卡带提供时返回 `None`
+    pub fn map_name_table(&self, address: u16) -> Option<u16> {
+        let table = (address >> 10) & 0x03;
+        let page = match self {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 0x01,
+            Mirroring::SingleScreenA => 0,
+            Mirroring::SingleScreenB => 1,
+            Mirroring::FourScreen if table < 2 => table,
+            Mirroring::FourScreen => return None,
+        };
+        Some(page << 10 | (address & 0x03FF))
+    }
+}
+
 pub const STD_PALETTE: [PaletteData; 64] = [
     PaletteData::from_rgba([0x7F, 0x7F, 0x7F, 0xFF]),
     PaletteData::from_rgba([0x20, 0x00, 0xB0, 0xFF]),
@@ -0,0 +1,263 @@
+use super::Ppu;
+use crate::memory::{Memory, Result};
+
+/// PPUCTRL：VRAM 地址每次增加 32
+pub(super) const CTRL_INCREMENT: u8 = 1 << 2;
+/// PPUCTRL：进入垂直消隐时产生 NMI
+pub(super) const CTRL_NMI: u8 = 1 << 7;
+/// PPUMASK：灰度
+pub(super) const MASK_GRAYSCALE: u8 = 1;
+/// PPUMASK：在最左侧 8 个像素显示背景
+pub(super) const MASK_BACKGROUND_LEFT: u8 = 1 << 1;
+/// PPUMASK：在最左侧 8 个像素显示精灵
+pub(super) const MASK_SPRITE_LEFT: u8 = 1 << 2;
+/// PPUMASK：显示背景
+pub(super) const MASK_BACKGROUND: u8 = 1 << 3;
+/// PPUMASK：显示精灵
+pub(super) const MASK_SPRITE: u8 = 1 << 4;
+/// PPUSTATUS：精灵溢出
+pub(super) const STATUS_SPRITE_OVERFLOW: u8 = 1 << 5;
+/// PPUSTATUS：0 号精灵命中
+pub(super) const STATUS_SPRITE_ZERO: u8 = 1 << 6;
+/// PPUSTATUS：垂直消隐
+pub(super) const STATUS_VBLANK: u8 = 1 << 7;
+
+/// PPU 的寄存器和内部锁存器
+#[derive(Debug, Default, Clone)]
+pub struct PpuRegisters {
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_addr: u8,
+    /// 当前 VRAM 地址（loopy v）
+    pub v: u16,
+    /// 临时 VRAM 地址（loopy t）
+    pub t: u16,
+    /// 水平精细滚动
+    pub x: u8,
+    /// PPUSCROLL/PPUADDR 共用的写入开关
+    pub w: bool,
+    /// PPUDATA 读缓冲
+    pub read_buffer: u8,
+    /// 最后一次出现在 PPU 数据总线上的值，读取只写寄存器时返回
+    pub latch: u8,
+}
+
+impl PpuRegisters {
+    pub fn new() -> Self {
+        PpuRegisters::default()
+    }
+}
+
+impl Ppu {
+    const PPU_CTRL: u16 = 0;
+    const PPU_MASK: u16 = 1;
+    const PPU_STATUS: u16 = 2;
+    const OAM_ADDR: u16 = 3;
+    const OAM_DATA: u16 = 4;
+    const PPU_SCROLL: u16 = 5;
+    const PPU_ADDR: u16 = 6;
+    const PPU_DATA: u16 = 7;
+    const ADDRESS_PALETTE_START: u16 = 0x3F00;
+    /// $2006 第二次写入到 v 更新之间的点数
+    const PENDING_V_DOTS: u8 = 3;
+
+    /// 读取 $2000-$3FFF 中的寄存器，每 8 字节镜像一次
+    pub fn read_register(&mut self, address: u16, vram: &mut impl Memory) -> Result<u8> {
+        let registers = &mut self.registers;
+        let data = match address & 0x7 {
+            Self::PPU_STATUS => {
+                let data = (registers.status & 0xE0) | (registers.latch & 0x1F);
+                registers.status &= !STATUS_VBLANK;
+                registers.w = false;
+                data
+            }
+            Self::OAM_DATA => self.oam[registers.oam_addr as usize],
+            Self::PPU_DATA => {
+                let address = registers.v & 0x3FFF;
+                let data = if address >= Self::ADDRESS_PALETTE_START {
+                    // 调色板直接返回，缓冲区填入其下方的名称表
+                    registers.read_buffer = vram.read(address - 0x1000)?;
+                    (vram.read(address)? & 0x3F) | (registers.latch & 0xC0)
+                } else {
+                    std::mem::replace(&mut registers.read_buffer, vram.read(address)?)
+                };
+                self.increment_vram_address();
+                data
+            }
+            _ => registers.latch,
+        };
+        self.registers.latch = data;
+        Ok(data)
+    }
+
+    /// 写入 $2000-$3FFF 中的寄存器，每 8 字节镜像一次
+    pub fn write_register(&mut self, address: u16, data: u8, vram: &mut impl Memory) -> Result<()> {
+        let registers = &mut self.registers;
+        registers.latch = data;
+        match address & 0x7 {
+            Self::PPU_CTRL => {
+                registers.ctrl = data;
+                registers.t = (registers.t & !0x0C00) | ((data as u16 & 0x03) << 10);
+            }
+            Self::PPU_MASK => registers.mask = data,
+            Self::OAM_ADDR => registers.oam_addr = data,
+            Self::OAM_DATA => {
+                // 属性字节的第 2-4 位不存在
+                self.oam[registers.oam_addr as usize] = match registers.oam_addr & 0x03 {
+                    2 => data & 0xE3,
+                    _ => data,
+                };
+                registers.oam_addr = registers.oam_addr.wrapping_add(1);
+            }
+            Self::PPU_SCROLL => {
+                if registers.w {
+                    registers.t = (registers.t & !0x73E0)
+                        | ((data as u16 & 0x07) << 12)
+                        | ((data as u16 & 0xF8) << 2);
+                } else {
+                    registers.t = (registers.t & !0x001F) | (data as u16 >> 3);
+                    registers.x = data & 0x07;
+                }
+                registers.w = !registers.w;
+            }
+            Self::PPU_ADDR => {
+                if registers.w {
+                    registers.t = (registers.t & 0xFF00) | data as u16;
+                    let t = registers.t;
+                    // 渲染期间 v 在几个点之后才更新，其他时候 CPU 观察不到这个延迟
+                    if self.rendering_active() {
+                        self.pending_v = Some((t, Self::PENDING_V_DOTS));
+                    } else {
+                        self.registers.v = t;
+                    }
+                } else {
+                    registers.t = (registers.t & 0x00FF) | ((data as u16 & 0x3F) << 8);
+                }
+                self.registers.w = !self.registers.w;
+            }
+            Self::PPU_DATA => {
+                vram.write(registers.v & 0x3FFF, data)?;
+                self.increment_vram_address();
+            }
+            // PPUSTATUS 只读
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// 读取寄存器但不产生副作用，供调试工具使用
+    pub fn peek_register(&self, address: u16, vram: &impl Memory) -> Result<u8> {
+        let registers = &self.registers;
+        match address & 0x7 {
+            Self::PPU_STATUS => Ok((registers.status & 0xE0) | (registers.latch & 0x1F)),
+            Self::OAM_DATA => Ok(self.oam[registers.oam_addr as usize]),
+            Self::PPU_DATA if registers.v & 0x3FFF >= Self::ADDRESS_PALETTE_START => {
+                Ok((vram.read(registers.v & 0x3FFF)? & 0x3F) | (registers.latch & 0xC0))
+            }
+            Self::PPU_DATA => Ok(registers.read_buffer),
+            _ => Ok(registers.latch),
+        }
+    }
+
+    fn increment_vram_address(&mut self) {
+        // 渲染期间访问 PPUDATA 会同时触发粗略 X 和 Y 的增加
+        if self.rendering_active() {
+            self.increment_x();
+            self.increment_y();
+            return;
+        }
+        let step = if self.registers.ctrl & CTRL_INCREMENT == 0 {
+            1
+        } else {
+            32
+        };
+        self.registers.v = self.registers.v.wrapping_add(step) & 0x7FFF;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ppu, STATUS_VBLANK};
+    use crate::bus::TestVideo;
+    use crate::ppu::Mirroring;
+
+    #[test]
+    fn status_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = TestVideo::new(Mirroring::Horizontal);
+        ppu.registers_mut().status = STATUS_VBLANK;
+        ppu.write_register(0x2006, 0x21, &mut vram).unwrap();
+        assert!(ppu.registers().w);
+        // 低 5 位来自数据总线上残留的值
+        assert_eq!(ppu.read_register(0x2002, &mut vram).unwrap(), 0x80 | 0x01);
+        assert!(!ppu.registers().w);
+        assert_eq!(ppu.read_register(0x2002, &mut vram).unwrap(), 0x01);
+        // 只写寄存器返回残留的值
+        assert_eq!(ppu.read_register(0x2000, &mut vram).unwrap(), 0x01);
+    }
+
+    #[test]
+    fn scroll_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = TestVideo::new(Mirroring::Horizontal);
+        ppu.write_register(0x2000, 0x02, &mut vram).unwrap();
+        ppu.write_register(0x2005, 0x7D, &mut vram).unwrap();
+        ppu.write_register(0x2005, 0x5E, &mut vram).unwrap();
+        // 精细 Y 110、名称表 10、粗略 Y 01011、粗略 X 01111
+        assert_eq!(ppu.registers().t, 0b0110_1001_0110_1111);
+        assert_eq!(ppu.registers().x, 0b101);
+        ppu.write_register(0x2006, 0x3D, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0xF0, &mut vram).unwrap();
+        assert_eq!(ppu.registers().v, 0x3DF0);
+        assert_eq!(ppu.registers().t, 0x3DF0);
+    }
+
+    #[test]
+    fn data_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = TestVideo::new(Mirroring::Horizontal);
+        ppu.write_register(0x2006, 0x24, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0x00, &mut vram).unwrap();
+        ppu.write_register(0x2007, 0x11, &mut vram).unwrap();
+        ppu.write_register(0x2007, 0x22, &mut vram).unwrap();
+        // 每次增加 32
+        ppu.write_register(0x2000, 0x04, &mut vram).unwrap();
+        ppu.write_register(0x2007, 0x33, &mut vram).unwrap();
+        assert_eq!(ppu.registers().v, 0x2422);
+
+        ppu.write_register(0x2000, 0x00, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0x24, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0x00, &mut vram).unwrap();
+        assert_eq!(ppu.peek_register(0x2007, &vram).unwrap(), 0x00);
+        // 第一次读取返回缓冲区的旧值
+        assert_eq!(ppu.read_register(0x2007, &mut vram).unwrap(), 0x00);
+        assert_eq!(ppu.read_register(0x2007, &mut vram).unwrap(), 0x11);
+        assert_eq!(ppu.read_register(0x2007, &mut vram).unwrap(), 0x22);
+        assert_eq!(ppu.registers().v, 0x2403);
+
+        // 调色板不经过缓冲区，缓冲区填入下方的名称表
+        ppu.write_register(0x2006, 0x3F, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0x01, &mut vram).unwrap();
+        ppu.write_register(0x2007, 0x2A, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0x3F, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0x01, &mut vram).unwrap();
+        assert_eq!(ppu.read_register(0x2007, &mut vram).unwrap(), 0x2A);
+        assert_eq!(ppu.registers().read_buffer, 0x00);
+    }
+
+    #[test]
+    fn oam_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = TestVideo::new(Mirroring::Horizontal);
+        ppu.write_register(0x2003, 0xFF, &mut vram).unwrap();
+        ppu.write_register(0x2004, 0x12, &mut vram).unwrap();
+        ppu.write_register(0x2004, 0x34, &mut vram).unwrap();
+        assert_eq!(ppu.oam()[0xFF], 0x12);
+        assert_eq!(ppu.oam()[0x00], 0x34);
+        // 读取不增加 OAMADDR
+        ppu.write_register(0x2003, 0xFF, &mut vram).unwrap();
+        assert_eq!(ppu.read_register(0x2004, &mut vram).unwrap(), 0x12);
+        assert_eq!(ppu.read_register(0x2004, &mut vram).unwrap(), 0x12);
+    }
+}
@@ -0,0 +1,705 @@
+use super::register::*;
+use super::sprite::SpritePixel;
+use super::Ppu;
+use crate::memory::{Memory, Result};
+
+/// 背景的取图流水线：每 8 个点取一个图块，移位寄存器逐点输出像素
+#[derive(Debug, Default, Clone)]
+pub(super) struct Background {
+    name_table: u8,
+    /// 已经从属性字节中选出的 2 位调色板号
+    attribute: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    shift_pattern_low: u16,
+    shift_pattern_high: u16,
+    shift_attribute_low: u16,
+    shift_attribute_high: u16,
+}
+
+impl Background {
+    fn shift(&mut self) {
+        self.shift_pattern_low <<= 1;
+        self.shift_pattern_high <<= 1;
+        self.shift_attribute_low <<= 1;
+        self.shift_attribute_high <<= 1;
+    }
+
+    /// 把下一个图块装入移位寄存器的低 8 位
+    fn load(&mut self) {
+        let expand = |bit: u8| if bit != 0 { 0xFF } else { 0x00 };
+        self.shift_pattern_low = (self.shift_pattern_low & 0xFF00) | self.pattern_low as u16;
+        self.shift_pattern_high = (self.shift_pattern_high & 0xFF00) | self.pattern_high as u16;
+        self.shift_attribute_low =
+            (self.shift_attribute_low & 0xFF00) | expand(self.attribute & 0x01);
+        self.shift_attribute_high =
+            (self.shift_attribute_high & 0xFF00) | expand(self.attribute & 0x02);
+    }
+
+    /// 精细滚动 `x` 处的 (调色板号, 像素值)
+    fn pixel(&self, x: u8) -> (u8, u8) {
+        let bit = 0x8000 >> x;
+        let select = |shift: u16| (shift & bit != 0) as u8;
+        (
+            select(self.shift_attribute_high) << 1 | select(self.shift_attribute_low),
+            select(self.shift_pattern_high) << 1 | select(self.shift_pattern_low),
+        )
+    }
+}
+
+impl Ppu {
+    pub(super) const VISIBLE_SCANLINES: u16 = 240;
+    const VBLANK_SCANLINE: u16 = 241;
+    const PRE_RENDER_SCANLINE: u16 = 261;
+    const ADDRESS_NAME_TABLE: u16 = 0x2000;
+    const ADDRESS_ATTRIBUTE_TABLE: u16 = 0x23C0;
+    const ADDRESS_PALETTE: u16 = 0x3F00;
+    const ADDRESS_SPRITE_PALETTE: u16 = 0x3F10;
+
+    /// 执行一个点，`vram` 是 PPU 的地址空间
+    pub fn clock(&mut self, vram: &mut impl Memory) -> Result<()> {
+        if let Some((v, dots)) = self.pending_v {
+            self.pending_v = match dots {
+                1 => {
+                    self.registers.v = v;
+                    None
+                }
+                _ => Some((v, dots - 1)),
+            };
+        }
+        let rendering = self.rendering_enabled();
+        let pre_render = self.scanline == Self::PRE_RENDER_SCANLINE;
+        let visible = self.scanline < Self::VISIBLE_SCANLINES;
+        if rendering && (visible || pre_render) {
+            self.fetch_background(vram)?;
+            self.clock_sprites(vram)?;
+        }
+        if visible && (1..=256).contains(&self.dot) {
+            self.render_pixel(vram)?;
+        }
+        if self.dot == 1 {
+            if self.scanline == Self::VBLANK_SCANLINE {
+                self.registers.status |= STATUS_VBLANK;
+                std::mem::swap(&mut self.front_buffer, &mut self.back_buffer);
+            } else if pre_render {
+                self.registers.status &=
+                    !(STATUS_VBLANK | STATUS_SPRITE_ZERO | STATUS_SPRITE_OVERFLOW);
+            }
+        }
+        self.advance(rendering);
+        Ok(())
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.registers.mask & (MASK_BACKGROUND | MASK_SPRITE) != 0
+    }
+
+    /// 正在渲染的扫描线上，v 被取图流水线占用
+    pub(super) fn rendering_active(&self) -> bool {
+        self.rendering_enabled()
+            && (self.scanline < Self::VISIBLE_SCANLINES
+                || self.scanline == Self::PRE_RENDER_SCANLINE)
+    }
+
+    fn advance(&mut self, rendering: bool) {
+        // 渲染时奇数帧的预渲染扫描线少一个点
+        let skip = rendering
+            && self.scanline == Self::PRE_RENDER_SCANLINE
+            && self.dot == Self::DOTS_PER_SCANLINE - 2
+            && self.frame_number % 2 == 1;
+        self.dot += 1;
+        if self.dot == Self::DOTS_PER_SCANLINE || skip {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == Self::SCANLINES_PER_FRAME {
+                self.scanline = 0;
+                self.frame_number = self.frame_number.wrapping_add(1);
+            }
+        }
+    }
+
+    fn fetch_background(&mut self, vram: &mut impl Memory) -> Result<()> {
+        let dot = self.dot;
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.background.shift();
+            match (dot - 1) % 8 {
+                0 => {
+                    self.background.load();
+                    self.background.name_table = self.fetch_name_table(vram)?;
+                }
+                2 => {
+                    let v = self.registers.v;
+                    let address = Self::ADDRESS_ATTRIBUTE_TABLE
+                        | (v & 0x0C00)
+                        | ((v >> 4) & 0x38)
+                        | ((v >> 2) & 0x07);
+                    // 每个属性字节覆盖 4x4 个图块，按 2x2 图块的象限选择
+                    let shift = ((v >> 4) & 0x04) | (v & 0x02);
+                    self.background.attribute = (vram.read(address)? >> shift) & 0x03;
+                }
+                4 => self.background.pattern_low = vram.read(self.pattern_address())?,
+                6 => self.background.pattern_high = vram.read(self.pattern_address() + 8)?,
+                7 => self.increment_x(),
+                _ => {}
+            }
+        }
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.background.load();
+                self.copy_x();
+            }
+            // 行末两次多余的名称表读取
+            338 | 340 => {
+                self.fetch_name_table(vram)?;
+            }
+            280..=304 if self.scanline == Self::PRE_RENDER_SCANLINE => self.copy_y(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn fetch_name_table(&self, vram: &mut impl Memory) -> Result<u8> {
+        vram.read(Self::ADDRESS_NAME_TABLE | (self.registers.v & 0x0FFF))
+    }
+
+    /// 当前图块低位平面所在的地址
+    fn pattern_address(&self) -> u16 {
+        let table = self.register().bg_pattern_address();
+        let fine_y = (self.registers.v >> 12) & 0x07;
+        table | (self.background.name_table as u16) << 4 | fine_y
+    }
+
+    fn render_pixel(&mut self, vram: &mut impl Memory) -> Result<()> {
+        let x = (self.dot - 1) as usize;
+        let mask = self.registers.mask;
+        let address = if !self.rendering_enabled() {
+            // 关闭渲染且 v 指向调色板时显示该颜色
+            match self.registers.v & 0x3FFF {
+                v if v >= Self::ADDRESS_PALETTE => v,
+                _ => Self::ADDRESS_PALETTE,
+            }
+        } else {
+            let (palette, pixel) =
+                if mask & MASK_BACKGROUND != 0 && (x >= 8 || mask & MASK_BACKGROUND_LEFT != 0) {
+                    self.background.pixel(self.registers.x)
+                } else {
+                    (0, 0)
+                };
+            let sprite = if mask & MASK_SPRITE != 0 && (x >= 8 || mask & MASK_SPRITE_LEFT != 0) {
+                self.sprite_pixel(x)
+            } else {
+                None
+            };
+            match sprite {
+                Some(sprite) if pixel != 0 && sprite.zero && x != 255 => {
+                    self.registers.status |= STATUS_SPRITE_ZERO;
+                }
+                _ => {}
+            }
+            match (sprite, pixel) {
+                (Some(sprite), 0) | (Some(sprite @ SpritePixel { behind: false, .. }), _) => {
+                    Self::ADDRESS_SPRITE_PALETTE | (sprite.palette << 2 | sprite.pixel) as u16
+                }
+                (_, 0) => Self::ADDRESS_PALETTE,
+                _ => Self::ADDRESS_PALETTE | (palette << 2 | pixel) as u16,
+            }
+        };
+        let mut color = vram.read(address)? & 0x3F;
+        if mask & MASK_GRAYSCALE != 0 {
+            color &= 0x30;
+        }
+        self.back_buffer[self.scanline as usize * Self::WIDTH + x] = color;
+        Ok(())
+    }
+
+    /// 粗略 X 加一，越过名称表右边界时切换水平名称表
+    pub(super) fn increment_x(&mut self) {
+        let v = &mut self.registers.v;
+        if *v & 0x001F == 31 {
+            *v &= !0x001F;
+            *v ^= 0x0400;
+        } else {
+            *v += 1;
+        }
+    }
+
+    /// 精细 Y 加一，进位到粗略 Y，越过第 29 行时切换垂直名称表
+    pub(super) fn increment_y(&mut self) {
+        let v = &mut self.registers.v;
+        if *v & 0x7000 != 0x7000 {
+            *v += 0x1000;
+            return;
+        }
+        *v &= !0x7000;
+        let coarse_y = match (*v & 0x03E0) >> 5 {
+            29 => {
+                *v ^= 0x0800;
+                0
+            }
+            // 属性表区域内的行回绕时不切换名称表
+            31 => 0,
+            coarse_y => coarse_y + 1,
+        };
+        *v = (*v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn copy_x(&mut self) {
+        let registers = &mut self.registers;
+        registers.v = (registers.v & !0x041F) | (registers.t & 0x041F);
+    }
+
+    fn copy_y(&mut self) {
+        let registers = &mut self.registers;
+        registers.v = (registers.v & !0x7BE0) | (registers.t & 0x7BE0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ppu;
+    use super::{STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_ZERO};
+    use crate::bus::TestVideo;
+    use crate::memory::Memory;
+    use crate::nes::run_blargg_2005;
+    use crate::ppu::{Mirroring, STD_PALETTE};
+
+    /// 8K CHR RAM 和四屏名称表的 PPU 地址空间
+    fn make_vram() -> TestVideo {
+        TestVideo::new(Mirroring::FourScreen)
+    }
+
+    fn run_frame(ppu: &mut Ppu, vram: &mut TestVideo) {
+        let frame_number = ppu.frame_number();
+        while ppu.frame_number() == frame_number {
+            ppu.clock(vram).unwrap();
+        }
+    }
+
+    #[test]
+    fn timing_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = make_vram();
+        let mut dots = 0;
+        while ppu.registers().status & 0x80 == 0 {
+            ppu.clock(&mut vram).unwrap();
+            dots += 1;
+        }
+        assert_eq!(dots, 241 * 341 + 2);
+        assert_eq!((ppu.scanline(), ppu.dot()), (241, 2));
+        while ppu.registers().status & 0x80 != 0 {
+            ppu.clock(&mut vram).unwrap();
+        }
+        assert_eq!((ppu.scanline(), ppu.dot()), (261, 2));
+
+        // 关闭渲染时每帧点数相同
+        run_frame(&mut ppu, &mut vram);
+        assert_eq!((ppu.frame_number(), ppu.scanline(), ppu.dot()), (1, 0, 0));
+        run_frame(&mut ppu, &mut vram);
+        assert_eq!((ppu.frame_number(), ppu.scanline(), ppu.dot()), (2, 0, 0));
+
+        // 开启渲染后奇数帧少一个点
+        ppu.registers_mut().mask = 0x08;
+        let mut dots = 0;
+        for _ in 0..2 {
+            let frame_number = ppu.frame_number();
+            while ppu.frame_number() == frame_number {
+                ppu.clock(&mut vram).unwrap();
+                dots += 1;
+            }
+        }
+        assert_eq!(dots, 341 * 262 * 2 - 1);
+    }
+
+    #[test]
+    fn background_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = make_vram();
+        // 1 号图块：每行从左到右的像素为 3 3 1 1 2 2 0 0
+        for row in 0..8 {
+            vram.write(0x0010 + row, 0xF0).unwrap();
+            vram.write(0x0018 + row, 0xCC).unwrap();
+        }
+        for address in 0x2000..0x23C0 {
+            vram.write(address, 0x01).unwrap();
+        }
+        // 左上象限用 1 号调色板，右上象限用 2 号调色板
+        vram.write(0x23C0, 0b1001).unwrap();
+        for (offset, color) in [
+            0x0F, 0x11, 0x12, 0x13, 0x0F, 0x21, 0x22, 0x23, 0x0F, 0x31, 0x32, 0x33,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            vram.write(0x3F00 + offset as u16, color).unwrap();
+        }
+        ppu.registers_mut().mask = 0x0A;
+        // 第一帧的预渲染扫描线之前 v 尚未设置
+        run_frame(&mut ppu, &mut vram);
+        run_frame(&mut ppu, &mut vram);
+        while ppu.scanline() != 241 || ppu.dot() != 2 {
+            ppu.clock(&mut vram).unwrap();
+        }
+
+        let frame = ppu.frame();
+        assert_eq!(frame.len(), Ppu::WIDTH * Ppu::HEIGHT);
+        assert_eq!(frame[..8], [0x23, 0x23, 0x21, 0x21, 0x22, 0x22, 0x0F, 0x0F]);
+        assert_eq!(
+            frame[16..24],
+            [0x33, 0x33, 0x31, 0x31, 0x32, 0x32, 0x0F, 0x0F]
+        );
+        // 每行相同，第三个图块行用 0 号调色板
+        assert_eq!(frame[7 * 256..7 * 256 + 8], frame[..8]);
+        assert_eq!(
+            frame[32 * 256..32 * 256 + 8],
+            [0x13, 0x13, 0x11, 0x11, 0x12, 0x12, 0x0F, 0x0F]
+        );
+
+        let rgba = ppu.frame_rgba();
+        assert_eq!(rgba.len(), frame.len() * 4);
+        let color = &STD_PALETTE[0x23];
+        assert_eq!(rgba[..4], [color.r(), color.g(), color.b(), color.a()]);
+    }
+
+    #[test]
+    fn fine_scroll_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = make_vram();
+        for row in 0..8 {
+            vram.write(0x0010 + row, 0xF0).unwrap();
+        }
+        // 只有第二个图块不是空白
+        vram.write(0x2001, 0x01).unwrap();
+        vram.write(0x3F01, 0x30).unwrap();
+        // 滚动 X = 10 使第二个图块出现在最左侧
+        ppu.write_register(0x2005, 10, &mut vram).unwrap();
+        ppu.write_register(0x2005, 0, &mut vram).unwrap();
+        ppu.registers_mut().mask = 0x0A;
+        run_frame(&mut ppu, &mut vram);
+        run_frame(&mut ppu, &mut vram);
+        while ppu.scanline() != 241 || ppu.dot() != 2 {
+            ppu.clock(&mut vram).unwrap();
+        }
+        assert_eq!(ppu.frame()[..4], [0x30, 0x30, 0x00, 0x00]);
+
+        // 隐藏最左侧的背景
+        ppu.registers_mut().mask = 0x08;
+        run_frame(&mut ppu, &mut vram);
+        while ppu.scanline() != 241 || ppu.dot() != 2 {
+            ppu.clock(&mut vram).unwrap();
+        }
+        assert_eq!(ppu.frame()[..4], [0x00; 4]);
+    }
+
+    fn run_to(ppu: &mut Ppu, vram: &mut TestVideo, scanline: u16, dot: u16) {
+        while ppu.scanline() != scanline || ppu.dot() != dot {
+            ppu.clock(vram).unwrap();
+        }
+    }
+
+    /// 名称表 0、1、2 分别铺满像素值为 1、2、3 的实心图块
+    fn make_split_vram() -> TestVideo {
+        let mut vram = make_vram();
+        for tile in 1..=3u16 {
+            for row in 0..8 {
+                vram.write(tile * 16 + row, if tile & 1 != 0 { 0xFF } else { 0 })
+                    .unwrap();
+                vram.write(tile * 16 + 8 + row, if tile & 2 != 0 { 0xFF } else { 0 })
+                    .unwrap();
+            }
+            let base = 0x2000 + (tile - 1) * 0x400;
+            for address in base..base + 0x3C0 {
+                vram.write(address, tile as u8).unwrap();
+            }
+        }
+        for (offset, color) in [0x0F, 0x11, 0x22, 0x33].into_iter().enumerate() {
+            vram.write(0x3F00 + offset as u16, color).unwrap();
+        }
+        vram
+    }
+
+    fn line(ppu: &Ppu, scanline: usize) -> &[u8] {
+        &ppu.frame()[scanline * Ppu::WIDTH..(scanline + 1) * Ppu::WIDTH]
+    }
+
+    #[test]
+    fn scroll_split_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = make_split_vram();
+        ppu.registers_mut().mask = 0x0A;
+        run_frame(&mut ppu, &mut vram);
+
+        // 行末切换水平名称表，从下下一条扫描线开始生效
+        run_to(&mut ppu, &mut vram, 100, 260);
+        ppu.write_register(0x2000, 0x01, &mut vram).unwrap();
+        // 帧中途修改垂直滚动要等到下一帧
+        run_to(&mut ppu, &mut vram, 150, 300);
+        ppu.write_register(0x2005, 0x00, &mut vram).unwrap();
+        ppu.write_register(0x2005, 0x40, &mut vram).unwrap();
+        // 通过 $2006 立即改变 v，切换到名称表 2
+        run_to(&mut ppu, &mut vram, 180, 300);
+        ppu.write_register(0x2006, 0x08, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0x00, &mut vram).unwrap();
+        run_to(&mut ppu, &mut vram, 241, 2);
+
+        assert!(line(&ppu, 0).iter().all(|color| *color == 0x11));
+        assert!(line(&ppu, 101).iter().all(|color| *color == 0x11));
+        assert!(line(&ppu, 102).iter().all(|color| *color == 0x22));
+        assert!(line(&ppu, 180).iter().all(|color| *color == 0x22));
+        assert!(line(&ppu, 181).iter().all(|color| *color == 0x33));
+        assert!(line(&ppu, 239).iter().all(|color| *color == 0x33));
+
+        // $2006 同时覆盖了 t，下一帧整帧都从名称表 2 开始
+        run_frame(&mut ppu, &mut vram);
+        run_to(&mut ppu, &mut vram, 241, 2);
+        assert_eq!(ppu.registers().t, 0x0800);
+        assert!(line(&ppu, 0).iter().all(|color| *color == 0x33));
+        assert!(line(&ppu, 239).iter().all(|color| *color == 0x33));
+    }
+
+    #[test]
+    fn pending_address_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = make_split_vram();
+        ppu.registers_mut().mask = 0x08;
+        run_frame(&mut ppu, &mut vram);
+        run_to(&mut ppu, &mut vram, 10, 300);
+        ppu.write_register(0x2006, 0x24, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0x42, &mut vram).unwrap();
+        ppu.clock(&mut vram).unwrap();
+        ppu.clock(&mut vram).unwrap();
+        assert_ne!(ppu.registers().v, 0x2442);
+        ppu.clock(&mut vram).unwrap();
+        assert_eq!(ppu.registers().v, 0x2442);
+
+        // 渲染期间访问 PPUDATA 同时增加粗略 X 和精细 Y
+        ppu.read_register(0x2007, &mut vram).unwrap();
+        assert_eq!(ppu.registers().v, 0x3443);
+
+        // 垂直消隐期间立即生效，按 PPUCTRL 增加
+        run_to(&mut ppu, &mut vram, 241, 2);
+        ppu.write_register(0x2006, 0x24, &mut vram).unwrap();
+        ppu.write_register(0x2006, 0x42, &mut vram).unwrap();
+        assert_eq!(ppu.registers().v, 0x2442);
+        ppu.read_register(0x2007, &mut vram).unwrap();
+        assert_eq!(ppu.registers().v, 0x2443);
+    }
+
+    /// 1 号图块像素全为 1，2 号图块左半边为 1、右半边为 2，$1000 处的 2、3 号图块全为 1、2
+    fn make_sprite_vram() -> TestVideo {
+        let mut vram = make_vram();
+        for row in 0..8 {
+            vram.write(0x0010 + row, 0xFF).unwrap();
+            vram.write(0x0020 + row, 0xF0).unwrap();
+            vram.write(0x0028 + row, 0x0F).unwrap();
+            vram.write(0x1020 + row, 0xFF).unwrap();
+            vram.write(0x1038 + row, 0xFF).unwrap();
+        }
+        for (offset, color) in [0x0F, 0x01, 0x02, 0x03].into_iter().enumerate() {
+            vram.write(0x3F00 + offset as u16, color).unwrap();
+        }
+        for (offset, color) in [0x0F, 0x15, 0x16, 0x17].into_iter().enumerate() {
+            vram.write(0x3F14 + offset as u16, color).unwrap();
+        }
+        vram
+    }
+
+    fn set_sprite(ppu: &mut Ppu, index: usize, sprite: [u8; 4]) {
+        ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&sprite);
+    }
+
+    fn hide_sprites(ppu: &mut Ppu) {
+        ppu.oam.fill(0xFF);
+    }
+
+    fn render_frame(ppu: &mut Ppu, vram: &mut TestVideo) {
+        run_frame(ppu, vram);
+        run_to(ppu, vram, 241, 2);
+    }
+
+    #[test]
+    fn sprite_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = make_sprite_vram();
+        hide_sprites(&mut ppu);
+        // 精灵比所在的扫描线晚一行显示
+        set_sprite(&mut ppu, 0, [9, 0x02, 0x01, 20]);
+        set_sprite(&mut ppu, 1, [9, 0x02, 0x41, 40]);
+        set_sprite(&mut ppu, 2, [19, 0x02, 0x81, 60]);
+        ppu.registers_mut().mask = 0x1E;
+        render_frame(&mut ppu, &mut vram);
+
+        let frame = ppu.frame();
+        assert!(line(&ppu, 9).iter().all(|color| *color == 0x0F));
+        assert_eq!(
+            frame[10 * 256 + 20..10 * 256 + 28],
+            [0x15, 0x15, 0x15, 0x15, 0x16, 0x16, 0x16, 0x16]
+        );
+        assert_eq!(
+            frame[17 * 256 + 40..17 * 256 + 48],
+            [0x16, 0x16, 0x16, 0x16, 0x15, 0x15, 0x15, 0x15]
+        );
+        assert_eq!(frame[20 * 256 + 60..20 * 256 + 64], [0x15; 4]);
+        assert!(line(&ppu, 18).iter().all(|color| *color == 0x0F));
+
+        // 8x16 的精灵，图块号 3 选择 $1000 处的 2、3 号图块
+        ppu.registers_mut().ctrl = 0x20;
+        hide_sprites(&mut ppu);
+        set_sprite(&mut ppu, 0, [29, 0x03, 0x01, 0]);
+        set_sprite(&mut ppu, 1, [29, 0x03, 0x81, 8]);
+        render_frame(&mut ppu, &mut vram);
+        let frame = ppu.frame();
+        assert_eq!(
+            frame[30 * 256..30 * 256 + 16],
+            [[0x15; 8], [0x16; 8]].concat()[..]
+        );
+        assert_eq!(
+            frame[38 * 256..38 * 256 + 16],
+            [[0x16; 8], [0x15; 8]].concat()[..]
+        );
+        assert!(line(&ppu, 46).iter().all(|color| *color == 0x0F));
+
+        // 隐藏最左侧 8 个像素
+        ppu.registers_mut().mask = 0x1A;
+        render_frame(&mut ppu, &mut vram);
+        let frame = ppu.frame();
+        assert_eq!(
+            frame[30 * 256..30 * 256 + 16],
+            [[0x0F; 8], [0x16; 8]].concat()[..]
+        );
+    }
+
+    #[test]
+    fn sprite_priority_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = make_sprite_vram();
+        // 左半屏的背景不透明
+        for address in 0x2000..0x23C0 {
+            let tile = if address & 0x1F < 16 { 0x01 } else { 0x00 };
+            vram.write(address, tile).unwrap();
+        }
+        hide_sprites(&mut ppu);
+        set_sprite(&mut ppu, 0, [9, 0x01, 0x01, 0]);
+        set_sprite(&mut ppu, 1, [9, 0x01, 0x21, 8]);
+        set_sprite(&mut ppu, 2, [9, 0x01, 0x21, 200]);
+        // 编号小的精灵在背景之后时，仍然遮挡编号大的精灵
+        set_sprite(&mut ppu, 3, [19, 0x01, 0x21, 16]);
+        set_sprite(&mut ppu, 4, [19, 0x02, 0x01, 16]);
+        ppu.registers_mut().mask = 0x1E;
+        render_frame(&mut ppu, &mut vram);
+
+        let frame = ppu.frame();
+        assert_eq!(frame[10 * 256..10 * 256 + 8], [0x15; 8]);
+        assert_eq!(frame[10 * 256 + 8..10 * 256 + 16], [0x01; 8]);
+        assert_eq!(frame[10 * 256 + 200..10 * 256 + 208], [0x15; 8]);
+        assert_eq!(frame[20 * 256 + 16..20 * 256 + 24], [0x01; 8]);
+    }
+
+    #[test]
+    fn sprite_zero_hit_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = make_sprite_vram();
+        for address in 0x2000..0x23C0 {
+            vram.write(address, 0x01).unwrap();
+        }
+        hide_sprites(&mut ppu);
+        set_sprite(&mut ppu, 0, [49, 0x01, 0x00, 100]);
+        ppu.registers_mut().mask = 0x1E;
+        run_frame(&mut ppu, &mut vram);
+
+        // 在像素 x 输出的点 x + 1 置位
+        run_to(&mut ppu, &mut vram, 50, 101);
+        assert_eq!(ppu.registers().status & STATUS_SPRITE_ZERO, 0);
+        ppu.clock(&mut vram).unwrap();
+        assert_ne!(ppu.registers().status & STATUS_SPRITE_ZERO, 0);
+        // 预渲染扫描线上清除
+        run_to(&mut ppu, &mut vram, 261, 2);
+        assert_eq!(ppu.registers().status & STATUS_SPRITE_ZERO, 0);
+
+        // 最右侧的像素和隐藏的最左侧像素不会命中
+        set_sprite(&mut ppu, 0, [49, 0x01, 0x00, 255]);
+        run_frame(&mut ppu, &mut vram);
+        run_to(&mut ppu, &mut vram, 260, 0);
+        assert_eq!(ppu.registers().status & STATUS_SPRITE_ZERO, 0);
+        set_sprite(&mut ppu, 0, [49, 0x01, 0x00, 0]);
+        ppu.registers_mut().mask = 0x1C;
+        run_frame(&mut ppu, &mut vram);
+        run_to(&mut ppu, &mut vram, 260, 0);
+        assert_eq!(ppu.registers().status & STATUS_SPRITE_ZERO, 0);
+        // 在背景之后的 0 号精灵也会命中
+        set_sprite(&mut ppu, 0, [49, 0x01, 0x20, 100]);
+        run_frame(&mut ppu, &mut vram);
+        run_to(&mut ppu, &mut vram, 260, 0);
+        assert_ne!(ppu.registers().status & STATUS_SPRITE_ZERO, 0);
+    }
+
+    fn overflow_after_frame(ppu: &mut Ppu, vram: &mut TestVideo) -> bool {
+        run_frame(ppu, vram);
+        run_to(ppu, vram, 260, 0);
+        ppu.registers().status & STATUS_SPRITE_OVERFLOW != 0
+    }
+
+    #[test]
+    fn sprite_overflow_test() {
+        let mut ppu = Ppu::new();
+        let mut vram = make_sprite_vram();
+        ppu.registers_mut().mask = 0x1C;
+        hide_sprites(&mut ppu);
+        for index in 0..8 {
+            set_sprite(&mut ppu, index, [20, 0x01, 0x01, index as u8 * 8]);
+        }
+        run_frame(&mut ppu, &mut vram);
+        assert!(!overflow_after_frame(&mut ppu, &mut vram));
+
+        // 第 9 个精灵在求值到它的那个点置位
+        set_sprite(&mut ppu, 8, [20, 0x01, 0x01, 64]);
+        run_frame(&mut ppu, &mut vram);
+        run_to(&mut ppu, &mut vram, 20, 100);
+        assert_eq!(ppu.registers().status & STATUS_SPRITE_OVERFLOW, 0);
+        run_to(&mut ppu, &mut vram, 20, 256);
+        assert_ne!(ppu.registers().status & STATUS_SPRITE_OVERFLOW, 0);
+        // 同一行只显示前 8 个精灵
+        run_to(&mut ppu, &mut vram, 241, 2);
+        let frame = ppu.frame();
+        assert_eq!(frame[21 * 256..21 * 256 + 64], [0x15; 64]);
+        assert_eq!(frame[21 * 256 + 64..21 * 256 + 72], [0x0F; 8]);
+
+        // 找到 8 个精灵后按错位的字节比较：第 10 个精灵的图块号被当作 Y
+        set_sprite(&mut ppu, 8, [200, 0x01, 0x01, 64]);
+        set_sprite(&mut ppu, 9, [200, 20, 0x01, 72]);
+        assert!(overflow_after_frame(&mut ppu, &mut vram));
+        // 第 10 个精灵在范围内却被漏掉
+        set_sprite(&mut ppu, 9, [20, 0x01, 0x01, 72]);
+        assert!(!overflow_after_frame(&mut ppu, &mut vram));
+    }
+
+    #[test]
+    #[ignore = "需要把 blargg 的 sprite_hit_tests 和 sprite_overflow_tests ROM 放到 test_data"]
+    fn blargg_sprite_test() {
+        let sprite_hit = [
+            "01.basics",
+            "02.alignment",
+            "03.corners",
+            "04.flip",
+            "05.left_clip",
+            "06.right_edge",
+            "07.screen_bottom",
+            "08.double_height",
+            "09.timing_basics",
+            "10.timing_order",
+            "11.edge_timing",
+        ]
+        .map(|name| format!("test_data/sprite_hit_tests/{name}.nes"));
+        let sprite_overflow = [
+            "1.Basics",
+            "2.Details",
+            "3.Timing",
+            "4.Obscure",
+            "5.Emulator",
+        ]
+        .map(|name| format!("test_data/sprite_overflow_tests/{name}.nes"));
+        for path in sprite_hit.iter().chain(&sprite_overflow) {
+            assert_eq!(run_blargg_2005(path, 600), 1, "{path}");
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use super::register::*;
+use super::Ppu;
+use crate::memory::{Memory, Result};
+
+/// 每条扫描线最多显示的精灵数
+const MAX_SPRITES: usize = 8;
+/// 精灵属性：调色板
+const ATTRIBUTE_PALETTE: u8 = 0x03;
+/// 精灵属性：显示在背景之后
+const ATTRIBUTE_BEHIND: u8 = 1 << 5;
+/// 精灵属性：水平翻转
+const ATTRIBUTE_FLIP_HORIZONTAL: u8 = 1 << 6;
+/// 精灵属性：垂直翻转
+const ATTRIBUTE_FLIP_VERTICAL: u8 = 1 << 7;
+
+/// 精灵求值的状态机和下一条扫描线的 8 个精灵输出单元
+#[derive(Debug, Default, Clone)]
+pub(super) struct Sprites {
+    /// 正在检查的精灵号
+    n: u8,
+    /// 正在复制的字节号
+    m: u8,
+    /// 奇数点从 OAM 读出的值
+    latch: u8,
+    /// 已复制到次级 OAM 的精灵数
+    found: u8,
+    /// 64 个精灵都已检查
+    done: bool,
+    /// 0 号精灵被复制到了次级 OAM
+    zero_found: bool,
+    /// 当前扫描线上的精灵数
+    count: usize,
+    /// 当前扫描线的第一个精灵是 0 号精灵
+    zero_visible: bool,
+    units: [SpriteUnit; MAX_SPRITES],
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct SpriteUnit {
+    x: u8,
+    attribute: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+}
+
+/// 精灵输出的一个像素
+#[derive(Debug, Clone, Copy)]
+pub(super) struct SpritePixel {
+    pub(super) palette: u8,
+    pub(super) pixel: u8,
+    pub(super) behind: bool,
+    pub(super) zero: bool,
+}
+
+impl Sprites {
+    fn next(&mut self) {
+        self.n += 1;
+        if self.n == 64 {
+            self.n = 0;
+            self.done = true;
+        }
+    }
+}
+
+impl Ppu {
+    /// 精灵流水线：1-64 清空次级 OAM，65-256 求值，257-320 取下一条扫描线的图案
+    pub(super) fn clock_sprites(&mut self, vram: &mut impl Memory) -> Result<()> {
+        let visible = self.scanline < Self::VISIBLE_SCANLINES;
+        match self.dot {
+            1..=64 if visible && self.dot.is_multiple_of(2) => {
+                self.secondary_oam[(self.dot / 2 - 1) as usize] = 0xFF;
+            }
+            65..=256 if visible => self.evaluate_sprite(),
+            257..=320 => {
+                self.registers.oam_addr = 0;
+                self.fetch_sprite(vram, visible)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn sprite_height(&self) -> u16 {
+        self.register().sprite_height()
+    }
+
+    fn evaluate_sprite(&mut self) {
+        if self.dot == 65 {
+            self.sprites.n = 0;
+            self.sprites.m = 0;
+            self.sprites.found = 0;
+            self.sprites.done = false;
+            self.sprites.zero_found = false;
+        }
+        let height = self.sprite_height();
+        let sprites = &mut self.sprites;
+        // 奇数点读取 OAM，偶数点写入次级 OAM
+        if !self.dot.is_multiple_of(2) {
+            sprites.latch = self.oam[sprites.n as usize * 4 + sprites.m as usize];
+            return;
+        }
+        if sprites.done {
+            return;
+        }
+        let in_range = self.scanline.wrapping_sub(sprites.latch as u16) < height;
+        if (sprites.found as usize) < MAX_SPRITES {
+            self.secondary_oam[sprites.found as usize * 4 + sprites.m as usize] = sprites.latch;
+            if sprites.m == 0 && !in_range {
+                sprites.next();
+                return;
+            }
+            if sprites.m == 0 && sprites.n == 0 {
+                sprites.zero_found = true;
+            }
+            sprites.m += 1;
+            if sprites.m == 4 {
+                sprites.m = 0;
+                sprites.found += 1;
+                sprites.next();
+            }
+        } else if in_range {
+            self.registers.status |= STATUS_SPRITE_OVERFLOW;
+            sprites.done = true;
+        } else {
+            // 硬件缺陷：检查下一个精灵时字节号也跟着增加
+            sprites.m = (sprites.m + 1) & 0x03;
+            sprites.next();
+        }
+    }
+
+    /// 每个精灵占 8 个点，没有精灵的单元也会读取 $FF 号图块
+    fn fetch_sprite(&mut self, vram: &mut impl Memory, visible: bool) -> Result<()> {
+        if self.dot == 257 {
+            // 预渲染扫描线不求值，第 0 条扫描线上没有精灵
+            self.sprites.count = if visible {
+                self.sprites.found as usize
+            } else {
+                0
+            };
+            self.sprites.zero_visible = visible && self.sprites.zero_found;
+        }
+        let slot = ((self.dot - 257) / 8) as usize;
+        let [_, _, attribute, x] = self.secondary_sprite(slot);
+        match (self.dot - 257) % 8 {
+            3 => {
+                let unit = &mut self.sprites.units[slot];
+                unit.attribute = attribute;
+                unit.x = x;
+            }
+            plane @ (4 | 6) => {
+                let data = vram.read(self.sprite_pattern_address(slot) + (plane - 4) * 4)?;
+                let data = match slot < self.sprites.count {
+                    false => 0,
+                    true if attribute & ATTRIBUTE_FLIP_HORIZONTAL != 0 => data.reverse_bits(),
+                    true => data,
+                };
+                let unit = &mut self.sprites.units[slot];
+                match plane {
+                    4 => unit.pattern_low = data,
+                    _ => unit.pattern_high = data,
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn secondary_sprite(&self, slot: usize) -> [u8; 4] {
+        let mut sprite = [0; 4];
+        sprite.copy_from_slice(&self.secondary_oam[slot * 4..slot * 4 + 4]);
+        sprite
+    }
+
+    /// 精灵在当前扫描线所在行的低位平面地址
+    fn sprite_pattern_address(&self, slot: usize) -> u16 {
+        let [y, tile, attribute, _] = self.secondary_sprite(slot);
+        let height = self.sprite_height();
+        let mut row = self.scanline.wrapping_sub(y as u16) & (height - 1);
+        if attribute & ATTRIBUTE_FLIP_VERTICAL != 0 {
+            row = height - 1 - row;
+        }
+        let (table, tile) = match height {
+            // 8x16 的精灵由图块号的最低位选择图案表
+            16 => ((tile as u16 & 0x01) << 12, (tile & 0xFE) as u16 | row >> 3),
+            _ => (self.register().sprite_pattern_address(), tile as u16),
+        };
+        table | tile << 4 | (row & 0x07)
+    }
+
+    /// 第 `x` 个像素上优先级最高的不透明精灵像素
+    pub(super) fn sprite_pixel(&self, x: usize) -> Option<SpritePixel> {
+        let units = &self.sprites.units[..self.sprites.count];
+        units.iter().enumerate().find_map(|(slot, unit)| {
+            let offset = x
+                .checked_sub(unit.x as usize)
+                .filter(|offset| *offset < 8)?;
+            let bit = 0x80 >> offset;
+            let pixel =
+                ((unit.pattern_high & bit != 0) as u8) << 1 | (unit.pattern_low & bit != 0) as u8;
+            (pixel != 0).then_some(SpritePixel {
+                palette: unit.attribute & ATTRIBUTE_PALETTE,
+                pixel,
+                behind: unit.attribute & ATTRIBUTE_BEHIND != 0,
+                zero: slot == 0 && self.sprites.zero_visible,
+            })
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use crate::cpu::Bus;
+use crate::ppu::Ppu;
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
 
 /// 进位标志
@@ -111,28 +111,18 @@ impl CpuRegisters {
 }
 
 pub struct PpuRegister<'a> {
-    pub(super) cpu_bus: &'a Bus,
+    pub(crate) ppu: &'a Ppu,
 }
 
 impl PpuRegister<'_> {
-    const PPU_CTRL: u16 = 0x2000;
-    const PPU_MASK: u16 = 0x2001;
-    const PPU_STATUS: u16 = 0x2002;
-
     pub fn ppu_ctrl(&self) -> u8 {
-        self.cpu_bus
-            .cpu_read(Self::PPU_CTRL)
-            .expect("Unable to read PPU CTRL(0x2000) register in memory.")
+        self.ppu.registers().ctrl
     }
     pub fn ppu_mask(&self) -> u8 {
-        self.cpu_bus
-            .cpu_read(Self::PPU_MASK)
-            .expect("Unable to read PPU MASK(0x2001) register in memory.")
+        self.ppu.registers().mask
     }
     pub fn ppu_status(&self) -> u8 {
-        self.cpu_bus
-            .cpu_read(Self::PPU_STATUS)
-            .expect("Unable to read PPU STATUS(0x2002) register in memory.")
+        self.ppu.registers().status
     }
     pub fn name_table(&self) -> u8 {
         self.ppu_ctrl() & 0b00000011
@@ -146,6 +136,14 @@ impl PpuRegister<'_> {
         }
     }
 
+    /// 精灵的高度，8x8 或 8x16
+    pub fn sprite_height(&self) -> u16 {
+        match self.ppu_ctrl() & 0b00100000 {
+            0b0 => 8,
+            _ => 16,
+        }
+    }
+
     pub fn bg_pattern_address(&self) -> u16 {
         match self.ppu_ctrl() & 0b00010000 {
             0b0 => 0,
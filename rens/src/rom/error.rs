@@ -3,5 +3,6 @@ use thiserror::Error;
 pub enum NesError {
     #[error("无效的INES文件: {0}")]
     InvalidInes(String),
+    #[error("不支持的Mapper: {0}")]
+    UnsupportedMapper(u8),
 }
-
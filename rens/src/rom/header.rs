@@ -1,12 +1,13 @@
 use std::convert::TryFrom;
 
+use super::{Board, NesError};
 use crate::ppu::Mirroring;
-use super::NesError;
 
 type Result<T> = std::result::Result<T, NesError>;
 /// NES ROM HEAD
 /// size: 16 bytes
 #[derive(Debug, Clone)]
+#[allow(dead_code)]
 pub struct Header {
     /// 常量 $4E $45 $53 $1A ("NES" followed by MS-DOS end-of-file)
     nes: [u8; 4],
@@ -40,7 +41,7 @@ pub struct Header {
     ///  - 0: NTSC
     ///  - 1: PAL
     flags9: u8,
-    /// 默认为0，可能有些程序会用到
+    /// NES 2.0 中低 4 位是 PRG RAM、高 4 位是 PRG NVRAM 的大小，为 `64 << n` 字节
     flags10: u8,
     /// 默认为0，可能有些程序会用到
     unused: [u8; 5],
@@ -120,6 +121,35 @@ impl Header {
     pub fn nes_2_format(&self) -> bool {
         ((self.flags7 >> 2) & 0b11) == 0b10
     }
+    /// NES 2.0 的子 Mapper 号，iNES 格式总是 0
+    pub fn submapper_number(&self) -> u8 {
+        if self.nes_2_format() {
+            self.flags8 >> 4
+        } else {
+            0
+        }
+    }
+    /// PRG RAM 与 PRG NVRAM 的总字节数，iNES 格式的 0 表示 8K
+    pub fn prg_ram_bytes(&self) -> usize {
+        const SIZE_PRG_RAM_UNIT: usize = 8 * 1024;
+        if self.nes_2_format() {
+            [self.flags10 & 0x0F, self.flags10 >> 4]
+                .into_iter()
+                .filter(|&shift| shift != 0)
+                .map(|shift| 64 << shift)
+                .sum()
+        } else {
+            self.flags8.max(1) as usize * SIZE_PRG_RAM_UNIT
+        }
+    }
+    /// 创建卡带需要的电路板信息
+    pub fn board(&self) -> Board {
+        Board {
+            submapper: self.submapper_number(),
+            prg_ram_size: self.prg_ram_bytes(),
+            nes_2: self.nes_2_format(),
+        }
+    }
     pub fn prg_ram_size(&self) -> Result<u8> {
         if self.nes_2_format() {
             Err(NesError::InvalidInes(String::from("此操作不支持NES 2.0")))
@@ -173,4 +203,21 @@ mod tests {
         assert!(!h2.nes_2_format());
         Ok(())
     }
+
+    #[test]
+    fn prg_ram_bytes_test() -> Result<()> {
+        let mut header = [0u8; 16];
+        header[0..4].copy_from_slice(&Header::NES_ASCII);
+        // iNES 的 0 表示 8K
+        assert_eq!(Header::from_slice(&header)?.prg_ram_bytes(), 0x2000);
+        header[8] = 4;
+        assert_eq!(Header::from_slice(&header)?.prg_ram_bytes(), 0x8000);
+        // NES 2.0：8K PRG RAM 和 8K PRG NVRAM
+        header[7] = 0b0000_1000;
+        header[10] = 0x77;
+        assert_eq!(Header::from_slice(&header)?.prg_ram_bytes(), 0x4000);
+        header[10] = 0x00;
+        assert_eq!(Header::from_slice(&header)?.prg_ram_bytes(), 0);
+        Ok(())
+    }
 }
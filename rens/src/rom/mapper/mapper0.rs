@@ -1,19 +1,25 @@
-use crate::memory::{Memory, MemoryError, Result};
+use crate::memory::{MemoryError, Result};
 
-use super::Mapper;
+use super::{Chr, Mapper};
+use crate::ppu::Mirroring;
 
 #[derive(Debug)]
 pub struct Mapper000 {
     prg_ram: Box<[u8; Self::MAPPER_SIZE_PRG_RAM as usize]>,
     prg_rom: Vec<u8>,
-    chr_rom: Vec<u8>,
+    chr: Chr,
     /// NROM-128 最后16KB镜像
     nrom_128: bool,
+    mirroring: Mirroring,
+    /// 四屏模式下卡带提供的后两个名称表
+    vram: Vec<u8>,
 }
 
 impl Mapper000 {
     const ADDRESS_CHR_BANK_START: u16 = 0x0000;
     const ADDRESS_CHR_BANK_END: u16 = 0x2000 - 1;
+    const ADDRESS_VRAM_START: u16 = 0x2000;
+    const ADDRESS_VRAM_END: u16 = 0x3EFF;
     const ADDRESS_PRG_RAM_BANK_START: u16 = 0x6000;
     const ADDRESS_PRG_RAM_BANK_END: u16 = 0x8000 - 1;
     const ADDRESS_PRG_BANK_FIRST_START: u16 = 0x8000;
@@ -23,15 +29,23 @@ impl Mapper000 {
 
     const MAPPER_SIZE_PRG_RAM: u16 = 8 * 1024;
     const MAPPER_SIZE_NROM_128: u16 = 16 * 1024;
+    const MAPPER_SIZE_VRAM: usize = 2 * 1024;
+    const MAPPER_SIZE_CHR: usize = 8 * 1024;
 
-    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
         let nrom_128 = prg_rom.len() == Self::MAPPER_SIZE_NROM_128 as usize; // 16 KiB for NROM-128, 32 KiB for NROM-256 (DIP-28 standard pin out)
         let prg_ram = Box::new([0; Self::MAPPER_SIZE_PRG_RAM as usize]); // 固定 8K PRG RAM
+        let vram = match mirroring {
+            Mirroring::FourScreen => vec![0; Self::MAPPER_SIZE_VRAM],
+            _ => Vec::new(),
+        };
         Self {
             prg_ram,
             prg_rom,
-            chr_rom,
+            chr: Chr::new(chr_rom),
             nrom_128,
+            mirroring,
+            vram,
         }
     }
 }
@@ -40,16 +54,9 @@ impl Mapper for Mapper000 {
     fn number(&self) -> u8 {
         0
     }
-}
 
-impl Memory for Mapper000 {
-    fn read(&self, address: u16) -> Result<u8> {
+    fn cpu_read(&self, address: u16) -> Result<u8> {
         match address {
-            Self::ADDRESS_CHR_BANK_START..=Self::ADDRESS_CHR_BANK_END => self
-                .chr_rom
-                .get(address as usize)
-                .copied()
-                .ok_or(MemoryError::ReadMemory(address)),
             Self::ADDRESS_PRG_RAM_BANK_START..=Self::ADDRESS_PRG_RAM_BANK_END => self
                 .prg_ram
                 .get((address - Self::ADDRESS_PRG_RAM_BANK_START) as usize)
@@ -73,33 +80,79 @@ impl Memory for Mapper000 {
                         .ok_or(MemoryError::ReadMemory(address))
                 }
             }
-            _ => Err(MemoryError::AddressOutOfRange(address)),
+            _ => Err(MemoryError::Unmapped(address)),
         }
     }
 
-    fn write(&mut self, address: u16, data: u8) -> Result<()> {
+    fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
         match address {
-            Self::ADDRESS_CHR_BANK_START..=Self::ADDRESS_CHR_BANK_END => {
-                self.chr_rom[address as usize] = data;
-                Ok(())
-            }
             Self::ADDRESS_PRG_RAM_BANK_START..=Self::ADDRESS_PRG_RAM_BANK_END => {
                 self.prg_ram[(address - Self::ADDRESS_PRG_RAM_BANK_START) as usize] = data;
                 Ok(())
             }
-            Self::ADDRESS_PRG_BANK_FIRST_START..=Self::ADDRESS_PRG_BANK_FIRST_END => {
-                self.prg_rom[(address - Self::ADDRESS_PRG_BANK_FIRST_START) as usize] = data;
-                Ok(())
+            // PRG ROM 不能写入
+            Self::ADDRESS_PRG_BANK_FIRST_START..=Self::ADDRESS_PRG_BANK_SECOND_END => Ok(()),
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            Self::ADDRESS_CHR_BANK_START..=Self::ADDRESS_CHR_BANK_END => {
+                Ok(self.chr.read(0, Self::MAPPER_SIZE_CHR, address))
             }
-            Self::ADDRESS_PRG_BANK_SECOND_START..=Self::ADDRESS_PRG_BANK_SECOND_END => {
-                if self.nrom_128 {
-                    self.prg_rom[(address - Self::ADDRESS_PRG_BANK_SECOND_START) as usize] = data;
-                } else {
-                    self.prg_rom[(address - Self::ADDRESS_PRG_BANK_FIRST_START) as usize] = data;
-                }
+            Self::ADDRESS_VRAM_START..=Self::ADDRESS_VRAM_END => self
+                .vram
+                .get((address & 0x07FF) as usize)
+                .copied()
+                .ok_or(MemoryError::ReadMemory(address)),
+            _ => Err(MemoryError::AddressOutOfRange(address)),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            Self::ADDRESS_CHR_BANK_START..=Self::ADDRESS_CHR_BANK_END => {
+                self.chr.write(0, Self::MAPPER_SIZE_CHR, address, data);
                 Ok(())
             }
+            Self::ADDRESS_VRAM_START..=Self::ADDRESS_VRAM_END => self
+                .vram
+                .get_mut((address & 0x07FF) as usize)
+                .map(|value| *value = data)
+                .ok_or(MemoryError::WriteMemory(address)),
             _ => Err(MemoryError::AddressOutOfRange(address)),
         }
     }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper000;
+    use crate::ppu::Mirroring;
+    use crate::rom::mapper::{numbered_banks, read_open_bus};
+    use crate::rom::Mapper;
+
+    #[test]
+    fn prg_test() {
+        // NROM-128 的 $C000 是 $8000 的镜像
+        let mut mapper = Mapper000::new(vec![0x11; 0x4000], Vec::new(), Mirroring::Vertical);
+        assert_eq!(mapper.cpu_read(0xC000).unwrap(), 0x11);
+        // 写入 PRG ROM 没有作用
+        mapper.cpu_write(0x8000, 0x22).unwrap();
+        mapper.cpu_write(0xFFFF, 0x22).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 0x11);
+        assert_eq!(mapper.cpu_read(0xFFFF).unwrap(), 0x11);
+        mapper.cpu_write(0x6000, 0x33).unwrap();
+        assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x33);
+
+        let mapper = Mapper000::new(numbered_banks(2, 0x4000), Vec::new(), Mirroring::Vertical);
+        assert_eq!(mapper.cpu_read(0xC000).unwrap(), 1);
+        // $6000 以下没有接线
+        assert_eq!(read_open_bus(mapper, 0x5000), 0x5A);
+    }
 }
@@ -0,0 +1,396 @@
+use crate::memory::{MemoryError, Result};
+
+use super::{bank_offset, Chr, Mapper};
+use crate::ppu::Mirroring;
+
+/// MMC1（SxROM），寄存器通过串行的 5 位移位寄存器写入
+#[derive(Debug)]
+pub struct Mapper001 {
+    prg_rom: Vec<u8>,
+    /// 大小由头部给出，SOROM（16K）和 SXROM（32K）由 CHR bank 寄存器选择 8K
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    /// 移位寄存器，第 5 次写入时最先写入的位在最低位
+    shift: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank: [u8; 2],
+    prg_bank: u8,
+    /// 已经执行的 CPU 周期
+    cycles: u64,
+    /// 上一次写入移位寄存器的周期
+    last_write: Option<u64>,
+}
+
+impl Mapper001 {
+    const ADDRESS_PRG_RAM_START: u16 = 0x6000;
+    const ADDRESS_PRG_RAM_END: u16 = 0x7FFF;
+    const ADDRESS_PRG_ROM_START: u16 = 0x8000;
+    const ADDRESS_CHR_END: u16 = 0x1FFF;
+
+    const SIZE_PRG_BANK: usize = 16 * 1024;
+    const SIZE_CHR_BANK: usize = 4 * 1024;
+    const SIZE_PRG_RAM_BANK: usize = 8 * 1024;
+    /// 超过 256K 的 PRG（SUROM/SXROM）由 CHR bank 寄存器的第 4 位选择高低两半
+    const SIZE_PRG_OUTER_BANK: usize = 256 * 1024;
+
+    /// 上电时固定最后一个 PRG bank
+    const CONTROL_POWER_ON: u8 = 0x0C;
+    const CONTROL_CHR_4K: u8 = 0x10;
+    const PRG_RAM_DISABLE: u8 = 0x10;
+
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, prg_ram_size: usize) -> Self {
+        Self {
+            prg_rom,
+            prg_ram: vec![0; prg_ram_size],
+            chr: Chr::new(chr_rom),
+            shift: 0,
+            shift_count: 0,
+            control: Self::CONTROL_POWER_ON,
+            chr_bank: [0; 2],
+            prg_bank: 0,
+            cycles: 0,
+            last_write: None,
+        }
+    }
+
+    /// 写入 $8000-$FFFF：第 7 位为 1 时复位移位寄存器，否则移入第 0 位，
+    /// 第 5 次写入时按地址的第 13-14 位写入内部寄存器
+    fn write_shift(&mut self, address: u16, data: u8) {
+        // 连续周期的写入（例如读-改-写指令）只有第一次有效
+        let consecutive = self
+            .last_write
+            .is_some_and(|cycle| self.cycles - cycle <= 1);
+        self.last_write = Some(self.cycles);
+        if consecutive {
+            return;
+        }
+        if data & 0x80 != 0 {
+            self.shift = 0;
+            self.shift_count = 0;
+            self.control |= Self::CONTROL_POWER_ON;
+            return;
+        }
+        self.shift |= (data & 0x01) << self.shift_count;
+        self.shift_count += 1;
+        if self.shift_count < 5 {
+            return;
+        }
+        let value = self.shift;
+        self.shift = 0;
+        self.shift_count = 0;
+        match (address >> 13) & 0x03 {
+            0 => self.control = value,
+            1 => self.chr_bank[0] = value,
+            2 => self.chr_bank[1] = value,
+            _ => self.prg_bank = value,
+        }
+    }
+
+    /// $8000-$FFFF 中 16K bank 的编号
+    fn prg_bank(&self, address: u16) -> usize {
+        let outer = match self.prg_rom.len() > Self::SIZE_PRG_OUTER_BANK {
+            true => self.chr_bank[0] as usize & 0x10,
+            false => 0,
+        };
+        let bank = self.prg_bank as usize & 0x0F;
+        let second = address >= 0xC000;
+        let inner = match (self.control >> 2) & 0x03 {
+            // 32K 模式忽略最低位
+            0 | 1 => (bank & !1) | second as usize,
+            2 if second => bank,
+            2 => 0,
+            _ if second => 0x0F,
+            _ => bank,
+        };
+        outer | inner
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        let high = address >= 0x1000;
+        match self.control & Self::CONTROL_CHR_4K {
+            0 => (self.chr_bank[0] as usize & !1) | high as usize,
+            _ => self.chr_bank[high as usize] as usize,
+        }
+    }
+
+    /// 8K PRG RAM 的编号，4K CHR 模式下近似使用第一个寄存器。
+    ///
+    /// 只有使用 CHR RAM 的 SOROM 和 SXROM 这样用，CHR ROM 电路板上这几位只选择 CHR bank
+    fn prg_ram_bank(&self) -> usize {
+        let bank = self.chr_bank[0] as usize >> 2;
+        match self.prg_ram.len() / Self::SIZE_PRG_RAM_BANK {
+            _ if !self.chr.is_ram() => 0,
+            0 | 1 => 0,
+            // SOROM 用第 3 位
+            2 => (bank >> 1) & 0x01,
+            _ => bank & 0x03,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & Self::PRG_RAM_DISABLE == 0 && !self.prg_ram.is_empty()
+    }
+}
+
+impl Mapper for Mapper001 {
+    fn number(&self) -> u8 {
+        1
+    }
+
+    fn cpu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            Self::ADDRESS_PRG_RAM_START..=Self::ADDRESS_PRG_RAM_END if self.prg_ram_enabled() => {
+                let offset = bank_offset(
+                    self.prg_ram.len(),
+                    self.prg_ram_bank(),
+                    Self::SIZE_PRG_RAM_BANK,
+                    address,
+                );
+                Ok(self.prg_ram[offset])
+            }
+            Self::ADDRESS_PRG_ROM_START..=0xFFFF => {
+                let bank = self.prg_bank(address);
+                let offset = bank_offset(self.prg_rom.len(), bank, Self::SIZE_PRG_BANK, address);
+                Ok(self.prg_rom[offset])
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            Self::ADDRESS_PRG_RAM_START..=Self::ADDRESS_PRG_RAM_END => {
+                if self.prg_ram_enabled() {
+                    let offset = bank_offset(
+                        self.prg_ram.len(),
+                        self.prg_ram_bank(),
+                        Self::SIZE_PRG_RAM_BANK,
+                        address,
+                    );
+                    self.prg_ram[offset] = data;
+                }
+                Ok(())
+            }
+            Self::ADDRESS_PRG_ROM_START..=0xFFFF => {
+                self.write_shift(address, data);
+                Ok(())
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                Ok(self
+                    .chr
+                    .read(self.chr_bank(address), Self::SIZE_CHR_BANK, address))
+            }
+            _ => Err(MemoryError::ReadMemory(address)),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                let bank = self.chr_bank(address);
+                self.chr.write(bank, Self::SIZE_CHR_BANK, address, data);
+                Ok(())
+            }
+            _ => Err(MemoryError::WriteMemory(address)),
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenA,
+            1 => Mirroring::SingleScreenB,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn clock_cpu(&mut self) {
+        self.cycles += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper001;
+    use crate::clock::Clock;
+    use crate::cpu::{assemble, CpuMode};
+    use crate::nes::Nes;
+    use crate::ppu::Mirroring;
+    use crate::rom::mapper::{numbered_banks, read_open_bus};
+    use crate::rom::Mapper;
+
+    /// 串行写入 5 位，每次写入之间间隔一个周期
+    fn write_register(mapper: &mut Mapper001, address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.cpu_write(address, (value >> bit) & 0x01).unwrap();
+            mapper.clock_cpu();
+            mapper.clock_cpu();
+        }
+    }
+
+    fn prg_banks(mapper: &Mapper001) -> [u8; 2] {
+        [0x8000, 0xC000].map(|address| mapper.cpu_read(address).unwrap())
+    }
+
+    #[test]
+    fn prg_mode_test() {
+        let mut mapper =
+            Mapper001::new(numbered_banks(8, 0x4000), numbered_banks(2, 0x1000), 0x2000);
+        // 上电时最后一个 bank 固定在 $C000
+        assert_eq!(prg_banks(&mapper), [0, 7]);
+        write_register(&mut mapper, 0xE000, 0x02);
+        assert_eq!(prg_banks(&mapper), [2, 7]);
+        write_register(&mut mapper, 0x8000, 0x08);
+        assert_eq!(prg_banks(&mapper), [0, 2]);
+        // 32K 模式忽略最低位
+        write_register(&mut mapper, 0x8000, 0x00);
+        write_register(&mut mapper, 0xE000, 0x05);
+        assert_eq!(prg_banks(&mapper), [4, 5]);
+    }
+
+    #[test]
+    fn shift_register_test() {
+        let mut mapper =
+            Mapper001::new(numbered_banks(8, 0x4000), numbered_banks(2, 0x1000), 0x2000);
+        write_register(&mut mapper, 0x8000, 0x02);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        assert_eq!(prg_banks(&mapper), [0, 1]);
+
+        // 第 7 位复位移位寄存器，并回到固定最后一个 bank 的模式
+        mapper.cpu_write(0xE000, 0x01).unwrap();
+        mapper.clock_cpu();
+        mapper.clock_cpu();
+        mapper.cpu_write(0x8000, 0x80).unwrap();
+        mapper.clock_cpu();
+        mapper.clock_cpu();
+        assert_eq!(prg_banks(&mapper), [0, 7]);
+        write_register(&mut mapper, 0xE000, 0x03);
+        assert_eq!(prg_banks(&mapper), [3, 7]);
+
+        // 连续周期的第二次写入被忽略
+        for bit in [1, 0, 0, 0, 0] {
+            mapper.cpu_write(0xE000, bit).unwrap();
+            mapper.cpu_write(0xE000, 1 - bit).unwrap();
+            mapper.clock_cpu();
+            mapper.clock_cpu();
+        }
+        assert_eq!(prg_banks(&mapper), [1, 7]);
+    }
+
+    #[test]
+    fn chr_mode_test() {
+        let mut mapper =
+            Mapper001::new(numbered_banks(2, 0x4000), numbered_banks(8, 0x1000), 0x2000);
+        write_register(&mut mapper, 0xA000, 0x05);
+        write_register(&mut mapper, 0xC000, 0x02);
+        // 8K 模式忽略最低位和第二个寄存器
+        let banks =
+            |mapper: &Mapper001| [0x0000, 0x1000].map(|address| mapper.ppu_read(address).unwrap());
+        assert_eq!(banks(&mapper), [4, 5]);
+        write_register(&mut mapper, 0x8000, 0x1C);
+        assert_eq!(banks(&mapper), [5, 2]);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+        // CHR ROM 不能写入
+        mapper.ppu_write(0x0000, 0xFF).unwrap();
+        assert_eq!(mapper.ppu_read(0x0000).unwrap(), 5);
+    }
+
+    #[test]
+    fn prg_ram_test() {
+        let mut mapper = Mapper001::new(numbered_banks(32, 0x4000), Vec::new(), 0x8000);
+        mapper.cpu_write(0x6000, 0x11).unwrap();
+        // SXROM 的第 2-3 位选择 8K PRG RAM
+        write_register(&mut mapper, 0xA000, 0x04);
+        assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x00);
+        mapper.cpu_write(0x6000, 0x22).unwrap();
+        write_register(&mut mapper, 0xA000, 0x00);
+        assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x11);
+        // 第 4 位禁用 PRG RAM，写入被忽略
+        write_register(&mut mapper, 0xE000, 0x10);
+        mapper.cpu_write(0x6000, 0x33).unwrap();
+        write_register(&mut mapper, 0xE000, 0x00);
+        assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x11);
+
+        // 512K 的 SUROM 用第 4 位选择后 256K，固定的 bank 也在同一半
+        assert_eq!(prg_banks(&mapper), [0, 15]);
+        write_register(&mut mapper, 0xA000, 0x10);
+        assert_eq!(prg_banks(&mapper), [16, 31]);
+        // CHR RAM 可以写入
+        mapper.ppu_write(0x1234, 0x44).unwrap();
+        assert_eq!(mapper.ppu_read(0x1234).unwrap(), 0x44);
+
+        // 禁用时读取的是总线上残留的值
+        write_register(&mut mapper, 0xE000, 0x10);
+        assert_eq!(read_open_bus(mapper, 0x6000), 0x5A);
+    }
+
+    #[test]
+    fn chr_rom_prg_ram_test() {
+        // SKROM：CHR ROM 和 8K 带电池的 PRG RAM，切换 CHR bank 不影响 PRG RAM
+        let mut mapper = Mapper001::new(
+            numbered_banks(8, 0x4000),
+            numbered_banks(32, 0x1000),
+            0x2000,
+        );
+        mapper.cpu_write(0x6000, 0x11).unwrap();
+        for bank in [0x04, 0x08, 0x0C] {
+            write_register(&mut mapper, 0xA000, bank);
+            assert_eq!(mapper.ppu_read(0x0000).unwrap(), bank);
+            assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x11);
+        }
+
+        // SOROM：CHR RAM 和 16K PRG RAM，由第 3 位选择 8K
+        let mut mapper = Mapper001::new(numbered_banks(8, 0x4000), Vec::new(), 0x4000);
+        mapper.cpu_write(0x6000, 0x11).unwrap();
+        write_register(&mut mapper, 0xA000, 0x04);
+        assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x11);
+        write_register(&mut mapper, 0xA000, 0x08);
+        assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn read_modify_write_test() {
+        // INC 先写回 $FF 复位移位寄存器，紧接着写入的 $00 被忽略，之后写入 2 选择 PRG bank
+        let assembly = assemble(
+            "
+            .org $C000
+            reset:  lda #$01
+                    sta $E000
+                    inc reset_value
+                    ldx #$00
+            write:  lda bits,x
+                    sta $E000
+                    inx
+                    cpx #$05
+                    bne write
+            done:   jmp done
+            bits:   .byte $00, $01, $00, $00, $00
+            .org $FFF0
+            reset_value: .byte $FF
+            .org $FFFC
+                    .word reset
+            ",
+        )
+        .unwrap();
+        for mode in [CpuMode::Instruction, CpuMode::Cycle] {
+            let mut prg = numbered_banks(4, 0x4000);
+            assembly.write_to(&mut prg, 0x0000).unwrap();
+            let mapper = Mapper001::new(prg, Vec::new(), 0x2000);
+            let mut nes = Nes::new(Box::new(mapper));
+            nes.cpu_mut().set_mode(mode);
+            nes.reset().unwrap();
+            let done = assembly.label("done").unwrap();
+            while !(nes.cpu().at_instruction_boundary() && nes.bus().registers().pc == done) {
+                nes.clock().unwrap();
+            }
+            assert_eq!(nes.bus().cpu_peek(0x8000).unwrap(), 2, "{mode:?}");
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::memory::{MemoryError, Result};
+
+use super::{bank_offset, bus_conflict, Chr, Mapper};
+use crate::ppu::Mirroring;
+
+/// UxROM，$8000 可切换 16K PRG bank，$C000 固定为最后一个
+#[derive(Debug)]
+pub struct Mapper002 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    /// UNROM 等电路板写入时与 ROM 的输出冲突（子 Mapper 2）
+    bus_conflicts: bool,
+    prg_bank: u8,
+}
+
+impl Mapper002 {
+    const ADDRESS_PRG_ROM_START: u16 = 0x8000;
+    const ADDRESS_PRG_FIXED_START: u16 = 0xC000;
+    const ADDRESS_CHR_END: u16 = 0x1FFF;
+    const SIZE_PRG_BANK: usize = 16 * 1024;
+    const SIZE_CHR_BANK: usize = 8 * 1024;
+
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        mirroring: Mirroring,
+        bus_conflicts: bool,
+    ) -> Self {
+        Self {
+            prg_rom,
+            chr: Chr::new(chr_rom),
+            mirroring,
+            bus_conflicts,
+            prg_bank: 0,
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank = match address {
+            Self::ADDRESS_PRG_FIXED_START.. => self.prg_rom.len() / Self::SIZE_PRG_BANK - 1,
+            _ => self.prg_bank as usize,
+        };
+        bank_offset(self.prg_rom.len(), bank, Self::SIZE_PRG_BANK, address)
+    }
+}
+
+impl Mapper for Mapper002 {
+    fn number(&self) -> u8 {
+        2
+    }
+
+    fn cpu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            Self::ADDRESS_PRG_ROM_START.. => Ok(self.prg_rom[self.prg_offset(address)]),
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            Self::ADDRESS_PRG_ROM_START.. => {
+                self.prg_bank = match self.bus_conflicts {
+                    true => bus_conflict(&self.prg_rom, self.prg_offset(address), data),
+                    false => data,
+                };
+                Ok(())
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => Ok(self.chr.read(0, Self::SIZE_CHR_BANK, address)),
+            _ => Err(MemoryError::ReadMemory(address)),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                self.chr.write(0, Self::SIZE_CHR_BANK, address, data);
+                Ok(())
+            }
+            _ => Err(MemoryError::WriteMemory(address)),
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper002;
+    use crate::ppu::Mirroring;
+    use crate::rom::mapper::{numbered_banks, read_open_bus};
+    use crate::rom::Mapper;
+
+    #[test]
+    fn bank_test() {
+        let prg = numbered_banks(8, 0x4000);
+        let mut mapper = Mapper002::new(prg, Vec::new(), Mirroring::Vertical, false);
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 0);
+        assert_eq!(mapper.cpu_read(0xC000).unwrap(), 7);
+        mapper.cpu_write(0x8000, 0x05).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 5);
+        assert_eq!(mapper.cpu_read(0xFFFF).unwrap(), 7);
+        // 超出的 bank 号回绕
+        mapper.cpu_write(0x8000, 0x0A).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 2);
+        // CHR RAM
+        mapper.ppu_write(0x1000, 0x12).unwrap();
+        assert_eq!(mapper.ppu_read(0x1000).unwrap(), 0x12);
+        // 没有 PRG RAM，读取的是总线上残留的值
+        assert_eq!(read_open_bus(mapper, 0x6000), 0x5A);
+    }
+
+    #[test]
+    fn bus_conflict_test() {
+        let prg = numbered_banks(8, 0x4000);
+        let mut mapper = Mapper002::new(prg, Vec::new(), Mirroring::Vertical, true);
+        // 写入的值与 $C000 处的 7 按位与
+        mapper.cpu_write(0xC000, 0x0E).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 6);
+        mapper.cpu_write(0x8000, 0x03).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 2);
+    }
+}
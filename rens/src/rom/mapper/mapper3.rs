@@ -0,0 +1,124 @@
+use crate::memory::{MemoryError, Result};
+
+use super::{bank_offset, bus_conflict, Chr, Mapper};
+use crate::ppu::Mirroring;
+
+/// CNROM，PRG 与 NROM 相同，可切换 8K CHR bank
+#[derive(Debug)]
+pub struct Mapper003 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    /// 写入时与 ROM 的输出冲突（子 Mapper 2）
+    bus_conflicts: bool,
+    chr_bank: u8,
+}
+
+impl Mapper003 {
+    const ADDRESS_PRG_ROM_START: u16 = 0x8000;
+    const ADDRESS_CHR_END: u16 = 0x1FFF;
+    /// 16K 的 PRG 在 $C000 镜像
+    const SIZE_PRG_BANK: usize = 32 * 1024;
+    const SIZE_CHR_BANK: usize = 8 * 1024;
+
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        mirroring: Mirroring,
+        bus_conflicts: bool,
+    ) -> Self {
+        Self {
+            prg_rom,
+            chr: Chr::new(chr_rom),
+            mirroring,
+            bus_conflicts,
+            chr_bank: 0,
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        bank_offset(self.prg_rom.len(), 0, Self::SIZE_PRG_BANK, address)
+    }
+}
+
+impl Mapper for Mapper003 {
+    fn number(&self) -> u8 {
+        3
+    }
+
+    fn cpu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            Self::ADDRESS_PRG_ROM_START.. => Ok(self.prg_rom[self.prg_offset(address)]),
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            Self::ADDRESS_PRG_ROM_START.. => {
+                self.chr_bank = match self.bus_conflicts {
+                    true => bus_conflict(&self.prg_rom, self.prg_offset(address), data),
+                    false => data,
+                };
+                Ok(())
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                Ok(self
+                    .chr
+                    .read(self.chr_bank as usize, Self::SIZE_CHR_BANK, address))
+            }
+            _ => Err(MemoryError::ReadMemory(address)),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                let bank = self.chr_bank as usize;
+                self.chr.write(bank, Self::SIZE_CHR_BANK, address, data);
+                Ok(())
+            }
+            _ => Err(MemoryError::WriteMemory(address)),
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper003;
+    use crate::ppu::Mirroring;
+    use crate::rom::mapper::numbered_banks;
+    use crate::rom::Mapper;
+
+    #[test]
+    fn bank_test() {
+        // 16K PRG 在 $C000 镜像
+        let prg = numbered_banks(2, 0x2000);
+        let chr = numbered_banks(4, 0x2000);
+        let mut mapper = Mapper003::new(prg.clone(), chr.clone(), Mirroring::Horizontal, false);
+        let prg_banks = [0x8000, 0xA000, 0xC000, 0xE000].map(|a| mapper.cpu_read(a).unwrap());
+        assert_eq!(prg_banks, [0, 1, 0, 1]);
+        mapper.cpu_write(0x8000, 0x02).unwrap();
+        assert_eq!(mapper.ppu_read(0x1FFF).unwrap(), 2);
+        mapper.cpu_write(0x8000, 0x07).unwrap();
+        assert_eq!(mapper.ppu_read(0x0000).unwrap(), 3);
+        // CHR ROM 不能写入
+        mapper.ppu_write(0x0000, 0x12).unwrap();
+        assert_eq!(mapper.ppu_read(0x0000).unwrap(), 3);
+
+        // 有总线冲突时写入的值与 ROM 按位与
+        let mut mapper = Mapper003::new(prg, chr, Mirroring::Horizontal, true);
+        mapper.cpu_write(0xA000, 0x03).unwrap();
+        assert_eq!(mapper.ppu_read(0x0000).unwrap(), 1);
+    }
+}
@@ -0,0 +1,414 @@
+use crate::memory::{MemoryError, Result};
+
+use super::{bank_offset, Chr, Mapper};
+use crate::ppu::Mirroring;
+
+/// MMC3 扫描线计数器在计数为 0 时的行为
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Mmc3Revision {
+    /// MMC3B/MMC3C：每次计数后只要为 0 就产生 IRQ，重新装入的值是 0 时也一样
+    Sharp,
+    /// MMC3A：只有从非 0 减到 0，或者 $C001 之后装入 0 时才产生 IRQ（NES 2.0 子 Mapper 4）
+    Nec,
+}
+
+/// MMC3（TxROM），8 个 bank 寄存器，由 PPU A12 的上升沿驱动扫描线计数器
+#[derive(Debug)]
+pub struct Mapper004 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    /// 四屏模式下卡带提供的后两个名称表
+    vram: Vec<u8>,
+    four_screen: bool,
+    revision: Mmc3Revision,
+    /// $8000：第 0-2 位选择下次写入的寄存器，第 6 位是 PRG 模式，第 7 位是 CHR 反转
+    bank_select: u8,
+    /// R0-R5 是 CHR bank，R6-R7 是 PRG bank
+    banks: [u8; 8],
+    mirroring: Mirroring,
+    /// $A001：第 7 位启用 PRG RAM，第 6 位禁止写入
+    prg_ram_protect: u8,
+    irq_latch: u8,
+    irq_counter: u8,
+    /// $C001 写入后，下一次计数时重新装入
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl Mapper004 {
+    const ADDRESS_PRG_RAM_START: u16 = 0x6000;
+    const ADDRESS_PRG_RAM_END: u16 = 0x7FFF;
+    const ADDRESS_PRG_ROM_START: u16 = 0x8000;
+    const ADDRESS_CHR_END: u16 = 0x1FFF;
+    const ADDRESS_VRAM_START: u16 = 0x2000;
+    const ADDRESS_VRAM_END: u16 = 0x3EFF;
+
+    const SIZE_PRG_BANK: usize = 8 * 1024;
+    const SIZE_CHR_BANK: usize = 1024;
+    const SIZE_PRG_RAM: usize = 8 * 1024;
+    const SIZE_VRAM: usize = 2 * 1024;
+
+    const BANK_SELECT_PRG_MODE: u8 = 0x40;
+    const BANK_SELECT_CHR_INVERSION: u8 = 0x80;
+    const PRG_RAM_ENABLE: u8 = 0x80;
+    const PRG_RAM_WRITE_PROTECT: u8 = 0x40;
+
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        mirroring: Mirroring,
+        revision: Mmc3Revision,
+    ) -> Self {
+        let four_screen = mirroring == Mirroring::FourScreen;
+        let vram = match four_screen {
+            true => vec![0; Self::SIZE_VRAM],
+            false => Vec::new(),
+        };
+        Self {
+            prg_rom,
+            prg_ram: vec![0; Self::SIZE_PRG_RAM],
+            chr: Chr::new(chr_rom),
+            vram,
+            four_screen,
+            revision,
+            bank_select: 0,
+            banks: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring,
+            // 上电状态不确定，多数游戏依赖 PRG RAM 可用
+            prg_ram_protect: Self::PRG_RAM_ENABLE,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    /// 写入 $8000-$FFFF，按地址范围和奇偶选择寄存器
+    fn write_register(&mut self, address: u16, data: u8) {
+        match (address & 0xE000, address & 0x01) {
+            (0x8000, 0) => self.bank_select = data,
+            (0x8000, _) => self.banks[(self.bank_select & 0x07) as usize] = data,
+            (0xA000, 0) if !self.four_screen => {
+                self.mirroring = match data & 0x01 {
+                    0 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                }
+            }
+            (0xA000, 0) => {}
+            (0xA000, _) => self.prg_ram_protect = data,
+            (0xC000, 0) => self.irq_latch = data,
+            (0xC000, _) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (_, 0) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            _ => self.irq_enabled = true,
+        }
+    }
+
+    /// $8000-$FFFF 中 8K bank 的编号，负数从最后一个 bank 开始数
+    fn prg_bank(&self, address: u16) -> usize {
+        let last = (self.prg_rom.len() / Self::SIZE_PRG_BANK).saturating_sub(1);
+        let mode = self.bank_select & Self::BANK_SELECT_PRG_MODE != 0;
+        match ((address >> 13) & 0x03, mode) {
+            (0, false) | (2, true) => self.banks[6] as usize,
+            (1, _) => self.banks[7] as usize,
+            // 只有一个 bank 时倒数第二个也是它
+            (0, true) | (2, false) => last.saturating_sub(1),
+            _ => last,
+        }
+    }
+
+    /// $0000-$1FFF 中 1K bank 的编号，反转时交换前后 4K
+    fn chr_bank(&self, address: u16) -> usize {
+        let address = match self.bank_select & Self::BANK_SELECT_CHR_INVERSION {
+            0 => address,
+            _ => address ^ 0x1000,
+        };
+        let slot = (address >> 10) as usize;
+        match slot {
+            // R0 和 R1 是 2K bank，忽略最低位
+            0..=3 => (self.banks[slot >> 1] as usize & !1) | (slot & 0x01),
+            _ => self.banks[slot - 2] as usize,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_ram_protect & Self::PRG_RAM_ENABLE != 0
+    }
+}
+
+impl Mapper for Mapper004 {
+    fn number(&self) -> u8 {
+        4
+    }
+
+    fn cpu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            Self::ADDRESS_PRG_RAM_START..=Self::ADDRESS_PRG_RAM_END if self.prg_ram_enabled() => {
+                Ok(self.prg_ram[(address - Self::ADDRESS_PRG_RAM_START) as usize])
+            }
+            Self::ADDRESS_PRG_ROM_START..=0xFFFF => {
+                let bank = self.prg_bank(address);
+                let offset = bank_offset(self.prg_rom.len(), bank, Self::SIZE_PRG_BANK, address);
+                Ok(self.prg_rom[offset])
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            Self::ADDRESS_PRG_RAM_START..=Self::ADDRESS_PRG_RAM_END => {
+                if self.prg_ram_enabled() && self.prg_ram_protect & Self::PRG_RAM_WRITE_PROTECT == 0
+                {
+                    self.prg_ram[(address - Self::ADDRESS_PRG_RAM_START) as usize] = data;
+                }
+                Ok(())
+            }
+            Self::ADDRESS_PRG_ROM_START..=0xFFFF => {
+                self.write_register(address, data);
+                Ok(())
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                Ok(self
+                    .chr
+                    .read(self.chr_bank(address), Self::SIZE_CHR_BANK, address))
+            }
+            Self::ADDRESS_VRAM_START..=Self::ADDRESS_VRAM_END if self.four_screen => {
+                Ok(self.vram[address as usize & (Self::SIZE_VRAM - 1)])
+            }
+            _ => Err(MemoryError::ReadMemory(address)),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                let bank = self.chr_bank(address);
+                self.chr.write(bank, Self::SIZE_CHR_BANK, address, data);
+                Ok(())
+            }
+            Self::ADDRESS_VRAM_START..=Self::ADDRESS_VRAM_END if self.four_screen => {
+                self.vram[address as usize & (Self::SIZE_VRAM - 1)] = data;
+                Ok(())
+            }
+            _ => Err(MemoryError::WriteMemory(address)),
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// 计数器为 0 或者刚写入 $C001 时重新装入，否则减一
+    fn ppu_a12_rise(&mut self) {
+        let previous = self.irq_counter;
+        let reload = self.irq_reload;
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        let fire = match self.revision {
+            Mmc3Revision::Sharp => self.irq_counter == 0,
+            Mmc3Revision::Nec => self.irq_counter == 0 && (previous != 0 || reload),
+        };
+        if fire && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mapper004, Mmc3Revision};
+    use crate::clock::Clock;
+    use crate::nes::{run_blargg, Nes};
+    use crate::ppu::Mirroring;
+    use crate::rom::mapper::{numbered_banks, read_open_bus};
+    use crate::rom::Mapper;
+
+    /// 8K PRG bank 和 1K CHR bank
+    fn make_mapper(prg_banks: usize, chr_banks: usize, revision: Mmc3Revision) -> Mapper004 {
+        let prg = numbered_banks(prg_banks, 0x2000);
+        let chr = numbered_banks(chr_banks, 0x0400);
+        Mapper004::new(prg, chr, Mirroring::Vertical, revision)
+    }
+
+    fn set_banks(mapper: &mut Mapper004, banks: [u8; 8]) {
+        for (register, bank) in banks.into_iter().enumerate() {
+            mapper.cpu_write(0x8000, register as u8).unwrap();
+            mapper.cpu_write(0x8001, bank).unwrap();
+        }
+    }
+
+    #[test]
+    fn bank_test() {
+        let mut mapper = make_mapper(16, 32, Mmc3Revision::Sharp);
+        set_banks(&mut mapper, [8, 11, 1, 2, 3, 4, 5, 6]);
+        let prg_banks = |mapper: &Mapper004| {
+            [0x8000, 0xA000, 0xC000, 0xE000].map(|a| mapper.cpu_read(a).unwrap())
+        };
+        let chr_banks = |mapper: &Mapper004| {
+            [
+                0x0000, 0x0400, 0x0800, 0x0C00, 0x1000, 0x1400, 0x1800, 0x1C00,
+            ]
+            .map(|a| mapper.ppu_read(a).unwrap())
+        };
+        assert_eq!(prg_banks(&mapper), [5, 6, 14, 15]);
+        // R0 和 R1 忽略最低位
+        assert_eq!(chr_banks(&mapper), [8, 9, 10, 11, 1, 2, 3, 4]);
+
+        // PRG 模式 1 交换 $8000 和 $C000，CHR 反转交换前后 4K
+        mapper.cpu_write(0x8000, 0xC0).unwrap();
+        assert_eq!(prg_banks(&mapper), [14, 6, 5, 15]);
+        assert_eq!(chr_banks(&mapper), [1, 2, 3, 4, 8, 9, 10, 11]);
+
+        mapper.cpu_write(0xA000, 0x01).unwrap();
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        // 四屏模式忽略镜像寄存器，后两个名称表在卡带上
+        let mut mapper = Mapper004::new(
+            vec![0; 0x8000],
+            Vec::new(),
+            Mirroring::FourScreen,
+            Mmc3Revision::Sharp,
+        );
+        mapper.cpu_write(0xA000, 0x01).unwrap();
+        assert_eq!(mapper.mirroring(), Mirroring::FourScreen);
+        mapper.ppu_write(0x2C00, 0x12).unwrap();
+        assert_eq!(mapper.ppu_read(0x3C00).unwrap(), 0x12);
+    }
+
+    #[test]
+    fn single_prg_bank_test() {
+        // 只有一个 8K PRG bank 时所有窗口都是它
+        let mut mapper = make_mapper(1, 8, Mmc3Revision::Sharp);
+        for mode in [0x00, 0x40] {
+            mapper.cpu_write(0x8000, mode).unwrap();
+            for address in [0x8000, 0xA000, 0xC000, 0xE000] {
+                assert_eq!(mapper.cpu_read(address).unwrap(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn prg_ram_test() {
+        let mut mapper = make_mapper(4, 8, Mmc3Revision::Sharp);
+        mapper.cpu_write(0x6000, 0x11).unwrap();
+        assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x11);
+        // 禁止写入
+        mapper.cpu_write(0xA001, 0xC0).unwrap();
+        mapper.cpu_write(0x6000, 0x22).unwrap();
+        assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x11);
+        // 禁用后读取的是总线上残留的值
+        mapper.cpu_write(0xA001, 0x00).unwrap();
+        assert_eq!(read_open_bus(mapper, 0x7FFF), 0x5A);
+    }
+
+    /// 产生 `count` 个上升沿，返回每次之后的 IRQ 输出
+    fn rise(mapper: &mut Mapper004, count: usize) -> Vec<bool> {
+        (0..count)
+            .map(|_| {
+                mapper.ppu_a12_rise();
+                let irq = mapper.irq();
+                // 应答
+                mapper.cpu_write(0xE000, 0).unwrap();
+                mapper.cpu_write(0xE001, 0).unwrap();
+                irq
+            })
+            .collect()
+    }
+
+    #[test]
+    fn irq_test() {
+        for revision in [Mmc3Revision::Sharp, Mmc3Revision::Nec] {
+            let mut mapper = make_mapper(4, 8, revision);
+            mapper.cpu_write(0xC000, 2).unwrap();
+            mapper.cpu_write(0xC001, 0).unwrap();
+            mapper.cpu_write(0xE001, 0).unwrap();
+            // 装入 2，两次之后为 0，然后重新装入
+            assert_eq!(
+                rise(&mut mapper, 6),
+                [false, false, true, false, false, true]
+            );
+            // 禁用时不产生 IRQ，但照常计数
+            mapper.cpu_write(0xE000, 0).unwrap();
+            mapper.ppu_a12_rise();
+            mapper.ppu_a12_rise();
+            assert!(!mapper.irq());
+            mapper.cpu_write(0xE001, 0).unwrap();
+            assert_eq!(rise(&mut mapper, 1), [true]);
+
+            // 锁存值为 0 时，Sharp 每次都产生 IRQ，NEC 只在写入 $C001 之后产生一次
+            mapper.cpu_write(0xC000, 0).unwrap();
+            mapper.cpu_write(0xC001, 0).unwrap();
+            let expected = match revision {
+                Mmc3Revision::Sharp => [true, true, true],
+                Mmc3Revision::Nec => [true, false, false],
+            };
+            assert_eq!(rise(&mut mapper, 3), expected);
+        }
+    }
+
+    #[test]
+    fn scanline_irq_test() {
+        // JMP $E000，复位后 I 标志为 1，IRQ 只保持在线上
+        let mut prg = vec![0xEA; 0x8000];
+        prg[0x6000..0x6003].copy_from_slice(&[0x4C, 0x00, 0xE0]);
+        prg[0x7FFC..0x7FFE].copy_from_slice(&[0x00, 0xE0]);
+        let mapper = Mapper004::new(prg, Vec::new(), Mirroring::Vertical, Mmc3Revision::Sharp);
+        let mut nes = Nes::new(Box::new(mapper));
+        nes.reset().unwrap();
+        // 背景使用 $0000，精灵使用 $1000，每条扫描线在取精灵图案时计数一次
+        nes.bus_mut().cpu_write(0x2000, 0x08).unwrap();
+        nes.bus_mut().cpu_write(0x2001, 0x18).unwrap();
+        nes.run_frame().unwrap();
+        let bus = nes.bus_mut();
+        bus.cpu_write(0xC000, 9).unwrap();
+        bus.cpu_write(0xC001, 0).unwrap();
+        bus.cpu_write(0xE001, 0).unwrap();
+        while !nes.bus().mapper_irq() {
+            nes.clock().unwrap();
+        }
+        // 预渲染扫描线装入 9，第 9 条扫描线减到 0
+        let ppu = nes.bus().ppu();
+        assert_eq!(ppu.scanline(), 9);
+        assert!((257..=320).contains(&ppu.dot()));
+        assert!(nes.cpu().irq_line());
+    }
+
+    #[test]
+    #[ignore = "需要把 blargg 的 mmc3_test ROM 放到 test_data/mmc3_test"]
+    fn blargg_mmc3_test() {
+        // 按头部选择 MMC3 的版本，iNES 头部总是 Sharp；测试 MMC3A 行为的 6-MMC3_alt
+        // 要用子 Mapper 4 的 NES 2.0 头部才能通过
+        for name in [
+            "1-clocking",
+            "2-details",
+            "3-A12_clocking",
+            "4-scanline_timing",
+            "5-MMC3",
+        ] {
+            let (result, text) = run_blargg(&format!("test_data/mmc3_test/{name}.nes"));
+            assert_eq!(result, 0, "{name}: {text}");
+        }
+    }
+}
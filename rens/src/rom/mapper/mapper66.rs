@@ -0,0 +1,108 @@
+use crate::memory::{MemoryError, Result};
+
+use super::{bank_offset, bus_conflict, Chr, Mapper};
+use crate::ppu::Mirroring;
+
+/// GxROM，同一个寄存器选择 32K PRG bank 和 8K CHR bank，写入时总有总线冲突
+#[derive(Debug)]
+pub struct Mapper066 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    register: u8,
+}
+
+impl Mapper066 {
+    const ADDRESS_PRG_ROM_START: u16 = 0x8000;
+    const ADDRESS_CHR_END: u16 = 0x1FFF;
+    const SIZE_PRG_BANK: usize = 32 * 1024;
+    const SIZE_CHR_BANK: usize = 8 * 1024;
+
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        Self {
+            prg_rom,
+            chr: Chr::new(chr_rom),
+            mirroring,
+            register: 0,
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank = (self.register >> 4 & 0x03) as usize;
+        bank_offset(self.prg_rom.len(), bank, Self::SIZE_PRG_BANK, address)
+    }
+
+    fn chr_bank(&self) -> usize {
+        (self.register & 0x03) as usize
+    }
+}
+
+impl Mapper for Mapper066 {
+    fn number(&self) -> u8 {
+        66
+    }
+
+    fn cpu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            Self::ADDRESS_PRG_ROM_START.. => Ok(self.prg_rom[self.prg_offset(address)]),
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            Self::ADDRESS_PRG_ROM_START.. => {
+                self.register = bus_conflict(&self.prg_rom, self.prg_offset(address), data);
+                Ok(())
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                Ok(self.chr.read(self.chr_bank(), Self::SIZE_CHR_BANK, address))
+            }
+            _ => Err(MemoryError::ReadMemory(address)),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                let bank = self.chr_bank();
+                self.chr.write(bank, Self::SIZE_CHR_BANK, address, data);
+                Ok(())
+            }
+            _ => Err(MemoryError::WriteMemory(address)),
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper066;
+    use crate::ppu::Mirroring;
+    use crate::rom::mapper::numbered_banks;
+    use crate::rom::Mapper;
+
+    #[test]
+    fn bank_test() {
+        // PRG 的每个字节都带上第 4-5 位，写入时不会被总线冲突清除
+        let mut prg = numbered_banks(4, 0x8000);
+        prg.iter_mut().for_each(|data| *data |= 0x30);
+        let mut mapper = Mapper066::new(prg, numbered_banks(4, 0x2000), Mirroring::Vertical);
+        mapper.cpu_write(0x8000, 0x21).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 0x32);
+        assert_eq!(mapper.ppu_read(0x0000).unwrap(), 0);
+        // bank 2 的内容 $32 与写入的值按位与
+        mapper.cpu_write(0x8000, 0x13).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 0x31);
+        assert_eq!(mapper.ppu_read(0x0000).unwrap(), 2);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::memory::{MemoryError, Result};
+
+use super::{bank_offset, bus_conflict, Chr, Mapper};
+use crate::ppu::Mirroring;
+
+/// AxROM，可切换 32K PRG bank，用单屏镜像选择 CIRAM 的一半
+#[derive(Debug)]
+pub struct Mapper007 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    /// AOROM 写入时与 ROM 的输出冲突（子 Mapper 2）
+    bus_conflicts: bool,
+    register: u8,
+}
+
+impl Mapper007 {
+    const ADDRESS_PRG_ROM_START: u16 = 0x8000;
+    const ADDRESS_CHR_END: u16 = 0x1FFF;
+    const SIZE_PRG_BANK: usize = 32 * 1024;
+    const SIZE_CHR_BANK: usize = 8 * 1024;
+    const REGISTER_PRG_BANK: u8 = 0x07;
+    const REGISTER_SINGLE_SCREEN_B: u8 = 0x10;
+
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, bus_conflicts: bool) -> Self {
+        Self {
+            prg_rom,
+            chr: Chr::new(chr_rom),
+            bus_conflicts,
+            register: 0,
+        }
+    }
+
+    fn prg_offset(&self, address: u16) -> usize {
+        let bank = (self.register & Self::REGISTER_PRG_BANK) as usize;
+        bank_offset(self.prg_rom.len(), bank, Self::SIZE_PRG_BANK, address)
+    }
+}
+
+impl Mapper for Mapper007 {
+    fn number(&self) -> u8 {
+        7
+    }
+
+    fn cpu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            Self::ADDRESS_PRG_ROM_START.. => Ok(self.prg_rom[self.prg_offset(address)]),
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            Self::ADDRESS_PRG_ROM_START.. => {
+                self.register = match self.bus_conflicts {
+                    true => bus_conflict(&self.prg_rom, self.prg_offset(address), data),
+                    false => data,
+                };
+                Ok(())
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => Ok(self.chr.read(0, Self::SIZE_CHR_BANK, address)),
+            _ => Err(MemoryError::ReadMemory(address)),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                self.chr.write(0, Self::SIZE_CHR_BANK, address, data);
+                Ok(())
+            }
+            _ => Err(MemoryError::WriteMemory(address)),
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.register & Self::REGISTER_SINGLE_SCREEN_B {
+            0 => Mirroring::SingleScreenA,
+            _ => Mirroring::SingleScreenB,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper007;
+    use crate::ppu::Mirroring;
+    use crate::rom::mapper::numbered_banks;
+    use crate::rom::Mapper;
+
+    #[test]
+    fn bank_test() {
+        let mut mapper = Mapper007::new(numbered_banks(8, 0x8000), Vec::new(), false);
+        assert_eq!(mapper.cpu_read(0xC000).unwrap(), 0);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+        mapper.cpu_write(0x8000, 0x15).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 5);
+        assert_eq!(mapper.cpu_read(0xFFFF).unwrap(), 5);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenB);
+
+        // AOROM 写入的值与 ROM 按位与，倒序排列后 bank 0 的内容是 7
+        let prg = numbered_banks(8, 0x8000).into_iter().rev().collect();
+        let mut mapper = Mapper007::new(prg, Vec::new(), true);
+        mapper.cpu_write(0x8000, 0x15).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 2);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+        mapper.cpu_write(0x8000, 0x13).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000).unwrap(), 5);
+    }
+}
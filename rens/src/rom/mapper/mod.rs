@@ -1,15 +1,199 @@
 mod mapper0;
+mod mapper1;
+mod mapper2;
+mod mapper3;
+mod mapper4;
+mod mapper66;
+mod mapper7;
+mod vrc;
 
-use crate::memory::Memory;
+use crate::memory::Result;
+use crate::ppu::Mirroring;
 
 use self::mapper0::Mapper000;
+use self::mapper1::Mapper001;
+use self::mapper2::Mapper002;
+use self::mapper3::Mapper003;
+use self::mapper4::Mapper004;
+use self::mapper66::Mapper066;
+use self::mapper7::Mapper007;
+use self::vrc::Vrc;
 
-pub fn make_mapper(number: u8, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Option<Box<dyn Mapper>> {
+pub use self::mapper4::Mmc3Revision;
+
+pub fn make_mapper(
+    number: u8,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    mirroring: Mirroring,
+) -> Option<Box<dyn Mapper>> {
+    make_mapper_with_board(number, Board::default(), prg_rom, chr_rom, mirroring)
+}
+
+/// 头部中区分同一 Mapper 不同电路板的信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Board {
+    /// NES 2.0 的子 Mapper 号，iNES 格式总是 0
+    pub submapper: u8,
+    /// PRG RAM 与带电池的 PRG NVRAM 的总字节数
+    pub prg_ram_size: usize,
+    /// 头部是 NES 2.0 格式，子 Mapper 0 表示确实没有细分，而不是没有给出
+    pub nes_2: bool,
+}
+
+impl Default for Board {
+    /// 没有头部信息时按最常见的 8K PRG RAM 处理
+    fn default() -> Self {
+        Self {
+            submapper: 0,
+            prg_ram_size: 8 * 1024,
+            nes_2: false,
+        }
+    }
+}
+
+/// 按电路板信息创建卡带，例如 MMC3 的子 Mapper 4 使用 `Mmc3Revision::Nec` 的 IRQ 行为
+pub fn make_mapper_with_board(
+    number: u8,
+    board: Board,
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    mirroring: Mirroring,
+) -> Option<Box<dyn Mapper>> {
+    let submapper = board.submapper;
     match number {
-        0 => Some(Box::new(Mapper000::new(prg_rom, chr_rom))),
+        0 => Some(Box::new(Mapper000::new(prg_rom, chr_rom, mirroring))),
+        1 => Some(Box::new(Mapper001::new(
+            prg_rom,
+            chr_rom,
+            board.prg_ram_size,
+        ))),
+        2 => Some(Box::new(Mapper002::new(
+            prg_rom,
+            chr_rom,
+            mirroring,
+            submapper == 2,
+        ))),
+        3 => Some(Box::new(Mapper003::new(
+            prg_rom,
+            chr_rom,
+            mirroring,
+            submapper == 2,
+        ))),
+        4 => {
+            let revision = match submapper {
+                4 => Mmc3Revision::Nec,
+                _ => Mmc3Revision::Sharp,
+            };
+            Some(Box::new(Mapper004::new(
+                prg_rom, chr_rom, mirroring, revision,
+            )))
+        }
+        7 => Some(Box::new(Mapper007::new(prg_rom, chr_rom, submapper == 2))),
+        21 | 22 | 23 | 25 => {
+            let vrc = Vrc::new(number, board, prg_rom, chr_rom, mirroring)?;
+            Some(Box::new(vrc))
+        }
+        66 => Some(Box::new(Mapper066::new(prg_rom, chr_rom, mirroring))),
         _ => None,
     }
 }
-pub trait Mapper: Memory {
+
+/// 卡带，分别连接在 CPU 总线和 PPU 总线上
+pub trait Mapper: Send {
     fn number(&self) -> u8;
+
+    /// CPU 地址空间中 $4020-$FFFF 的读取，不能有副作用
+    fn cpu_read(&self, address: u16) -> Result<u8>;
+    /// CPU 地址空间中 $4020-$FFFF 的写入，包括 Mapper 的寄存器
+    fn cpu_write(&mut self, address: u16, data: u8) -> Result<()>;
+    /// `cpu_read` 的结果中卡带没有驱动的位，由总线上残留的值补上
+    fn cpu_open_bus_mask(&self, _address: u16) -> u8 {
+        0
+    }
+    /// PPU 地址空间中的图案表 $0000-$1FFF，以及 `map_name_table` 交给卡带的名称表
+    fn ppu_read(&self, address: u16) -> Result<u8>;
+    fn ppu_write(&mut self, address: u16, data: u8) -> Result<()>;
+
+    /// 当前的名称表镜像方式
+    fn mirroring(&self) -> Mirroring;
+    /// 名称表地址 $2000-$3EFF 在主机 2K CIRAM 中的偏移，返回 `None` 时由卡带提供
+    fn map_name_table(&self, address: u16) -> Option<u16> {
+        self.mirroring().map_name_table(address)
+    }
+
+    /// 卡带的 IRQ 输出
+    fn irq(&self) -> bool {
+        false
+    }
+    /// 每个 CPU 周期执行一次
+    fn clock_cpu(&mut self) {}
+    /// PPU 地址线 A12 在保持低电平一段时间后变为高电平，见 `Bus::clock_ppu`
+    fn ppu_a12_rise(&mut self) {}
+    /// PPU 开始一条新的扫描线
+    fn scanline(&mut self) {}
+}
+
+/// 以 `size` 字节为一个 bank 时，第 `bank` 个 bank 中 `address` 所在的位置。
+///
+/// bank 号超出 `len` 时回绕，相当于忽略没有接线的高位
+fn bank_offset(len: usize, bank: usize, size: usize, address: u16) -> usize {
+    (bank * size + (address as usize & (size - 1))) % len.max(1)
+}
+
+/// 有总线冲突的电路板写入 ROM 区域时，ROM 同时输出数据，实际写入的是两者按位与
+fn bus_conflict(prg_rom: &[u8], offset: usize, data: u8) -> u8 {
+    data & prg_rom.get(offset).copied().unwrap_or(0xFF)
+}
+
+/// `count` 个 `size` 字节的 bank，每个 bank 的内容都是自己的编号
+#[cfg(test)]
+fn numbered_banks(count: usize, size: usize) -> Vec<u8> {
+    (0..count).flat_map(|bank| vec![bank as u8; size]).collect()
+}
+
+/// 把卡带接到总线上，先在数据总线上留下 $5A，再读取 `address`，
+/// 卡带没有驱动数据总线时读到的是 $5A
+#[cfg(test)]
+fn read_open_bus(mapper: impl Mapper + 'static, address: u16) -> u8 {
+    let mut bus = crate::bus::Bus::new(Box::new(mapper));
+    bus.cpu_write(0x0000, 0x5A).unwrap();
+    bus.cpu_read(address).unwrap()
+}
+
+/// 卡带上的图案表，没有 CHR ROM 时使用 8K CHR RAM
+#[derive(Debug)]
+struct Chr {
+    data: Vec<u8>,
+    ram: bool,
+}
+
+impl Chr {
+    const SIZE_CHR_RAM: usize = 8 * 1024;
+
+    fn new(chr_rom: Vec<u8>) -> Self {
+        match chr_rom.is_empty() {
+            true => Self {
+                data: vec![0; Self::SIZE_CHR_RAM],
+                ram: true,
+            },
+            false => Self {
+                data: chr_rom,
+                ram: false,
+            },
+        }
+    }
+    fn is_ram(&self) -> bool {
+        self.ram
+    }
+    fn read(&self, bank: usize, size: usize, address: u16) -> u8 {
+        self.data[bank_offset(self.data.len(), bank, size, address)]
+    }
+    /// 只有 CHR RAM 可以写入
+    fn write(&mut self, bank: usize, size: usize, address: u16, data: u8) {
+        if self.ram {
+            let offset = bank_offset(self.data.len(), bank, size, address);
+            self.data[offset] = data;
+        }
+    }
 }
@@ -0,0 +1,603 @@
+use crate::memory::{MemoryError, Result};
+
+use super::{bank_offset, Board, Chr, Mapper};
+use crate::ppu::Mirroring;
+
+/// Konami VRC2 和 VRC4 的芯片
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum VrcChip {
+    /// 没有 IRQ 和 PRG 模式，$6000-$6FFF 是一位的锁存器
+    Vrc2,
+    /// 有 CPU 周期计数的 IRQ、PRG 模式和 8K PRG RAM
+    Vrc4,
+}
+
+/// 不同电路板把不同的 CPU 地址线接到芯片的寄存器选择引脚上
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct VrcVariant {
+    chip: VrcChip,
+    /// 接到寄存器选择低位的地址线
+    low: u16,
+    /// 接到寄存器选择高位的地址线
+    high: u16,
+    /// VRC2a 不使用 CHR bank 的最低位
+    chr_shift: u8,
+    /// $9002/$9003 是 VRC4 的 PRG 模式寄存器
+    prg_mode: bool,
+}
+
+impl VrcVariant {
+    /// 根据 Mapper 号和 NES 2.0 子 Mapper 号选择电路板。
+    ///
+    /// 没有子 Mapper 时同时解码同一 Mapper 下各电路板的地址线，并按 VRC4 处理，
+    /// VRC4 的寄存器是 VRC2 的超集。iNES 头的 Mapper 23/25 也可能是 VRC2，
+    /// 它的镜像写入会落到 $9002/$9003 上，所以这时忽略 PRG 模式寄存器
+    fn new(number: u8, board: Board) -> Option<Self> {
+        let (chip, low, high) = match (number, board.submapper) {
+            // VRC4a, VRC4c
+            (21, 1) => (VrcChip::Vrc4, 0x02, 0x04),
+            (21, 2) => (VrcChip::Vrc4, 0x40, 0x80),
+            (21, _) => (VrcChip::Vrc4, 0x42, 0x84),
+            // VRC2a
+            (22, _) => {
+                return Some(Self {
+                    chip: VrcChip::Vrc2,
+                    low: 0x02,
+                    high: 0x01,
+                    chr_shift: 1,
+                    prg_mode: false,
+                })
+            }
+            // VRC4f, VRC4e, VRC2b
+            (23, 1) => (VrcChip::Vrc4, 0x01, 0x02),
+            (23, 2) => (VrcChip::Vrc4, 0x04, 0x08),
+            (23, 3) => (VrcChip::Vrc2, 0x01, 0x02),
+            (23, _) => (VrcChip::Vrc4, 0x05, 0x0A),
+            // VRC4b, VRC4d, VRC2c
+            (25, 1) => (VrcChip::Vrc4, 0x02, 0x01),
+            (25, 2) => (VrcChip::Vrc4, 0x08, 0x04),
+            (25, 3) => (VrcChip::Vrc2, 0x02, 0x01),
+            (25, _) => (VrcChip::Vrc4, 0x0A, 0x05),
+            _ => return None,
+        };
+        let ambiguous = matches!(number, 23 | 25) && board.submapper == 0 && !board.nes_2;
+        Some(Self {
+            chip,
+            low,
+            high,
+            chr_shift: 0,
+            prg_mode: chip == VrcChip::Vrc4 && !ambiguous,
+        })
+    }
+
+    /// 把 CPU 地址转换成 $x000-$x003 形式的寄存器地址
+    fn register(&self, address: u16) -> u16 {
+        let low = (address & self.low != 0) as u16;
+        let high = (address & self.high != 0) as u16;
+        (address & 0xF000) | high << 1 | low
+    }
+}
+
+/// VRC4 的 IRQ，扫描线模式下用预分频器把 CPU 周期换算成扫描线
+#[derive(Debug, Default)]
+struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    /// 每个 CPU 周期减 3，不大于 0 时加 341 并计数一次
+    prescaler: i16,
+    /// $F002：第 0 位是应答后的启用状态，第 1 位启用，第 2 位为 1 时每个 CPU 周期计数
+    control: u8,
+    pending: bool,
+}
+
+impl VrcIrq {
+    const CONTROL_ENABLE_AFTER_ACK: u8 = 0x01;
+    const CONTROL_ENABLE: u8 = 0x02;
+    const CONTROL_CYCLE_MODE: u8 = 0x04;
+    const PRESCALER_PERIOD: i16 = 341;
+
+    fn write_control(&mut self, data: u8) {
+        self.control = data;
+        self.pending = false;
+        if data & Self::CONTROL_ENABLE != 0 {
+            self.counter = self.latch;
+            self.prescaler = Self::PRESCALER_PERIOD;
+        }
+    }
+
+    fn acknowledge(&mut self) {
+        self.pending = false;
+        let enable = match self.control & Self::CONTROL_ENABLE_AFTER_ACK {
+            0 => 0,
+            _ => Self::CONTROL_ENABLE,
+        };
+        self.control = (self.control & !Self::CONTROL_ENABLE) | enable;
+    }
+
+    fn clock(&mut self) {
+        if self.control & Self::CONTROL_ENABLE == 0 {
+            return;
+        }
+        if self.control & Self::CONTROL_CYCLE_MODE == 0 {
+            self.prescaler -= 3;
+            if self.prescaler > 0 {
+                return;
+            }
+            self.prescaler += Self::PRESCALER_PERIOD;
+        }
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+/// Konami VRC2/VRC4（Mapper 21、22、23、25），8K PRG bank 和 1K CHR bank
+#[derive(Debug)]
+pub struct Vrc {
+    number: u8,
+    variant: VrcVariant,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    prg_bank: [u8; 2],
+    /// VRC4 的 PRG 模式，为 1 时交换 $8000 和 $C000
+    prg_swap: bool,
+    /// 每个 bank 号由高低两个 4 位寄存器组成
+    chr_bank: [u16; 8],
+    mirroring: Mirroring,
+    /// VRC2 在 $6000-$6FFF 的锁存器，没有 PRG RAM 的游戏用来检测盗版
+    latch: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc {
+    const ADDRESS_PRG_RAM_START: u16 = 0x6000;
+    const ADDRESS_PRG_RAM_END: u16 = 0x7FFF;
+    const ADDRESS_LATCH_END: u16 = 0x6FFF;
+    const ADDRESS_PRG_ROM_START: u16 = 0x8000;
+    const ADDRESS_CHR_END: u16 = 0x1FFF;
+
+    const SIZE_PRG_BANK: usize = 8 * 1024;
+    const SIZE_CHR_BANK: usize = 1024;
+    const SIZE_PRG_RAM: usize = 8 * 1024;
+
+    /// 不是 VRC2/VRC4 的 Mapper 号返回 `None`
+    pub fn new(
+        number: u8,
+        board: Board,
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        mirroring: Mirroring,
+    ) -> Option<Self> {
+        let variant = VrcVariant::new(number, board)?;
+        let prg_ram = match variant.chip {
+            VrcChip::Vrc2 => Vec::new(),
+            VrcChip::Vrc4 => vec![0; Self::SIZE_PRG_RAM],
+        };
+        Some(Self {
+            number,
+            variant,
+            prg_rom,
+            prg_ram,
+            chr: Chr::new(chr_rom),
+            prg_bank: [0; 2],
+            prg_swap: false,
+            chr_bank: [0; 8],
+            mirroring,
+            latch: 0,
+            irq: VrcIrq::default(),
+        })
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        let vrc4 = self.variant.chip == VrcChip::Vrc4;
+        match self.variant.register(address) {
+            0x8000..=0x8003 => self.prg_bank[0] = data & 0x1F,
+            0x9002 | 0x9003 if self.variant.prg_mode => self.prg_swap = data & 0x02 != 0,
+            0x9002 | 0x9003 if vrc4 => {}
+            0x9000..=0x9003 => {
+                let mode = match vrc4 {
+                    true => data & 0x03,
+                    false => data & 0x01,
+                };
+                self.mirroring = match mode {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenA,
+                    _ => Mirroring::SingleScreenB,
+                };
+            }
+            0xA000..=0xA003 => self.prg_bank[1] = data & 0x1F,
+            register @ 0xB000..=0xEFFF => {
+                // $B000 是 bank 0 的低 4 位，$B001 是高位，$B002/$B003 是 bank 1，依此类推
+                let index = ((register - 0xB000) >> 11 | (register & 0x02) >> 1) as usize;
+                let bank = &mut self.chr_bank[index];
+                *bank = match register & 0x01 {
+                    0 => (*bank & 0x1F0) | (data as u16 & 0x0F),
+                    _ => (*bank & 0x0F) | (data as u16 & 0x1F) << 4,
+                };
+            }
+            0xF000 if vrc4 => self.irq.latch = (self.irq.latch & 0xF0) | (data & 0x0F),
+            0xF001 if vrc4 => self.irq.latch = (self.irq.latch & 0x0F) | data << 4,
+            0xF002 if vrc4 => self.irq.write_control(data),
+            0xF003 if vrc4 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    /// $8000-$FFFF 中 8K bank 的编号
+    fn prg_bank(&self, address: u16) -> usize {
+        let last = (self.prg_rom.len() / Self::SIZE_PRG_BANK).saturating_sub(1);
+        match ((address >> 13) & 0x03, self.prg_swap) {
+            (0, false) | (2, true) => self.prg_bank[0] as usize,
+            (1, _) => self.prg_bank[1] as usize,
+            // 只有一个 bank 时倒数第二个也是它
+            (0, true) | (2, false) => last.saturating_sub(1),
+            _ => last,
+        }
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        (self.chr_bank[(address >> 10) as usize] >> self.variant.chr_shift) as usize
+    }
+}
+
+impl Mapper for Vrc {
+    fn number(&self) -> u8 {
+        self.number
+    }
+
+    fn cpu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            Self::ADDRESS_PRG_RAM_START..=Self::ADDRESS_PRG_RAM_END if !self.prg_ram.is_empty() => {
+                Ok(self.prg_ram[(address - Self::ADDRESS_PRG_RAM_START) as usize])
+            }
+            Self::ADDRESS_PRG_RAM_START..=Self::ADDRESS_LATCH_END => Ok(self.latch),
+            Self::ADDRESS_PRG_ROM_START..=0xFFFF => {
+                let bank = self.prg_bank(address);
+                let offset = bank_offset(self.prg_rom.len(), bank, Self::SIZE_PRG_BANK, address);
+                Ok(self.prg_rom[offset])
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    /// 锁存器只驱动第 0 位
+    fn cpu_open_bus_mask(&self, address: u16) -> u8 {
+        match address {
+            Self::ADDRESS_PRG_RAM_START..=Self::ADDRESS_LATCH_END if self.prg_ram.is_empty() => {
+                0xFE
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            Self::ADDRESS_PRG_RAM_START..=Self::ADDRESS_PRG_RAM_END if !self.prg_ram.is_empty() => {
+                self.prg_ram[(address - Self::ADDRESS_PRG_RAM_START) as usize] = data;
+                Ok(())
+            }
+            Self::ADDRESS_PRG_RAM_START..=Self::ADDRESS_PRG_RAM_END => {
+                if address <= Self::ADDRESS_LATCH_END {
+                    self.latch = data & 0x01;
+                }
+                Ok(())
+            }
+            Self::ADDRESS_PRG_ROM_START..=0xFFFF => {
+                self.write_register(address, data);
+                Ok(())
+            }
+            _ => Err(MemoryError::Unmapped(address)),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> Result<u8> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                Ok(self
+                    .chr
+                    .read(self.chr_bank(address), Self::SIZE_CHR_BANK, address))
+            }
+            _ => Err(MemoryError::ReadMemory(address)),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+        match address {
+            0..=Self::ADDRESS_CHR_END => {
+                let bank = self.chr_bank(address);
+                self.chr.write(bank, Self::SIZE_CHR_BANK, address, data);
+                Ok(())
+            }
+            _ => Err(MemoryError::WriteMemory(address)),
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn clock_cpu(&mut self) {
+        self.irq.clock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vrc;
+    use crate::memory::Result;
+    use crate::nes::Nes;
+    use crate::ppu::Mirroring;
+    use crate::rom::mapper::{numbered_banks, read_open_bus};
+    use crate::rom::{Board, Mapper, NesLoader};
+    use std::sync::{Arc, Mutex};
+
+    /// NES 2.0 头部的电路板，16 个 8K PRG bank 和 256 个 1K CHR bank
+    fn make_vrc(number: u8, submapper: u8) -> Vrc {
+        let board = Board {
+            submapper,
+            nes_2: true,
+            ..Board::default()
+        };
+        make_board(number, board)
+    }
+
+    fn make_board(number: u8, board: Board) -> Vrc {
+        let prg = numbered_banks(16, 0x2000);
+        let chr = numbered_banks(256, 0x0400);
+        Vrc::new(number, board, prg, chr, Mirroring::Vertical).unwrap()
+    }
+
+    fn prg_banks(vrc: &Vrc) -> [u8; 4] {
+        [0x8000, 0xA000, 0xC000, 0xE000].map(|address| vrc.cpu_read(address).unwrap())
+    }
+
+    #[test]
+    fn address_line_test() {
+        // ($9002, $B001, $B002) 在各电路板上的地址
+        let boards = [
+            (21, 1, [0x9004, 0xB002, 0xB004]),
+            (21, 2, [0x9080, 0xB040, 0xB080]),
+            (22, 0, [0x9001, 0xB002, 0xB001]),
+            (23, 1, [0x9002, 0xB001, 0xB002]),
+            (23, 2, [0x9008, 0xB004, 0xB008]),
+            (25, 1, [0x9001, 0xB002, 0xB001]),
+            (25, 2, [0x9004, 0xB008, 0xB004]),
+            // 没有子 Mapper 时两组地址线都有效
+            (23, 0, [0x9008, 0xB001, 0xB008]),
+            (25, 0, [0x9001, 0xB008, 0xB001]),
+        ];
+        for (number, submapper, [swap, high, bank1]) in boards {
+            let mut vrc = make_vrc(number, submapper);
+            vrc.cpu_write(0x8000, 0x03).unwrap();
+            vrc.cpu_write(0xB000, 0x04).unwrap();
+            vrc.cpu_write(high, 0x01).unwrap();
+            vrc.cpu_write(bank1, 0x06).unwrap();
+            let chr = [0x0000, 0x0400].map(|address| vrc.ppu_read(address).unwrap());
+            if number == 22 {
+                // VRC2a 忽略最低位，也没有 PRG 模式
+                assert_eq!(chr, [0x0A, 0x03]);
+                vrc.cpu_write(swap, 0x02).unwrap();
+                assert_eq!(prg_banks(&vrc), [3, 0, 14, 15]);
+                continue;
+            }
+            assert_eq!(
+                chr,
+                [0x14, 0x06],
+                "mapper {} submapper {}",
+                number,
+                submapper
+            );
+            vrc.cpu_write(swap, 0x02).unwrap();
+            assert_eq!(prg_banks(&vrc), [14, 0, 3, 15]);
+        }
+    }
+
+    #[test]
+    fn mirroring_test() {
+        let mut vrc = make_vrc(25, 1);
+        vrc.cpu_write(0x9000, 0x01).unwrap();
+        assert_eq!(vrc.mirroring(), Mirroring::Horizontal);
+        vrc.cpu_write(0x9000, 0x03).unwrap();
+        assert_eq!(vrc.mirroring(), Mirroring::SingleScreenB);
+        // VRC2 只有一位
+        let mut vrc = make_vrc(25, 3);
+        vrc.cpu_write(0x9000, 0x03).unwrap();
+        assert_eq!(vrc.mirroring(), Mirroring::Horizontal);
+        vrc.cpu_write(0x9002, 0x02).unwrap();
+        assert_eq!(vrc.mirroring(), Mirroring::Vertical);
+        assert_eq!(prg_banks(&vrc), [0, 0, 14, 15]);
+    }
+
+    #[test]
+    fn ines_vrc2_test() {
+        // iNES 头的 Mapper 23 可能是 VRC2b：$9002/$9003 不是 PRG 模式寄存器
+        let mut vrc = make_board(23, Board::default());
+        vrc.cpu_write(0x8000, 0x03).unwrap();
+        vrc.cpu_write(0xA000, 0x05).unwrap();
+        vrc.cpu_write(0x9002, 0x03).unwrap();
+        vrc.cpu_write(0x9003, 0x02).unwrap();
+        assert_eq!(prg_banks(&vrc), [3, 5, 14, 15]);
+        assert_eq!(vrc.mirroring(), Mirroring::Vertical);
+        vrc.cpu_write(0x9000, 0x01).unwrap();
+        assert_eq!(vrc.mirroring(), Mirroring::Horizontal);
+        // VRC2b 用 A0/A1 选择 CHR 寄存器的高低位
+        vrc.cpu_write(0xB000, 0x04).unwrap();
+        vrc.cpu_write(0xB001, 0x01).unwrap();
+        vrc.cpu_write(0xB002, 0x06).unwrap();
+        let chr = [0x0000, 0x0400].map(|address| vrc.ppu_read(address).unwrap());
+        assert_eq!(chr, [0x14, 0x06]);
+
+        // 同样的 ROM 用 NES 2.0 子 Mapper 3 时是确定的 VRC2b
+        let mut vrc = make_vrc(23, 3);
+        vrc.cpu_write(0x9002, 0x02).unwrap();
+        assert_eq!(prg_banks(&vrc), [0, 0, 14, 15]);
+    }
+
+    #[test]
+    fn single_prg_bank_test() {
+        let mut vrc = Vrc::new(
+            23,
+            Board::default(),
+            vec![7; 0x2000],
+            Vec::new(),
+            Mirroring::Vertical,
+        )
+        .unwrap();
+        vrc.cpu_write(0x8000, 0x01).unwrap();
+        assert_eq!(prg_banks(&vrc), [7; 4]);
+    }
+
+    #[test]
+    fn ram_test() {
+        // VRC2 的锁存器只驱动最低位，其余位和 $7000 以上是总线上残留的值
+        let mut vrc = make_vrc(23, 3);
+        vrc.cpu_write(0x6000, 0xFF).unwrap();
+        assert_eq!(vrc.cpu_read(0x6FFF).unwrap(), 0x01);
+        assert_eq!(read_open_bus(vrc, 0x6FFF), 0x5B);
+        let mut vrc = make_vrc(23, 3);
+        vrc.cpu_write(0x6000, 0x00).unwrap();
+        assert_eq!(read_open_bus(vrc, 0x6000), 0x5A);
+        assert_eq!(read_open_bus(make_vrc(23, 3), 0x7000), 0x5A);
+        let mut vrc = make_vrc(23, 2);
+        vrc.cpu_write(0x7FFF, 0xFF).unwrap();
+        assert_eq!(vrc.cpu_read(0x7FFF).unwrap(), 0xFF);
+    }
+
+    /// 执行 `cycles` 个 CPU 周期，返回第一次产生 IRQ 的周期
+    fn first_irq(vrc: &mut Vrc, cycles: usize) -> Option<usize> {
+        (1..=cycles).find(|_| {
+            vrc.clock_cpu();
+            vrc.irq()
+        })
+    }
+
+    #[test]
+    fn irq_test() {
+        let mut vrc = make_vrc(23, 2);
+        // 锁存 $FE，周期模式下两个周期后溢出
+        vrc.cpu_write(0xF000, 0x0E).unwrap();
+        vrc.cpu_write(0xF004, 0x0F).unwrap();
+        vrc.cpu_write(0xF008, 0x07).unwrap();
+        assert_eq!(first_irq(&mut vrc, 10), Some(2));
+        // 应答后按第 0 位继续计数，溢出时重新装入
+        vrc.cpu_write(0xF00C, 0).unwrap();
+        assert!(!vrc.irq());
+        assert_eq!(first_irq(&mut vrc, 10), Some(2));
+        vrc.cpu_write(0xF008, 0x06).unwrap();
+        vrc.cpu_write(0xF00C, 0).unwrap();
+        assert_eq!(first_irq(&mut vrc, 10), None);
+
+        // 扫描线模式下每 341 / 3 个周期计数一次
+        vrc.cpu_write(0xF008, 0x02).unwrap();
+        assert_eq!(first_irq(&mut vrc, 1000), Some(228));
+        // VRC2 没有 IRQ
+        let mut vrc = make_vrc(23, 3);
+        vrc.cpu_write(0xF008, 0x07).unwrap();
+        assert_eq!(first_irq(&mut vrc, 1000), None);
+    }
+
+    /// 记录游戏写入卡带的地址和值
+    struct Recorder {
+        vrc: Vrc,
+        writes: Arc<Mutex<Vec<(u16, u8)>>>,
+    }
+
+    impl Mapper for Recorder {
+        fn number(&self) -> u8 {
+            self.vrc.number()
+        }
+        fn cpu_read(&self, address: u16) -> Result<u8> {
+            self.vrc.cpu_read(address)
+        }
+        fn cpu_open_bus_mask(&self, address: u16) -> u8 {
+            self.vrc.cpu_open_bus_mask(address)
+        }
+        fn cpu_write(&mut self, address: u16, data: u8) -> Result<()> {
+            self.writes.lock().unwrap().push((address, data));
+            self.vrc.cpu_write(address, data)
+        }
+        fn ppu_read(&self, address: u16) -> Result<u8> {
+            self.vrc.ppu_read(address)
+        }
+        fn ppu_write(&mut self, address: u16, data: u8) -> Result<()> {
+            self.vrc.ppu_write(address, data)
+        }
+        fn mirroring(&self) -> Mirroring {
+            self.vrc.mirroring()
+        }
+        fn irq(&self) -> bool {
+            self.vrc.irq()
+        }
+        fn clock_cpu(&mut self) {
+            self.vrc.clock_cpu();
+        }
+    }
+
+    #[test]
+    fn rom_test() {
+        // iNES 头的 Mapper 23，实际是只用 A0/A1 的 VRC2b 游戏
+        let rom = std::fs::read("test_data/1.nes").unwrap();
+        let loader = NesLoader::from_slice(&rom).unwrap();
+        let header = loader.header();
+        assert_eq!(header.mapper_number(), 23);
+        let (prg, chr) = (loader.prg(), loader.chr());
+        let vrc = Vrc::new(
+            23,
+            header.board(),
+            prg.to_vec(),
+            chr.to_vec(),
+            header.mirroring(),
+        )
+        .unwrap();
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            vrc,
+            writes: writes.clone(),
+        };
+        let mut nes = Nes::new(Box::new(recorder));
+        nes.reset().unwrap();
+        for _ in 0..120 {
+            nes.run_frame().unwrap();
+        }
+
+        let writes = writes.lock().unwrap();
+        let last = |register: u16| {
+            writes
+                .iter()
+                .rev()
+                .find(|(address, _)| *address == register)
+                .map(|(_, data)| *data as usize)
+                .unwrap()
+        };
+        // 没有用到 VRC4 的 IRQ
+        assert!(writes.iter().all(|(address, _)| *address < 0xF000));
+        // $8000/$A000 的窗口是最后选择的 PRG bank
+        let bus = nes.bus();
+        for (window, register) in [(0x8000, 0x8000), (0xA000, 0xA000)] {
+            let bank = last(register) & 0x1F;
+            let expected = &prg[bank * 0x2000 % prg.len()..][..0x10];
+            let actual = (window..window + 0x10).map(|address| bus.cpu_peek(address).unwrap());
+            assert!(actual.eq(expected.iter().copied()), "${window:04X}");
+        }
+        // 8 个 1K CHR 窗口由 $B000-$E003 的高低两半组成
+        for slot in 0..8u16 {
+            let register = 0xB000 + (slot >> 1) * 0x1000 + (slot & 0x01) * 2;
+            let bank = (last(register) & 0x0F) | (last(register + 1) & 0x1F) << 4;
+            let expected = &chr[bank * 0x0400 % chr.len()..][..0x10];
+            let actual = (0..0x10).map(|offset| bus.ppu_read(slot * 0x0400 + offset).unwrap());
+            assert!(actual.eq(expected.iter().copied()), "CHR slot {slot}");
+        }
+        // 游戏已经打开渲染，画出了不止一种颜色
+        let frame = bus.ppu().frame();
+        assert!(frame.iter().any(|pixel| *pixel != frame[0]));
+    }
+}
@@ -0,0 +1,228 @@
+use crate::apu::{ChannelOutput, Resampler};
+use crate::clock::Clock;
+use crate::cpu::CpuError;
+use crate::nes::Nes;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum WavError {
+    #[error("Io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Cpu error: {0}")]
+    Cpu(#[from] CpuError),
+}
+
+/// 录制的音轨，除了混音之外每个通道可以单独导出
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Track {
+    Mix,
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl Track {
+    pub const CHANNELS: [Track; 5] = [
+        Track::Pulse1,
+        Track::Pulse2,
+        Track::Triangle,
+        Track::Noise,
+        Track::Dmc,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Track::Mix => "mix",
+            Track::Pulse1 => "pulse1",
+            Track::Pulse2 => "pulse2",
+            Track::Triangle => "triangle",
+            Track::Noise => "noise",
+            Track::Dmc => "dmc",
+        }
+    }
+
+    /// 单独的通道也经过混音器，电平与在混音中的相同
+    fn level(&self, channels: &ChannelOutput) -> f32 {
+        let channel = match self {
+            Track::Mix => return channels.mix(),
+            Track::Pulse1 => ChannelOutput {
+                pulse1: channels.pulse1,
+                ..Default::default()
+            },
+            Track::Pulse2 => ChannelOutput {
+                pulse2: channels.pulse2,
+                ..Default::default()
+            },
+            Track::Triangle => ChannelOutput {
+                triangle: channels.triangle,
+                ..Default::default()
+            },
+            Track::Noise => ChannelOutput {
+                noise: channels.noise,
+                ..Default::default()
+            },
+            Track::Dmc => ChannelOutput {
+                dmc: channels.dmc,
+                ..Default::default()
+            },
+        };
+        channel.mix()
+    }
+}
+
+/// 无界面运行 `frames` 帧，录制混音，`stems` 为真时同时录制各个通道
+pub fn record(
+    nes: &mut Nes,
+    frames: u32,
+    sample_rate: u32,
+    stems: bool,
+) -> Result<Vec<(Track, Vec<f32>)>, CpuError> {
+    let mut tracks = vec![Track::Mix];
+    if stems {
+        tracks.extend(Track::CHANNELS);
+    }
+    let mut resamplers = tracks
+        .iter()
+        .map(|_| Resampler::new(Nes::CLOCK_RATE, sample_rate))
+        .collect::<Vec<_>>();
+    let mut samples = vec![Vec::new(); tracks.len()];
+    let mut buffer = vec![0.0; sample_rate as usize / 10];
+    for _ in 0..frames {
+        let frame_number = nes.bus().ppu().frame_number();
+        while nes.bus().ppu().frame_number() == frame_number {
+            nes.clock()?;
+            let channels = nes.bus().apu().channels();
+            let outputs = tracks.iter().zip(&mut resamplers).zip(&mut samples);
+            for ((track, resampler), samples) in outputs {
+                resampler.clock(track.level(&channels));
+                // 在重采样器丢弃之前取出
+                if resampler.available() >= buffer.len() {
+                    let count = resampler.take(&mut buffer);
+                    samples.extend_from_slice(&buffer[..count]);
+                }
+            }
+        }
+    }
+    for (resampler, samples) in resamplers.iter_mut().zip(&mut samples) {
+        let count = resampler.take(&mut buffer);
+        samples.extend_from_slice(&buffer[..count]);
+    }
+    Ok(tracks.into_iter().zip(samples).collect())
+}
+
+/// 把单声道采样写成 16 位 PCM 的 WAV 文件
+pub fn write_wav<W: Write>(mut writer: W, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let data_size = samples.len() as u32 * block_align as u32;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    // PCM
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&CHANNELS.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// 各音轨的文件名，混音为 `path` 本身，其余在扩展名前加上通道名，例如 `music.pulse1.wav`
+pub fn track_path(path: &Path, track: Track) -> PathBuf {
+    match track {
+        Track::Mix => path.to_path_buf(),
+        _ => {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            path.with_file_name(format!("{}.{}.wav", stem, track.name()))
+        }
+    }
+}
+
+/// 录制 `frames` 帧并写入 `path`，见 `record` 和 `track_path`
+pub fn export_wav(
+    nes: &mut Nes,
+    frames: u32,
+    sample_rate: u32,
+    path: &Path,
+    stems: bool,
+) -> Result<(), WavError> {
+    for (track, samples) in record(nes, frames, sample_rate, stems)? {
+        let file = File::create(track_path(path, track))?;
+        write_wav(BufWriter::new(file), sample_rate, &samples)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{record, track_path, write_wav, Track};
+    use crate::nes::Nes;
+    use crate::ppu::Mirroring;
+    use crate::rom::make_mapper;
+    use std::path::Path;
+
+    #[test]
+    fn write_wav_test() {
+        let mut wav = Vec::new();
+        write_wav(&mut wav, 44100, &[0.0, 1.0, -2.0]).unwrap();
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[4..8], &42u32.to_le_bytes());
+        assert_eq!(&wav[24..28], &44100u32.to_le_bytes());
+        assert_eq!(&wav[40..44], &6u32.to_le_bytes());
+        assert_eq!(&wav[44..], &[0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80]);
+        assert_eq!(
+            track_path(Path::new("out/music.wav"), Track::Dmc),
+            Path::new("out/music.dmc.wav")
+        );
+    }
+
+    #[test]
+    fn record_test() {
+        // LDA #$0F; STA $4015; LDA #$BF; STA $4000; LDA #$40; STA $4002; STA $4003; JMP $C012
+        let program = [
+            0xA9, 0x0F, 0x8D, 0x15, 0x40, 0xA9, 0xBF, 0x8D, 0x00, 0x40, 0xA9, 0x40, 0x8D, 0x02,
+            0x40, 0x8D, 0x03, 0x40, 0x4C, 0x12, 0xC0,
+        ];
+        let mut prg = vec![0xEA; 0x4000];
+        prg[..program.len()].copy_from_slice(&program);
+        prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        let mut nes =
+            Nes::new(make_mapper(0, prg, vec![0; 0x2000], Mirroring::Horizontal).unwrap());
+        nes.reset().unwrap();
+        let tracks = record(&mut nes, 10, 44100, true).unwrap();
+        let names = tracks
+            .iter()
+            .map(|(track, _)| track.name())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["mix", "pulse1", "pulse2", "triangle", "noise", "dmc"]
+        );
+        // 10 帧约 29780 * 10 个周期
+        let lengths = tracks
+            .iter()
+            .map(|(_, samples)| samples.len())
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, [7337; 6]);
+        let loud = |samples: &[f32]| samples.iter().any(|sample| sample.abs() > 0.05);
+        assert!(loud(&tracks[0].1));
+        assert!(loud(&tracks[1].1));
+        // 三角波停止时保持当前电平，上电时有一次跳变
+        assert!([2, 4, 5].iter().all(|index| !loud(&tracks[*index].1)));
+    }
+}